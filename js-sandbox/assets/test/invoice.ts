@@ -0,0 +1,15 @@
+interface Line {
+	qty: number;
+	price: number;
+}
+
+function total(lines: Line[]): number {
+	return lines.reduce((sum: number, l: Line) => sum + l.qty * l.price, 0);
+}
+
+function checked(lines: Line[]): number {
+	if (lines.length === 0) {
+		throw new Error("invoice has no lines");
+	}
+	return total(lines);
+}
@@ -0,0 +1,19 @@
+import { round2 } from "./plugin_util.js";
+
+let invoiced = 0;
+
+export function lineTotal(qty, price) {
+  return round2(qty * price);
+}
+
+export async function invoice(lines) {
+  invoiced += 1;
+  const totals = await Promise.all(lines.map(async (l) => lineTotal(l.qty, l.price)));
+  return round2(totals.reduce((a, b) => a + b, 0));
+}
+
+export default {
+  invoiceCount() {
+    return invoiced;
+  },
+};
@@ -0,0 +1,3 @@
+export function round2(value) {
+  return Math.round(value * 100) / 100;
+}
@@ -38,7 +38,7 @@ async function mainFunc() {
   mainFunc2();
   mainFunc();
   default_func();
-  sqlSelect();
+  sqlSelect("SELECT ? AS answer", [42]);
 
   async function second_func(){
     console.log('Hello, World 4 from JS!');
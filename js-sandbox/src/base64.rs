@@ -0,0 +1,61 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+/// Encodes base64 with the standard alphabet and padding.
+pub(crate) fn encode(bytes: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+	let mut output = String::with_capacity((bytes.len() + 2) / 3 * 4);
+	for chunk in bytes.chunks(3) {
+		let mut buffer = [0u8; 3];
+		buffer[..chunk.len()].copy_from_slice(chunk);
+		let buffer = u32::from_be_bytes([0, buffer[0], buffer[1], buffer[2]]);
+
+		for i in 0..4 {
+			if i <= chunk.len() {
+				let index = (buffer >> (18 - 6 * i)) & 0x3f;
+				output.push(ALPHABET[index as usize] as char);
+			} else {
+				output.push('=');
+			}
+		}
+	}
+
+	output
+}
+
+/// Decodes base64, with or without padding. Accepts the URL-safe alphabet as well, and ignores whitespace.
+pub(crate) fn decode(input: &[u8]) -> Option<Vec<u8>> {
+	fn value(c: u8) -> Option<u32> {
+		match c {
+			b'A'..=b'Z' => Some((c - b'A') as u32),
+			b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+			b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+			b'+' | b'-' => Some(62),
+			b'/' | b'_' => Some(63),
+			_ => None,
+		}
+	}
+
+	let input: Vec<u8> = input
+		.iter()
+		.copied()
+		.filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+		.collect();
+
+	let mut output = Vec::with_capacity(input.len() * 3 / 4);
+	for chunk in input.chunks(4) {
+		if chunk.len() == 1 {
+			return None;
+		}
+
+		let mut buffer = 0u32;
+		for (i, c) in chunk.iter().enumerate() {
+			buffer |= value(*c)? << (18 - 6 * i);
+		}
+
+		let bytes = buffer.to_be_bytes();
+		output.extend_from_slice(&bytes[1..chunk.len()]);
+	}
+
+	Some(output)
+}
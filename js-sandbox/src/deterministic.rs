@@ -0,0 +1,74 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use deno_core::anyhow::anyhow;
+use deno_core::{op, OpState};
+
+use crate::timers::Timers;
+use crate::AnyError;
+
+/// Settings of the deterministic mode, set through [`ScriptBuilder::deterministic()`](crate::ScriptBuilder::deterministic).
+#[derive(Clone, Debug)]
+pub(crate) struct DeterministicConfig {
+	pub seed: u64,
+	pub start_time: SystemTime,
+}
+
+/// Sources of randomness and time for deterministic scripts, stored in their `OpState`.
+pub(crate) struct Deterministic {
+	/// State of the SplitMix64 generator
+	rng: u64,
+
+	/// `Date.now()` at virtual time zero, in milliseconds since the Unix epoch
+	start_time_ms: f64,
+}
+
+impl Deterministic {
+	pub fn new(config: &DeterministicConfig) -> Self {
+		let start_time_ms = match config.start_time.duration_since(UNIX_EPOCH) {
+			Ok(since) => since.as_millis() as f64,
+			Err(before) => -(before.duration().as_millis() as f64),
+		};
+
+		Self {
+			rng: config.seed,
+			start_time_ms,
+		}
+	}
+
+	/// Next pseudo-random number, uniformly distributed in `[0, 1)`
+	pub fn next_f64(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+
+	/// SplitMix64: small, fast and good enough for `Math.random()`, which makes no guarantees either
+	pub fn next_u64(&mut self) -> u64 {
+		self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+		let mut z = self.rng;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		z ^ (z >> 31)
+	}
+}
+
+fn deterministic(state: &mut OpState) -> Result<&mut Deterministic, AnyError> {
+	state.try_borrow_mut::<Deterministic>().ok_or_else(|| {
+		anyhow!("Script was created from a deterministic snapshot; build it with ScriptBuilder::deterministic()")
+	})
+}
+
+#[op]
+fn op_deterministic_random(state: &mut OpState) -> Result<f64, AnyError> {
+	Ok(deterministic(state)?.next_f64())
+}
+
+/// Milliseconds since the Unix epoch on the virtual clock
+#[op]
+fn op_deterministic_date_now(state: &mut OpState) -> Result<f64, AnyError> {
+	let start_time_ms = deterministic(state)?.start_time_ms;
+	let elapsed = state.borrow::<Timers>().now();
+
+	Ok(start_time_ms + elapsed.as_millis() as f64)
+}
@@ -1,4 +1,9 @@
+use deno_core::anyhow::anyhow;
 use deno_core::v8::{FunctionCallbackArguments, HandleScope, ReturnValue};
+use deno_core::{serde_v8, v8, JsRuntime, OpState};
+use serde::Serialize;
+
+use crate::{AnyError, JsValue};
 
 pub struct ExposedObject1 {
 	pub name: String,
@@ -35,6 +40,12 @@ pub trait ExposedFunction {
 	// 	rv: ReturnValue,
 	// );
 	fn name() -> String;
+
+	/// Permission group of the function, see [`Permissions::allow_host_functions()`](crate::Permissions::allow_host_functions).
+	/// Defaults to the function's name.
+	fn group() -> String {
+		Self::name()
+	}
 }
 
 pub trait ExposedFunction2 {
@@ -98,6 +109,13 @@ impl ExposedFunction for DefaultExposedFunction {
 // 	}
 // }
 
+/// `sqlSelect(query, params)`, for runtimes that expose host functions individually with
+/// [`Script::add_exposed_func()`](crate::Script::add_exposed_func). Scripts built with
+/// [`ScriptBuilder::sql()`](crate::ScriptBuilder::sql) have it already; see [`HostSql`](crate::HostSql).
+///
+/// Queries run on the script's [`HostSql`](crate::HostSql), so create the runtime with
+/// [`Script::rd_get_run_time_with_builder()`](crate::Script::rd_get_run_time_with_builder) and a builder configured with
+/// [`ScriptBuilder::sql()`](crate::ScriptBuilder::sql); without one, calls throw.
 pub struct SqlSelectExposedFunction {
 	name: String,
 }
@@ -109,23 +127,60 @@ impl SqlSelectExposedFunction {
 }
 
 impl ExposedFunction for SqlSelectExposedFunction {
-	fn rust_func_for_js(
-		_scope: &mut HandleScope,
-		_args: FunctionCallbackArguments,
-		_rv: ReturnValue,
-	) {
-		println!("In SqlSelectExposedFunction rust_func_for_js");
+	fn rust_func_for_js(scope: &mut HandleScope, args: FunctionCallbackArguments, rv: ReturnValue) {
+		call_sql(scope, args, rv, crate::sql::select);
 	}
 
 	fn name() -> String {
-		return "sqlSelect".to_string();	}
+		"sqlSelect".to_string()
+	}
 
-	// fn run_call_back(
-	// 	&self,
-	// 	scope: &mut HandleScope,
-	// 	args: FunctionCallbackArguments,
-	// 	rv: ReturnValue,
-	// ) {
-	// 	println!("In SqlSelectExposedFunction run_call_back");
-	// }
+	fn group() -> String {
+		"sql".to_string()
+	}
+}
+
+/// `sqlExecute(query, params)`, the counterpart of [`SqlSelectExposedFunction`] for statements modifying data.
+pub struct SqlExecuteExposedFunction;
+
+impl ExposedFunction for SqlExecuteExposedFunction {
+	fn rust_func_for_js(scope: &mut HandleScope, args: FunctionCallbackArguments, rv: ReturnValue) {
+		call_sql(scope, args, rv, crate::sql::execute);
+	}
+
+	fn name() -> String {
+		"sqlExecute".to_string()
+	}
+
+	fn group() -> String {
+		"sql".to_string()
+	}
+}
+
+/// Runs `run` with the query and parameters passed from JS, and returns its result or throws its error.
+fn call_sql<T: Serialize>(
+	scope: &mut HandleScope,
+	args: FunctionCallbackArguments,
+	mut rv: ReturnValue,
+	run: fn(&mut OpState, &str, &[JsValue]) -> Result<T, AnyError>,
+) {
+	let mut result = || -> Result<v8::Local<v8::Value>, AnyError> {
+		let sql: String = serde_v8::from_v8(scope, args.get(0))
+			.map_err(|_| anyhow!("SQL query must be a string"))?;
+		let params: Option<Vec<JsValue>> = serde_v8::from_v8(scope, args.get(1))
+			.map_err(|_| anyhow!("SQL parameters must be passed as one array"))?;
+
+		let state = JsRuntime::op_state_from(scope);
+		let value = run(&mut state.borrow_mut(), &sql, &params.unwrap_or_default())?;
+		Ok(serde_v8::to_v8(scope, value)?)
+	};
+
+	match result() {
+		Ok(value) => rv.set(value),
+		Err(e) => {
+			let message = v8::String::new(scope, &e.to_string()).unwrap();
+			let exception = v8::Exception::error(scope, message);
+			scope.throw_exception(exception);
+		}
+	}
 }
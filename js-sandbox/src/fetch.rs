@@ -0,0 +1,425 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use deno_core::anyhow::anyhow;
+use deno_core::url::Url;
+use deno_core::{op, CancelHandle, Cancelable, JsBuffer, OpDecl, OpState, ToJsBuffer};
+use serde::{Deserialize, Serialize};
+
+use crate::permissions::PermissionState;
+use crate::AnyError;
+
+/// HTTP requests for scripts, through `fetch()`. Enabled with [`ScriptBuilder::fetch()`](crate::ScriptBuilder::fetch).
+///
+/// Requests are sent by a [`FetchTransport`]; with the cargo feature `http`, [`Fetch::http()`] uses a real HTTP client.
+/// Scripts can only reach hosts allowed by [`Permissions::allow_net()`](crate::Permissions::allow_net):
+///
+/// ```rust
+/// use js_sandbox::{Fetch, FetchFuture, FetchRequest, FetchResponse, FetchTransport, Permissions, Script, AnyError};
+///
+/// // Answers every request with the same prices, as a test double for the real service
+/// struct Prices;
+///
+/// impl FetchTransport for Prices {
+/// 	fn send(&self, _request: FetchRequest) -> FetchFuture {
+/// 		Box::pin(async { Ok(FetchResponse::new(200, r#"{ "EUR": 1.08 }"#)) })
+/// 	}
+/// }
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let mut script = Script::builder()
+/// 		.fetch(Fetch::new(Prices))
+/// 		.permissions(Permissions::new().allow_net("prices.internal"))
+/// 		.build_from_string(r#"
+/// 			async function rate(currency) {
+/// 				const response = await fetch("https://prices.internal/latest");
+/// 				return (await response.json())[currency];
+/// 			}"#)?;
+///
+/// 	let rate: f64 = script.call("rate", ("EUR",))?;
+/// 	assert_eq!(rate, 1.08);
+/// 	Ok(())
+/// }
+/// ```
+///
+/// Only `http:` and `https:` URLs and the [`methods()`](Self::methods) given here are accepted. Request and response
+/// bodies are limited in size, and each request in time. Pending requests are cancelled when a call exceeds the script's
+/// timeout. Scripts cannot set the headers `Host`, `Connection`, `Content-Length`, `Transfer-Encoding`, `TE`, `Upgrade`
+/// and `Proxy-*`.
+///
+/// In JS, `fetch(url, { method, headers, body })` returns a `Response` with `status`, `statusText`, `ok`, `url`,
+/// `headers` and the body methods `text()`, `json()`, `arrayBuffer()` and `bytes()`. Bodies can be strings,
+/// `ArrayBuffer`s or typed arrays. Streams, `Request` objects and abort signals are not supported.
+#[derive(Clone)]
+pub struct Fetch {
+	transport: Arc<dyn FetchTransport>,
+	methods: Vec<String>,
+	max_request_size: usize,
+	max_response_size: usize,
+	timeout: Duration,
+}
+
+impl Fetch {
+	/// Sends requests through `transport`, allowing the methods `GET` and `HEAD`.
+	pub fn new(transport: impl FetchTransport + 'static) -> Self {
+		Self {
+			transport: Arc::new(transport),
+			methods: vec!["GET".to_string(), "HEAD".to_string()],
+			max_request_size: 1024 * 1024,
+			max_response_size: 10 * 1024 * 1024,
+			timeout: Duration::from_secs(30),
+		}
+	}
+
+	/// Sends requests over the network with [`HttpTransport`].
+	#[cfg(feature = "http")]
+	pub fn http() -> Self {
+		Self::new(HttpTransport::new())
+	}
+
+	/// Replaces the allowed HTTP methods, e.g. `["GET", "POST"]` for webhooks.
+	pub fn methods<S: AsRef<str>>(mut self, methods: impl IntoIterator<Item = S>) -> Self {
+		self.methods = methods
+			.into_iter()
+			.map(|method| method.as_ref().to_ascii_uppercase())
+			.collect();
+		self
+	}
+
+	/// Limits request bodies to `bytes`; 1 MiB by default.
+	pub fn max_request_size(mut self, bytes: usize) -> Self {
+		self.max_request_size = bytes;
+		self
+	}
+
+	/// Limits response bodies to `bytes`; 10 MiB by default.
+	pub fn max_response_size(mut self, bytes: usize) -> Self {
+		self.max_response_size = bytes;
+		self
+	}
+
+	/// Fails requests taking longer than `timeout`; 30 seconds by default. The script's timeout applies as well.
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = timeout;
+		self
+	}
+}
+
+impl fmt::Debug for Fetch {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Fetch")
+			.field("methods", &self.methods)
+			.field("max_request_size", &self.max_request_size)
+			.field("max_response_size", &self.max_response_size)
+			.field("timeout", &self.timeout)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Future returned by [`FetchTransport::send()`]
+pub type FetchFuture = Pin<Box<dyn Future<Output = Result<FetchResponse, AnyError>>>>;
+
+/// Sends the HTTP requests of [`Fetch`]; implemented by [`HttpTransport`] and by test doubles.
+///
+/// Requests reaching the transport are already checked against the script's permissions and the limits of `Fetch`.
+/// The returned future runs on the script's thread and is dropped if the request times out or is cancelled.
+pub trait FetchTransport: Send + Sync {
+	/// Sends `request` and returns the response, without following redirects.
+	fn send(&self, request: FetchRequest) -> FetchFuture;
+}
+
+/// An HTTP request made by a script
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchRequest {
+	/// Method in upper case, e.g. `GET`
+	pub method: String,
+
+	/// Absolute `http:` or `https:` URL
+	pub url: String,
+
+	/// Header names and values, in the order given by the script
+	pub headers: Vec<(String, String)>,
+
+	pub body: Vec<u8>,
+
+	/// Larger response bodies are rejected; transports should stop reading once they exceed it
+	pub max_response_size: usize,
+}
+
+/// An HTTP response passed back to a script
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchResponse {
+	pub status: u16,
+	pub headers: Vec<(String, String)>,
+	pub body: Vec<u8>,
+}
+
+impl FetchResponse {
+	/// Creates a response without headers.
+	pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+		Self {
+			status,
+			headers: Vec::new(),
+			body: body.into(),
+		}
+	}
+
+	/// Adds a header.
+	pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+		self.headers.push((name.into(), value.into()));
+		self
+	}
+}
+
+/// Transport sending requests over the network with reqwest. Redirects are not followed, as they could lead to hosts
+/// the script may not access.
+#[cfg(feature = "http")]
+#[derive(Clone, Debug)]
+pub struct HttpTransport {
+	client: reqwest::Client,
+}
+
+#[cfg(feature = "http")]
+impl HttpTransport {
+	pub fn new() -> Self {
+		let client = reqwest::Client::builder()
+			.redirect(reqwest::redirect::Policy::none())
+			.build()
+			.expect("HTTP client can be created");
+
+		Self { client }
+	}
+}
+
+#[cfg(feature = "http")]
+impl Default for HttpTransport {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(feature = "http")]
+impl FetchTransport for HttpTransport {
+	fn send(&self, request: FetchRequest) -> FetchFuture {
+		let client = self.client.clone();
+
+		Box::pin(async move {
+			let method = reqwest::Method::from_bytes(request.method.as_bytes())?;
+			let mut builder = client.request(method, &request.url);
+			for (name, value) in &request.headers {
+				builder = builder.header(name, value);
+			}
+
+			let mut response = builder.body(request.body).send().await?;
+			let status = response.status().as_u16();
+			let headers = response
+				.headers()
+				.iter()
+				.map(|(name, value)| {
+					let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+					(name.as_str().to_string(), value)
+				})
+				.collect();
+
+			let mut body = Vec::new();
+			while let Some(chunk) = response.chunk().await? {
+				if body.len() + chunk.len() > request.max_response_size {
+					return Err(anyhow!(
+						"response exceeds the size limit of {} bytes",
+						request.max_response_size
+					));
+				}
+				body.extend_from_slice(&chunk);
+			}
+
+			Ok(FetchResponse {
+				status,
+				headers,
+				body,
+			})
+		})
+	}
+}
+
+/// Fetch settings of a script and the handle cancelling its pending requests, stored in its `OpState`
+pub(crate) struct FetchState {
+	fetch: Fetch,
+	cancel: Rc<CancelHandle>,
+}
+
+impl FetchState {
+	pub fn new(fetch: Fetch) -> Self {
+		Self {
+			fetch,
+			cancel: CancelHandle::new_rc(),
+		}
+	}
+
+	/// Cancels all pending requests, e.g. after a script timed out. Later requests are not affected.
+	pub fn cancel_all(&mut self) {
+		self.cancel.cancel();
+		self.cancel = CancelHandle::new_rc();
+	}
+}
+
+/// Ops for fetch.js, registered with the crate's other ops
+pub(crate) fn ops() -> Vec<OpDecl> {
+	vec![op_fetch::decl(), op_fetch_decode_text::decl()]
+}
+
+#[derive(Deserialize)]
+struct FetchArgs {
+	method: String,
+	url: String,
+	headers: Vec<(String, String)>,
+
+	/// Body given as string; byte bodies are passed separately
+	text: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FetchResult {
+	status: u16,
+	headers: Vec<(String, String)>,
+	url: String,
+	body: ToJsBuffer,
+}
+
+/// Validates a request against the limits and permissions of the script
+fn prepare(
+	state: &mut OpState,
+	args: FetchArgs,
+	bytes: Option<JsBuffer>,
+) -> Result<FetchRequest, AnyError> {
+	let fetch = &state
+		.try_borrow::<FetchState>()
+		.ok_or_else(|| {
+			anyhow!("fetch is not enabled; build the script with ScriptBuilder::fetch()")
+		})?
+		.fetch;
+
+	let method = args.method.to_ascii_uppercase();
+	if !fetch.methods.contains(&method) {
+		return Err(anyhow!("fetch: method {method} is not allowed"));
+	}
+
+	let url =
+		Url::parse(&args.url).map_err(|e| anyhow!("fetch: invalid URL '{}': {e}", args.url))?;
+	if url.scheme() != "http" && url.scheme() != "https" {
+		return Err(anyhow!(
+			"fetch: URL scheme '{}' is not supported",
+			url.scheme()
+		));
+	}
+	let host = url
+		.host_str()
+		.ok_or_else(|| anyhow!("fetch: URL '{url}' has no host"))?;
+
+	// These would let a script talk to other hosts or smuggle requests past the allowlist
+	if let Some((name, _)) = args
+		.headers
+		.iter()
+		.find(|(name, _)| is_forbidden_header(name))
+	{
+		return Err(anyhow!("fetch: header '{name}' cannot be set by scripts"));
+	}
+
+	let body = match (args.text, bytes) {
+		(Some(text), _) => text.into_bytes(),
+		(None, Some(bytes)) => bytes.to_vec(),
+		(None, None) => Vec::new(),
+	};
+	if body.len() > fetch.max_request_size {
+		return Err(anyhow!(
+			"fetch: request body exceeds the size limit of {} bytes",
+			fetch.max_request_size
+		));
+	}
+
+	let request = FetchRequest {
+		method,
+		url: url.to_string(),
+		headers: args.headers,
+		body,
+		max_response_size: fetch.max_response_size,
+	};
+
+	state
+		.borrow_mut::<PermissionState>()
+		.check_net(host, url.port_or_known_default())?;
+	Ok(request)
+}
+
+fn is_forbidden_header(name: &str) -> bool {
+	const FORBIDDEN: [&str; 6] = [
+		"host",
+		"connection",
+		"content-length",
+		"transfer-encoding",
+		"te",
+		"upgrade",
+	];
+
+	let name = name.to_ascii_lowercase();
+	FORBIDDEN.contains(&name.as_str()) || name.starts_with("proxy-")
+}
+
+#[op]
+async fn op_fetch(
+	state: Rc<RefCell<OpState>>,
+	args: FetchArgs,
+	bytes: Option<JsBuffer>,
+) -> Result<FetchResult, AnyError> {
+	let (request, transport, timeout, cancel) = {
+		let mut state = state.borrow_mut();
+		let request = prepare(&mut state, args, bytes)?;
+		let fetch_state = state.borrow::<FetchState>();
+		let fetch = &fetch_state.fetch;
+		(
+			request,
+			fetch.transport.clone(),
+			fetch.timeout,
+			fetch_state.cancel.clone(),
+		)
+	};
+
+	let url = request.url.clone();
+	let max_size = request.max_response_size;
+	let response = tokio::time::timeout(timeout, transport.send(request))
+		.or_cancel(cancel)
+		.await
+		.map_err(|_| anyhow!("fetch: request to '{url}' was cancelled"))?
+		.map_err(|_| {
+			anyhow!(
+				"fetch: request to '{url}' timed out after {} ms",
+				timeout.as_millis()
+			)
+		})?
+		.map_err(|e| anyhow!("fetch: request to '{url}' failed: {e}"))?;
+
+	if response.body.len() > max_size {
+		return Err(anyhow!(
+			"fetch: response from '{url}' exceeds the size limit of {max_size} bytes"
+		));
+	}
+
+	Ok(FetchResult {
+		status: response.status,
+		headers: response.headers,
+		url,
+		body: response.body.into(),
+	})
+}
+
+#[op]
+fn op_fetch_decode_text(bytes: JsBuffer) -> String {
+	String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,393 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use deno_core::anyhow::anyhow;
+use deno_core::{op, JsBuffer, OpDecl, OpState, ToJsBuffer};
+use serde::Serialize;
+
+use crate::module_loader::path_within;
+use crate::permissions::PermissionState;
+use crate::{AnyError, VirtualFs};
+
+/// Read-only file access for scripts, through `host.fs`. Enabled with [`ScriptBuilder::host_fs()`](crate::ScriptBuilder::host_fs).
+///
+/// Scripts only see directories mounted under a virtual path, e.g. `/templates`, and nothing outside of them:
+///
+/// ```rust,no_run
+/// use js_sandbox::{HostFs, Permissions, Script, AnyError};
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let fs = HostFs::new()
+/// 		.mount("/templates", "plugins/tenant-42/templates")?
+/// 		.mount("/tax", "shared/tax-tables")?;
+///
+/// 	let mut script = Script::builder()
+/// 		.host_fs(fs)
+/// 		.permissions(Permissions::new().allow_read("plugins/tenant-42").allow_read("shared/tax-tables"))
+/// 		.build_from_string("function rates() { return host.fs.readText('/tax/2023.csv'); }")?;
+///
+/// 	let csv: String = script.call("rates", ())?;
+/// 	Ok(())
+/// }
+/// ```
+///
+/// In JS, `host.fs` offers these synchronous functions, taking virtual paths:
+/// * `readText(path)`: contents of a UTF-8 file as string.
+/// * `readBytes(path)`: contents of a file as `Uint8Array`.
+/// * `list(path)`: entries of a directory as `{ name, isFile, isDirectory, isSymlink }`, sorted by name.
+/// * `stat(path)`: `{ isFile, isDirectory, size, modified }`, with `modified` in milliseconds since the epoch.
+/// * `writeText(path, text)`, `writeBytes(path, bytes)` and `remove(path)`: only available with a [`VirtualFs`].
+///
+/// Paths leading outside of the mounts, through `..` or symlinks, are refused. Files larger than
+/// [`max_file_size()`](Self::max_file_size) cannot be read. In addition, the script needs read permission for the real
+/// paths, see [`Permissions::allow_read()`](crate::Permissions::allow_read).
+///
+/// Files of a [`VirtualFs`] given to [`ScriptBuilder::virtual_fs()`](crate::ScriptBuilder::virtual_fs) take precedence
+/// over mounted ones and need no permission. Mounted directories are never written to.
+#[derive(Clone, Debug)]
+pub struct HostFs {
+	mounts: Vec<Mount>,
+	max_file_size: u64,
+	max_list_entries: usize,
+
+	/// Files overlaying the mounts, set through `ScriptBuilder::virtual_fs()`
+	pub(crate) virtual_fs: Option<VirtualFs>,
+}
+
+#[derive(Clone, Debug)]
+struct Mount {
+	/// Normalized virtual path, split into segments
+	segments: Vec<String>,
+
+	/// Canonical path of the mounted directory
+	root: PathBuf,
+}
+
+impl Default for HostFs {
+	fn default() -> Self {
+		Self {
+			mounts: Vec::new(),
+			max_file_size: 10 * 1024 * 1024,
+			max_list_entries: 10_000,
+			virtual_fs: None,
+		}
+	}
+}
+
+impl HostFs {
+	/// Creates a file system without mounts.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Makes the directory `dir` visible to scripts under `virtual_path`, e.g. `"/templates"`.
+	///
+	/// Mounts can be nested; the innermost one applies. Fails if `dir` does not exist or is not a directory, or if
+	/// `virtual_path` is not absolute.
+	pub fn mount(mut self, virtual_path: &str, dir: impl AsRef<Path>) -> Result<Self, AnyError> {
+		let root = dir.as_ref().canonicalize()?;
+		if !root.is_dir() {
+			return Err(anyhow!("Cannot mount {}: not a directory", root.display()));
+		}
+
+		let segments = virtual_segments(virtual_path)?
+			.into_iter()
+			.map(str::to_string)
+			.collect();
+		self.mounts.push(Mount { segments, root });
+		Ok(self)
+	}
+
+	/// Limits the size of files that scripts can read; 10 MiB by default.
+	pub fn max_file_size(mut self, bytes: u64) -> Self {
+		self.max_file_size = bytes;
+		self
+	}
+
+	/// Limits the number of entries `list()` returns for a directory; 10000 by default. Larger directories fail to list.
+	pub fn max_list_entries(mut self, entries: usize) -> Self {
+		self.max_list_entries = entries;
+		self
+	}
+
+	/// Maps `virtual_path` to the real path within the innermost mount containing it, with symlinks resolved.
+	fn resolve(&self, virtual_path: &str) -> Result<PathBuf, AnyError> {
+		let segments = virtual_segments(virtual_path)?;
+
+		let mount = self
+			.mounts
+			.iter()
+			.filter(|mount| {
+				mount.segments.len() <= segments.len()
+					&& mount.segments.iter().zip(&segments).all(|(a, b)| a == b)
+			})
+			.max_by_key(|mount| mount.segments.len())
+			.ok_or_else(|| {
+				io_error(
+					ErrorKind::NotFound,
+					format!("host.fs: '{virtual_path}' is not within a mounted directory"),
+				)
+			})?;
+
+		let path = segments[mount.segments.len()..]
+			.iter()
+			.fold(mount.root.clone(), |path, segment| path.join(segment));
+
+		path_within(&path, &mount.root)
+			.map_err(|e| {
+				io_error(
+					e.kind(),
+					format!("host.fs: cannot access '{virtual_path}': {e}"),
+				)
+			})?
+			.ok_or_else(|| anyhow!("host.fs: '{virtual_path}' leads outside of its mount"))
+	}
+}
+
+/// Splits an absolute virtual path into segments, resolving `.` and `..`. Fails if `..` would leave the root.
+pub(crate) fn virtual_segments(path: &str) -> Result<Vec<&str>, AnyError> {
+	let Some(relative) = path.strip_prefix('/') else {
+		return Err(anyhow!("host.fs: path '{path}' is not absolute"));
+	};
+
+	let mut segments = Vec::new();
+	for segment in relative.split('/') {
+		match segment {
+			"" | "." => {}
+			".." => {
+				if segments.pop().is_none() {
+					return Err(anyhow!("host.fs: path '{path}' leads outside of the root"));
+				}
+			}
+			_ if segment.contains(['\\', '\0']) || segment.ends_with(':') => {
+				return Err(anyhow!(
+					"host.fs: path '{path}' contains invalid characters"
+				));
+			}
+			_ => segments.push(segment),
+		}
+	}
+
+	Ok(segments)
+}
+
+/// Resolves `virtual_path` and checks that the script may read the result.
+fn readable_path(state: &mut OpState, virtual_path: &str) -> Result<PathBuf, AnyError> {
+	let path = host_fs(state)?.resolve(virtual_path)?;
+	state.borrow_mut::<PermissionState>().check_read(&path)?;
+	Ok(path)
+}
+
+/// Error keeping the [`ErrorKind`] of a failed file system access, so that a missing path can be told from other failures
+fn io_error(kind: ErrorKind, message: String) -> AnyError {
+	std::io::Error::new(kind, message).into()
+}
+
+fn is_not_found(error: &AnyError) -> bool {
+	error
+		.downcast_ref::<std::io::Error>()
+		.map_or(false, |e| e.kind() == ErrorKind::NotFound)
+}
+
+fn host_fs(state: &OpState) -> Result<&HostFs, AnyError> {
+	state.try_borrow::<HostFs>().ok_or_else(|| {
+		anyhow!("host.fs is not enabled; build the script with ScriptBuilder::host_fs() or virtual_fs()")
+	})
+}
+
+fn virtual_fs(state: &OpState) -> Result<Option<VirtualFs>, AnyError> {
+	Ok(host_fs(state)?.virtual_fs.clone())
+}
+
+fn writable_fs(state: &OpState, virtual_path: &str) -> Result<VirtualFs, AnyError> {
+	virtual_fs(state)?.ok_or_else(|| {
+		anyhow!("host.fs: cannot write '{virtual_path}': only a virtual file system is writable")
+	})
+}
+
+fn read_file(state: &mut OpState, virtual_path: &str) -> Result<Vec<u8>, AnyError> {
+	if let Some(bytes) = virtual_fs(state)?.and_then(|vfs| vfs.get(virtual_path)) {
+		return Ok(bytes);
+	}
+
+	let path = readable_path(state, virtual_path)?;
+	let limit = host_fs(state)?.max_file_size;
+	let too_large = || anyhow!("host.fs: '{virtual_path}' exceeds the size limit of {limit} bytes");
+
+	let file = std::fs::File::open(&path)
+		.map_err(|e| anyhow!("host.fs: cannot read '{virtual_path}': {e}"))?;
+	if file.metadata()?.len() > limit {
+		return Err(too_large());
+	}
+
+	// The file may grow after the check
+	let mut bytes = Vec::new();
+	file.take(limit + 1)
+		.read_to_end(&mut bytes)
+		.map_err(|e| anyhow!("host.fs: cannot read '{virtual_path}': {e}"))?;
+	if bytes.len() as u64 > limit {
+		return Err(too_large());
+	}
+
+	Ok(bytes)
+}
+
+/// Ops for fs.js, registered with the crate's other ops
+pub(crate) fn ops() -> Vec<OpDecl> {
+	vec![
+		op_fs_read_text::decl(),
+		op_fs_read_bytes::decl(),
+		op_fs_list::decl(),
+		op_fs_stat::decl(),
+		op_fs_write_text::decl(),
+		op_fs_write_bytes::decl(),
+		op_fs_remove::decl(),
+	]
+}
+
+#[op]
+fn op_fs_read_text(state: &mut OpState, path: String) -> Result<String, AnyError> {
+	let bytes = read_file(state, &path)?;
+	String::from_utf8(bytes).map_err(|_| anyhow!("host.fs: '{path}' is not valid UTF-8"))
+}
+
+#[op]
+fn op_fs_read_bytes(state: &mut OpState, path: String) -> Result<ToJsBuffer, AnyError> {
+	Ok(read_file(state, &path)?.into())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DirEntry {
+	name: String,
+	is_file: bool,
+	is_directory: bool,
+	is_symlink: bool,
+}
+
+/// Entries of the mounted directory `path`, read lazily
+fn read_dir<'a>(
+	state: &mut OpState,
+	path: &'a str,
+) -> Result<impl Iterator<Item = Result<DirEntry, AnyError>> + 'a, AnyError> {
+	let dir = readable_path(state, path)?;
+	let error =
+		move |e: std::io::Error| io_error(e.kind(), format!("host.fs: cannot list '{path}': {e}"));
+
+	let entries = std::fs::read_dir(dir).map_err(error)?;
+	Ok(entries.map(move |entry| {
+		let entry = entry.map_err(error)?;
+		let file_type = entry.file_type().map_err(error)?;
+		Ok(DirEntry {
+			name: entry.file_name().to_string_lossy().into_owned(),
+			is_file: file_type.is_file(),
+			is_directory: file_type.is_dir(),
+			is_symlink: file_type.is_symlink(),
+		})
+	}))
+}
+
+/// Lists a directory, with virtual files taking precedence over those of mounted directories
+#[op]
+fn op_fs_list(state: &mut OpState, path: String) -> Result<Vec<DirEntry>, AnyError> {
+	let virtual_entries = virtual_fs(state)?.and_then(|vfs| vfs.list(&path));
+	let limit = host_fs(state)?.max_list_entries;
+	let too_many = || anyhow!("host.fs: '{path}' has more than {limit} entries");
+
+	let mut entries = Vec::new();
+	match (read_dir(state, &path), &virtual_entries) {
+		(Ok(dir_entries), _) => {
+			for entry in dir_entries {
+				// Stop before reading all of a huge directory
+				if entries.len() == limit {
+					return Err(too_many());
+				}
+				entries.push(entry?);
+			}
+		}
+		// Virtual directories need no counterpart on the host
+		(Err(e), Some(_)) if is_not_found(&e) => {}
+		(Err(e), Some(_)) => return Err(e),
+		(Err(e), None) => return Err(e),
+	}
+
+	for virtual_entry in virtual_entries.into_iter().flatten() {
+		entries.retain(|entry| entry.name != virtual_entry.name);
+		entries.push(DirEntry {
+			name: virtual_entry.name,
+			is_file: virtual_entry.is_file,
+			is_directory: !virtual_entry.is_file,
+			is_symlink: false,
+		});
+	}
+
+	if entries.len() > limit {
+		return Err(too_many());
+	}
+
+	entries.sort_by(|a, b| a.name.cmp(&b.name));
+	Ok(entries)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileStat {
+	is_file: bool,
+	is_directory: bool,
+	size: u64,
+	modified: Option<f64>,
+}
+
+#[op]
+fn op_fs_stat(state: &mut OpState, path: String) -> Result<FileStat, AnyError> {
+	if let Some(vfs) = virtual_fs(state)? {
+		let stat = |is_file: bool, size: usize| FileStat {
+			is_file,
+			is_directory: !is_file,
+			size: size as u64,
+			modified: None,
+		};
+
+		match vfs.kind(&path) {
+			Some(true) => return Ok(stat(true, vfs.get(&path).map_or(0, |bytes| bytes.len()))),
+			Some(false) => return Ok(stat(false, 0)),
+			None => {}
+		}
+	}
+
+	let real_path = readable_path(state, &path)?;
+	let metadata = std::fs::metadata(real_path)
+		.map_err(|e| anyhow!("host.fs: cannot access '{path}': {e}"))?;
+
+	let modified = metadata
+		.modified()
+		.ok()
+		.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+		.map(|duration| duration.as_millis() as f64);
+
+	Ok(FileStat {
+		is_file: metadata.is_file(),
+		is_directory: metadata.is_dir(),
+		size: metadata.len(),
+		modified,
+	})
+}
+
+#[op]
+fn op_fs_write_text(state: &mut OpState, path: String, text: String) -> Result<(), AnyError> {
+	writable_fs(state, &path)?.write(&path, text.into_bytes())
+}
+
+#[op]
+fn op_fs_write_bytes(state: &mut OpState, path: String, bytes: JsBuffer) -> Result<(), AnyError> {
+	writable_fs(state, &path)?.write(&path, bytes.to_vec())
+}
+
+/// Removes a virtual file; returns whether it existed
+#[op]
+fn op_fs_remove(state: &mut OpState, path: String) -> Result<bool, AnyError> {
+	Ok(writable_fs(state, &path)?.remove(&path).is_some())
+}
@@ -0,0 +1,47 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Deterministic mode, installed after timers.js for scripts built with `ScriptBuilder::deterministic()`.
+//
+// Replaces `Math.random()` with a seeded generator and makes `Date` follow the virtual clock, starting at a time set by
+// the host. Both are backed by ops (deterministic.rs), so that the state lives in Rust.
+((globalThis) => {
+	const ops = Deno.core.ops;
+	const OriginalDate = globalThis.Date;
+
+	const now = () => ops.op_deterministic_date_now();
+
+	// Called as function, `Date()` returns the current time as string; with `new` and no arguments, the current time
+	function Date(...args) {
+		if (new.target === undefined) {
+			return new OriginalDate(now()).toString();
+		}
+
+		return Reflect.construct(OriginalDate, args.length === 0 ? [now()] : args, new.target);
+	}
+
+	Object.setPrototypeOf(Date, OriginalDate);
+	Object.defineProperties(Date, {
+		prototype: { value: OriginalDate.prototype, writable: false },
+		now: { value: now, writable: true, configurable: true },
+		length: { value: 7 },
+	});
+	Object.defineProperty(OriginalDate.prototype, "constructor", {
+		value: Date,
+		writable: true,
+		configurable: true,
+	});
+
+	Object.defineProperty(globalThis, "Date", {
+		value: Date,
+		enumerable: false,
+		writable: true,
+		configurable: true,
+	});
+
+	Object.defineProperty(Math, "random", {
+		value: () => ops.op_deterministic_random(),
+		enumerable: false,
+		writable: true,
+		configurable: true,
+	});
+})(globalThis);
@@ -0,0 +1,184 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// `fetch()`, installed for scripts built with `ScriptBuilder::fetch()`.
+//
+// Provides `fetch`, `Headers` and `Response` with buffered bodies. Allowed methods, hosts and sizes are checked in
+// fetch.rs, which hands requests to the host's transport.
+((globalThis) => {
+	const core = Deno.core;
+	const ops = core.ops;
+
+	const STATUS_TEXT = {
+		200: "OK",
+		201: "Created",
+		202: "Accepted",
+		204: "No Content",
+		301: "Moved Permanently",
+		302: "Found",
+		304: "Not Modified",
+		400: "Bad Request",
+		401: "Unauthorized",
+		403: "Forbidden",
+		404: "Not Found",
+		409: "Conflict",
+		422: "Unprocessable Entity",
+		429: "Too Many Requests",
+		500: "Internal Server Error",
+		502: "Bad Gateway",
+		503: "Service Unavailable",
+		504: "Gateway Timeout",
+	};
+
+	// Header names are case-insensitive; values of repeated headers are joined like browsers do
+	class Headers {
+		#map = new Map();
+
+		constructor(init) {
+			if (init === undefined || init === null) {
+				return;
+			}
+
+			const entries = init instanceof Headers || Array.isArray(init) ? init : Object.entries(init);
+			for (const [name, value] of entries) {
+				this.append(name, value);
+			}
+		}
+
+		append(name, value) {
+			const key = String(name).toLowerCase();
+			const previous = this.#map.get(key);
+			this.#map.set(key, previous === undefined ? String(value) : `${previous}, ${value}`);
+		}
+
+		set(name, value) {
+			this.#map.set(String(name).toLowerCase(), String(value));
+		}
+
+		get(name) {
+			return this.#map.get(String(name).toLowerCase()) ?? null;
+		}
+
+		has(name) {
+			return this.#map.has(String(name).toLowerCase());
+		}
+
+		delete(name) {
+			this.#map.delete(String(name).toLowerCase());
+		}
+
+		forEach(callback, thisArg) {
+			for (const [name, value] of this) {
+				callback.call(thisArg, value, name, this);
+			}
+		}
+
+		*entries() {
+			yield* [...this.#map].sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+		}
+
+		*keys() {
+			for (const [name] of this) {
+				yield name;
+			}
+		}
+
+		*values() {
+			for (const [, value] of this) {
+				yield value;
+			}
+		}
+
+		[Symbol.iterator]() {
+			return this.entries();
+		}
+	}
+
+	class Response {
+		#body;
+		#bodyUsed = false;
+
+		constructor({ status, headers, url, body }) {
+			this.status = status;
+			this.statusText = STATUS_TEXT[status] ?? "";
+			this.ok = status >= 200 && status < 300;
+			this.url = url;
+			this.headers = new Headers(headers);
+			this.#body = body;
+		}
+
+		get bodyUsed() {
+			return this.#bodyUsed;
+		}
+
+		#consume() {
+			if (this.#bodyUsed) {
+				return Promise.reject(new TypeError("Response body has already been consumed"));
+			}
+			this.#bodyUsed = true;
+			return Promise.resolve(this.#body);
+		}
+
+		bytes() {
+			return this.#consume();
+		}
+
+		async arrayBuffer() {
+			const bytes = await this.#consume();
+			return bytes.buffer.slice(bytes.byteOffset, bytes.byteOffset + bytes.byteLength);
+		}
+
+		async text() {
+			return ops.op_fetch_decode_text(await this.#consume());
+		}
+
+		async json() {
+			return JSON.parse(await this.text());
+		}
+	}
+
+	function requestBody(body, headers) {
+		if (body === undefined || body === null) {
+			return {};
+		}
+		if (typeof body === "string") {
+			return { text: body };
+		}
+		if (body instanceof ArrayBuffer) {
+			return { bytes: new Uint8Array(body) };
+		}
+		if (ArrayBuffer.isView(body)) {
+			return { bytes: new Uint8Array(body.buffer, body.byteOffset, body.byteLength) };
+		}
+		if (typeof URLSearchParams !== "undefined" && body instanceof URLSearchParams) {
+			if (!headers.has("content-type")) {
+				headers.set("content-type", "application/x-www-form-urlencoded;charset=UTF-8");
+			}
+			return { text: body.toString() };
+		}
+		throw new TypeError("fetch(): body must be a string, ArrayBuffer or typed array");
+	}
+
+	async function fetch(input, init = {}) {
+		const url = typeof input === "string" ? input : String(input?.href ?? input);
+		const headers = new Headers(init.headers);
+		const { text, bytes } = requestBody(init.body, headers);
+
+		const args = {
+			method: String(init.method ?? "GET"),
+			url,
+			headers: [...headers],
+			text: text ?? null,
+		};
+		const result = await core.opAsync("op_fetch", args, bytes ?? null);
+		return new Response(result);
+	}
+
+	for (const [name, value] of Object.entries({ fetch, Headers, Response })) {
+		Object.defineProperty(globalThis, name, {
+			value,
+			enumerable: false,
+			writable: true,
+			configurable: true,
+		});
+	}
+})(globalThis);
@@ -0,0 +1,38 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// File system installed as `host.fs` for scripts built with `ScriptBuilder::host_fs()` or `virtual_fs()`.
+//
+// Paths are virtual, within the directories the host mounted or its in-memory VirtualFs; only the latter is writable.
+// Resolution, limits and permission checks happen in host_fs.rs.
+((globalThis) => {
+	const ops = Deno.core.ops;
+
+	function checkPath(path) {
+		if (typeof path !== "string") {
+			throw new TypeError("host.fs: path must be a string");
+		}
+		return path;
+	}
+
+	const fs = {
+		readText: (path) => ops.op_fs_read_text(checkPath(path)),
+		readBytes: (path) => ops.op_fs_read_bytes(checkPath(path)),
+		list: (path) => ops.op_fs_list(checkPath(path)),
+		stat: (path) => ops.op_fs_stat(checkPath(path)),
+		writeText: (path, text) => ops.op_fs_write_text(checkPath(path), String(text)),
+		writeBytes(path, bytes) {
+			if (!(bytes instanceof Uint8Array)) {
+				throw new TypeError("host.fs: bytes must be a Uint8Array");
+			}
+			ops.op_fs_write_bytes(checkPath(path), bytes);
+		},
+		remove: (path) => ops.op_fs_remove(checkPath(path)),
+	};
+
+	Object.defineProperty(globalThis.host, "fs", {
+		value: Object.freeze(fs),
+		enumerable: true,
+		writable: false,
+		configurable: false,
+	});
+})(globalThis);
@@ -0,0 +1,84 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Host API, installed into every runtime before user code runs.
+//
+// Exposes `host.on(event, handler)` and `host.off(event, handler?)` to scripts. The dispatcher `__rust_emit` is
+// called from Rust by `Script::emit()` and is not meant to be used from JS.
+//
+// `host.PermissionDenied` is the error thrown by ops when the script's permissions deny an access.
+((globalThis) => {
+	const handlers = new Map();
+
+	class PermissionDenied extends Error {
+		constructor(message) {
+			super(message);
+			this.name = "PermissionDenied";
+		}
+	}
+
+	Deno.core.registerErrorClass("PermissionDenied", PermissionDenied);
+
+	function on(event, handler) {
+		if (typeof handler !== "function") {
+			throw new TypeError(`host.on(): handler for event '${event}' is not a function`);
+		}
+
+		const list = handlers.get(event);
+		if (list) {
+			list.push(handler);
+		} else {
+			handlers.set(event, [handler]);
+		}
+	}
+
+	// Without a handler, removes all handlers of that event. Returns whether anything was removed.
+	function off(event, handler) {
+		if (handler === undefined) {
+			return handlers.delete(event);
+		}
+
+		const list = handlers.get(event);
+		const index = list ? list.indexOf(handler) : -1;
+		if (index < 0) {
+			return false;
+		}
+
+		list.splice(index, 1);
+		if (list.length === 0) {
+			handlers.delete(event);
+		}
+		return true;
+	}
+
+	// Invokes handlers in registration order, awaiting async ones one after another.
+	// A throwing handler does not prevent the remaining ones from running.
+	async function emit(event, payload) {
+		const list = (handlers.get(event) ?? []).slice();
+		const results = [];
+
+		for (const handler of list) {
+			try {
+				const value = await handler(payload);
+				results.push({ ok: value === undefined ? null : value });
+			} catch (e) {
+				results.push({ err: String(e) });
+			}
+		}
+
+		return results;
+	}
+
+	Object.defineProperty(globalThis, "host", {
+		value: { on, off, PermissionDenied },
+		enumerable: false,
+		writable: false,
+		configurable: false,
+	});
+
+	Object.defineProperty(globalThis, "__rust_emit", {
+		value: emit,
+		enumerable: false,
+		writable: false,
+		configurable: false,
+	});
+})(globalThis);
@@ -0,0 +1,69 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Key-value storage, installed as `host.kv` for scripts built with `ScriptBuilder::kv()`.
+//
+// Values are JSON and live in the script's namespace of the host's store; quotas and permission checks happen in kv.rs.
+// Stored values may be `null`, so ops wrap them as `{ value }` to tell them apart from missing ones.
+((globalThis) => {
+	const ops = Deno.core.ops;
+
+	// Attempts of `update()` before giving up, if other scripts keep changing the value
+	const MAX_ATTEMPTS = 100;
+
+	function checkKey(key) {
+		if (typeof key !== "string") {
+			throw new TypeError("host.kv: key must be a string");
+		}
+		return key;
+	}
+
+	function slot(value) {
+		return value === undefined ? null : { value };
+	}
+
+	function get(key) {
+		return ops.op_kv_get(checkKey(key))?.value;
+	}
+
+	function set(key, value) {
+		if (value === undefined) {
+			throw new TypeError("host.kv.set(): value must not be undefined; use delete() to remove it");
+		}
+		ops.op_kv_set(checkKey(key), value);
+	}
+
+	function update(key, fn) {
+		checkKey(key);
+		if (typeof fn !== "function") {
+			throw new TypeError("host.kv.update(): fn is not a function");
+		}
+
+		for (let attempt = 0; attempt < MAX_ATTEMPTS; attempt++) {
+			const current = get(key);
+			const next = fn(current);
+			if (next instanceof Promise) {
+				throw new TypeError("host.kv.update(): fn must not be async");
+			}
+			if (ops.op_kv_compare_and_set(key, slot(current), slot(next))) {
+				return next;
+			}
+		}
+
+		throw new Error(`host.kv.update(): '${key}' kept changing during ${MAX_ATTEMPTS} attempts`);
+	}
+
+	const kv = {
+		get,
+		set,
+		delete: (key) => ops.op_kv_delete(checkKey(key)),
+		list: (prefix = "") => ops.op_kv_list(String(prefix)),
+		update,
+	};
+
+	Object.defineProperty(globalThis.host, "kv", {
+		value: Object.freeze(kv),
+		enumerable: true,
+		writable: false,
+		configurable: false,
+	});
+})(globalThis);
@@ -0,0 +1,47 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Export lookup for module scripts, installed after the main module has been evaluated.
+//
+// Rust stores the module namespace in `__rust_module`; this file replaces it with an object that resolves function names
+// for `Script::call()`. Names not exported directly are looked up on the default export.
+((globalThis) => {
+	const namespace = globalThis.__rust_module;
+
+	function isObject(value) {
+		return value !== null && (typeof value === "object" || typeof value === "function");
+	}
+
+	function resolve(name) {
+		if (name in namespace) {
+			return namespace[name];
+		}
+
+		const fallback = namespace.default;
+		if (isObject(fallback) && name in fallback) {
+			const value = fallback[name];
+			return typeof value === "function" ? value.bind(fallback) : value;
+		}
+
+		throw new ReferenceError(`'${name}' is not exported by the module`);
+	}
+
+	function has(name) {
+		try {
+			return typeof resolve(name) === "function";
+		} catch (e) {
+			if (e instanceof ReferenceError) return false;
+			throw e;
+		}
+	}
+
+	function names() {
+		return Object.keys(namespace);
+	}
+
+	Object.defineProperty(globalThis, "__rust_module", {
+		value: Object.freeze({ resolve, has, names }),
+		enumerable: false,
+		writable: false,
+		configurable: false,
+	});
+})(globalThis);
@@ -0,0 +1,254 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Node.js compatibility, installed for scripts built with `ScriptBuilder::node_compat()`.
+//
+// Provides `process`, `Buffer` and a CommonJS `require()` for libraries written for Node. Environment variables and
+// files are subject to the script's permissions; modules are loaded through the script's module loader (node.rs).
+((globalThis) => {
+	const core = Deno.core;
+	const ops = core.ops;
+
+	// ------------------------------------------------------------------------------------------------------------------
+	// process
+
+	// Variables set or deleted by the script; they shadow the host's values and never reach the host
+	const envOverlay = new Map();
+	const DELETED = Symbol("deleted");
+
+	function envGet(name) {
+		if (envOverlay.has(name)) {
+			const value = envOverlay.get(name);
+			return value === DELETED ? undefined : value;
+		}
+		return ops.op_node_env_get(name) ?? undefined;
+	}
+
+	function envKeys() {
+		const keys = new Set(ops.op_node_env_keys());
+		for (const [name, value] of envOverlay) {
+			if (value === DELETED) {
+				keys.delete(name);
+			} else {
+				keys.add(name);
+			}
+		}
+		return [...keys];
+	}
+
+	const env = new Proxy({}, {
+		get: (_, name) => (typeof name === "string" ? envGet(name) : undefined),
+		set(_, name, value) {
+			envOverlay.set(String(name), String(value));
+			return true;
+		},
+		deleteProperty(_, name) {
+			envOverlay.set(String(name), DELETED);
+			return true;
+		},
+		// Unlike reading, checking for a variable does not require permission; only allowed variables are listed
+		has: (_, name) => envKeys().includes(name),
+		ownKeys: () => envKeys(),
+		getOwnPropertyDescriptor(_, name) {
+			const value = typeof name === "string" ? envGet(name) : undefined;
+			if (value === undefined) {
+				return undefined;
+			}
+			return { value, writable: true, enumerable: true, configurable: true };
+		},
+	});
+
+	const process = {
+		env,
+		argv: [],
+		execArgv: [],
+		platform: "sandbox",
+		version: "v18.0.0",
+		versions: { node: "18.0.0" },
+		cwd: () => "/",
+		nextTick: (callback, ...args) => queueMicrotask(() => callback(...args)),
+		exit(code = 0) {
+			throw new Error(`process.exit(${code}) is not supported in the sandbox`);
+		},
+	};
+
+	// ------------------------------------------------------------------------------------------------------------------
+	// Buffer
+
+	const ENCODINGS = {
+		"utf8": "utf8",
+		"utf-8": "utf8",
+		"hex": "hex",
+		"base64": "base64",
+		"latin1": "latin1",
+		"binary": "latin1",
+		"ascii": "ascii",
+	};
+
+	function normalizeEncoding(encoding = "utf8") {
+		const normalized = ENCODINGS[String(encoding).toLowerCase()];
+		if (normalized === undefined) {
+			throw new TypeError(`Unknown encoding: ${encoding}`);
+		}
+		return normalized;
+	}
+
+	class Buffer extends Uint8Array {
+		static from(value, encodingOrOffset, length) {
+			if (typeof value === "string") {
+				const bytes = ops.op_node_buffer_encode(value, normalizeEncoding(encodingOrOffset));
+				return new Buffer(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+			}
+			if (value instanceof ArrayBuffer) {
+				const offset = encodingOrOffset ?? 0;
+				return new Buffer(value, offset, length ?? value.byteLength - offset);
+			}
+			if (ArrayBuffer.isView(value) || Array.isArray(value)) {
+				const buffer = new Buffer(value.length);
+				buffer.set(value);
+				return buffer;
+			}
+			if (value && value.type === "Buffer" && Array.isArray(value.data)) {
+				return Buffer.from(value.data);
+			}
+			throw new TypeError("Buffer.from(): argument must be a string, array, buffer or ArrayBuffer");
+		}
+
+		static alloc(size, fill = 0, encoding) {
+			const buffer = new Buffer(size);
+			if (typeof fill === "string") {
+				const pattern = Buffer.from(fill, encoding);
+				for (let i = 0; i < size && pattern.length > 0; i++) {
+					buffer[i] = pattern[i % pattern.length];
+				}
+			} else {
+				buffer.fill(fill);
+			}
+			return buffer;
+		}
+
+		static allocUnsafe(size) {
+			return new Buffer(size);
+		}
+
+		static isBuffer(value) {
+			return value instanceof Buffer;
+		}
+
+		static isEncoding(encoding) {
+			return typeof encoding === "string" && ENCODINGS[encoding.toLowerCase()] !== undefined;
+		}
+
+		static byteLength(value, encoding) {
+			if (typeof value !== "string") {
+				return value.byteLength;
+			}
+			return Buffer.from(value, encoding).length;
+		}
+
+		static concat(list, totalLength) {
+			totalLength ??= list.reduce((sum, item) => sum + item.length, 0);
+
+			const result = Buffer.alloc(totalLength);
+			let offset = 0;
+			for (const item of list) {
+				if (offset >= totalLength) {
+					break;
+				}
+				const part = item.subarray(0, totalLength - offset);
+				result.set(part, offset);
+				offset += part.length;
+			}
+			return result;
+		}
+
+		toString(encoding, start = 0, end = this.length) {
+			return ops.op_node_buffer_decode(this.subarray(start, end), normalizeEncoding(encoding));
+		}
+
+		toJSON() {
+			return { type: "Buffer", data: Array.from(this) };
+		}
+
+		equals(other) {
+			return this.length === other.length && this.every((byte, i) => byte === other[i]);
+		}
+
+		// Like Node, slices share memory with the original
+		slice(start, end) {
+			return this.subarray(start, end);
+		}
+
+		write(string, offset = 0, encoding = "utf8") {
+			if (typeof offset === "string") {
+				encoding = offset;
+				offset = 0;
+			}
+
+			const bytes = Buffer.from(string, encoding).subarray(0, this.length - offset);
+			this.set(bytes, offset);
+			return bytes.length;
+		}
+	}
+
+	// ------------------------------------------------------------------------------------------------------------------
+	// require
+
+	const cache = new Map();
+
+	function requireFrom(referrer, specifier) {
+		if (typeof specifier !== "string") {
+			throw new TypeError("require(): specifier must be a string");
+		}
+
+		const { url, code, json } = ops.op_node_require(specifier, referrer);
+		const cached = cache.get(url);
+		if (cached) {
+			return cached.exports;
+		}
+
+		const module = { id: url, filename: url, loaded: false, exports: {} };
+		cache.set(url, module);
+
+		try {
+			if (json) {
+				module.exports = JSON.parse(code);
+			} else {
+				const wrapper = `(function (exports, require, module, __filename, __dirname) {${code}\n})`;
+				const [fn, error] = core.evalContext(wrapper, url);
+				if (error) {
+					throw error.thrown;
+				}
+
+				const dirname = url.slice(0, url.lastIndexOf("/"));
+				fn.call(module.exports, module.exports, makeRequire(url), module, url, dirname);
+			}
+		} catch (e) {
+			cache.delete(url);
+			throw e;
+		}
+
+		module.loaded = true;
+		return module.exports;
+	}
+
+	function makeRequire(referrer) {
+		const require = (specifier) => requireFrom(referrer, specifier);
+		require.cache = cache;
+		return require;
+	}
+
+	const globals = {
+		process,
+		Buffer,
+		require: makeRequire(null),
+	};
+
+	for (const [name, value] of Object.entries(globals)) {
+		Object.defineProperty(globalThis, name, {
+			value,
+			enumerable: false,
+			writable: true,
+			configurable: true,
+		});
+	}
+})(globalThis);
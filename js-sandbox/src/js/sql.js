@@ -0,0 +1,43 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// SQL access, installed as `sqlSelect()` and `sqlExecute()` for scripts built with `ScriptBuilder::sql()`.
+//
+// Values are only ever passed as bound parameters. Called as template tag, e.g. sqlSelect`... WHERE id = ${id}`, the
+// interpolated values become `?` placeholders with parameters. Statements are run by the host's executor (sql.rs).
+((globalThis) => {
+	const ops = Deno.core.ops;
+
+	function statement(name, query, args) {
+		// Template tag: the strings array has a `raw` property, the values follow as arguments
+		if (Array.isArray(query) && Array.isArray(query.raw)) {
+			return [query.join("?"), args];
+		}
+
+		if (typeof query !== "string") {
+			throw new TypeError(`${name}(): query must be a string`);
+		}
+
+		const [params = [], ...rest] = args;
+		if (!Array.isArray(params) || rest.length > 0) {
+			throw new TypeError(`${name}(): parameters must be passed as one array`);
+		}
+		return [query, params];
+	}
+
+	function sqlSelect(query, ...args) {
+		return ops.op_sql_select(...statement("sqlSelect", query, args));
+	}
+
+	function sqlExecute(query, ...args) {
+		return ops.op_sql_execute(...statement("sqlExecute", query, args));
+	}
+
+	for (const [name, value] of Object.entries({ sqlSelect, sqlExecute })) {
+		Object.defineProperty(globalThis, name, {
+			value,
+			enumerable: false,
+			writable: true,
+			configurable: true,
+		});
+	}
+})(globalThis);
@@ -0,0 +1,98 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Timer globals, installed into every runtime before user code runs.
+//
+// Provides `setTimeout`, `setInterval`, their `clear*` counterparts, `queueMicrotask` and `performance.now()`.
+// Deadlines and limits are managed in Rust (timers.rs); this side keeps the callbacks and runs them once
+// `op_timer_wait` reports them due.
+((globalThis) => {
+	const ops = Deno.core.ops;
+	const callbacks = new Map();
+	let waiting = false;
+
+	function start(callback, delay, args, repeat) {
+		if (typeof callback !== "function") {
+			throw new TypeError("Timer callback is not a function; strings are not evaluated");
+		}
+
+		const id = ops.op_timer_start(Number(delay) || 0, repeat);
+		callbacks.set(id, { callback, args, repeat });
+		wait();
+		return id;
+	}
+
+	function cancel(id) {
+		if (typeof id === "number" && callbacks.delete(id)) {
+			ops.op_timer_cancel(id);
+		}
+	}
+
+	// A single pending op waits for all timers; it keeps the event loop alive as long as timers are pending.
+	function wait() {
+		if (waiting || callbacks.size === 0) {
+			return;
+		}
+
+		waiting = true;
+		Deno.core.opAsync("op_timer_wait").then(dispatch);
+	}
+
+	// Runs due callbacks in order. The first error is rethrown after all of them ran, failing the current call.
+	function dispatch(ids) {
+		waiting = false;
+
+		// No timers left on the Rust side: they were cancelled, e.g. after a timeout
+		if (ids.length === 0) {
+			callbacks.clear();
+			return;
+		}
+
+		let error;
+		for (const id of ids) {
+			const timer = callbacks.get(id);
+			if (!timer) {
+				continue;
+			}
+			if (!timer.repeat) {
+				callbacks.delete(id);
+			}
+
+			try {
+				timer.callback(...timer.args);
+			} catch (e) {
+				error ??= e;
+			}
+		}
+
+		wait();
+		if (error !== undefined) {
+			throw error;
+		}
+	}
+
+	function queueMicrotask(callback) {
+		if (typeof callback !== "function") {
+			throw new TypeError("queueMicrotask(): callback is not a function");
+		}
+
+		Promise.resolve().then(() => callback());
+	}
+
+	const globals = {
+		setTimeout: (callback, delay, ...args) => start(callback, delay, args, false),
+		setInterval: (callback, delay, ...args) => start(callback, delay, args, true),
+		clearTimeout: cancel,
+		clearInterval: cancel,
+		queueMicrotask,
+		performance: { now: () => ops.op_timer_now() },
+	};
+
+	for (const [name, value] of Object.entries(globals)) {
+		Object.defineProperty(globalThis, name, {
+			value,
+			enumerable: false,
+			writable: true,
+			configurable: true,
+		});
+	}
+})(globalThis);
@@ -0,0 +1,358 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Web APIs, installed for scripts built with `ScriptBuilder::web_apis()` (cargo feature "web").
+//
+// A subset commonly used by npm utility libraries: `TextEncoder`, `TextDecoder`, `URL`, `URLSearchParams`, `atob`,
+// `btoa`, `structuredClone` and `crypto.randomUUID()`. The work is done by ops in web.rs; none of these reach the
+// network or file system.
+((globalThis) => {
+	const core = Deno.core;
+	const ops = core.ops;
+
+	function domError(name, message) {
+		const error = new Error(message);
+		error.name = name;
+		return error;
+	}
+
+	function toBytes(input) {
+		if (input === undefined) {
+			return new Uint8Array(0);
+		}
+		if (input instanceof ArrayBuffer) {
+			return new Uint8Array(input);
+		}
+		if (ArrayBuffer.isView(input)) {
+			return new Uint8Array(input.buffer, input.byteOffset, input.byteLength);
+		}
+		throw new TypeError("Expected an ArrayBuffer or ArrayBufferView");
+	}
+
+	// ------------------------------------------------------------------------------------------------------------------
+	// Encoding
+
+	class TextEncoder {
+		get encoding() {
+			return "utf-8";
+		}
+
+		encode(input = "") {
+			return ops.op_web_encode_utf8(String(input));
+		}
+
+		// Writes as many complete characters as fit into `dest`
+		encodeInto(source, dest) {
+			let read = 0;
+			let written = 0;
+
+			for (const char of String(source)) {
+				const codePoint = char.codePointAt(0);
+				const size = codePoint < 0x80 ? 1 : codePoint < 0x800 ? 2 : codePoint < 0x10000 ? 3 : 4;
+				if (written + size > dest.length) {
+					break;
+				}
+
+				dest.set(ops.op_web_encode_utf8(char), written);
+				read += char.length;
+				written += size;
+			}
+
+			return { read, written };
+		}
+	}
+
+	class TextDecoder {
+		#fatal;
+		#ignoreBOM;
+
+		constructor(label = "utf-8", options = {}) {
+			const encoding = String(label).trim().toLowerCase();
+			if (encoding !== "utf-8" && encoding !== "utf8" && encoding !== "unicode-1-1-utf-8") {
+				throw new RangeError(`TextDecoder: encoding '${label}' is not supported, only UTF-8`);
+			}
+
+			this.#fatal = Boolean(options.fatal);
+			this.#ignoreBOM = Boolean(options.ignoreBOM);
+		}
+
+		get encoding() {
+			return "utf-8";
+		}
+
+		get fatal() {
+			return this.#fatal;
+		}
+
+		get ignoreBOM() {
+			return this.#ignoreBOM;
+		}
+
+		decode(input, options = {}) {
+			if (options.stream) {
+				throw new TypeError("TextDecoder: streaming is not supported");
+			}
+
+			try {
+				return ops.op_web_decode_utf8(toBytes(input), this.#fatal, this.#ignoreBOM);
+			} catch (e) {
+				throw new TypeError(e.message);
+			}
+		}
+	}
+
+	// ------------------------------------------------------------------------------------------------------------------
+	// URL
+
+	// Access to private fields across the two classes, assigned in their static blocks
+	let linkParams, resetParams, setSearch, getComponent, setComponent;
+
+	class URLSearchParams {
+		#list = [];
+		#url = null;
+
+		constructor(init = "") {
+			if (init instanceof URLSearchParams) {
+				this.#list = init.#list.map(([name, value]) => [name, value]);
+			} else if (typeof init === "object" && init !== null) {
+				const pairs = Symbol.iterator in init ? Array.from(init) : Object.entries(init);
+				for (const pair of pairs) {
+					const [name, value, ...rest] = Array.from(pair);
+					if (rest.length > 0 || value === undefined) {
+						throw new TypeError("URLSearchParams: each pair must have exactly two elements");
+					}
+					this.#list.push([String(name), String(value)]);
+				}
+			} else {
+				this.#parse(String(init));
+			}
+		}
+
+		static {
+			// Links the params to a URL, which is updated on every change
+			linkParams = (params, url) => {
+				params.#url = url;
+			};
+			resetParams = (params, search) => params.#parse(search);
+		}
+
+		#parse(query) {
+			this.#list = ops.op_web_url_search_params_parse(query.startsWith("?") ? query.slice(1) : query);
+		}
+
+		#update() {
+			if (this.#url) {
+				const query = this.toString();
+				setSearch(this.#url, query === "" ? "" : "?" + query);
+			}
+		}
+
+		get size() {
+			return this.#list.length;
+		}
+
+		append(name, value) {
+			this.#list.push([String(name), String(value)]);
+			this.#update();
+		}
+
+		delete(name, value) {
+			name = String(name);
+			this.#list = this.#list.filter(
+				([n, v]) => n !== name || (value !== undefined && v !== String(value)),
+			);
+			this.#update();
+		}
+
+		get(name) {
+			const entry = this.#list.find(([n]) => n === String(name));
+			return entry ? entry[1] : null;
+		}
+
+		getAll(name) {
+			return this.#list.filter(([n]) => n === String(name)).map(([, v]) => v);
+		}
+
+		has(name, value) {
+			name = String(name);
+			return this.#list.some(([n, v]) => n === name && (value === undefined || v === String(value)));
+		}
+
+		set(name, value) {
+			name = String(name);
+			value = String(value);
+
+			const index = this.#list.findIndex(([n]) => n === name);
+			if (index < 0) {
+				this.#list.push([name, value]);
+			} else {
+				this.#list[index][1] = value;
+				this.#list = this.#list.filter(([n], i) => n !== name || i <= index);
+			}
+			this.#update();
+		}
+
+		sort() {
+			this.#list.sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+			this.#update();
+		}
+
+		forEach(callback, thisArg) {
+			for (const [name, value] of this.#list) {
+				callback.call(thisArg, value, name, this);
+			}
+		}
+
+		*entries() {
+			yield* this.#list.map(([name, value]) => [name, value]);
+		}
+
+		*keys() {
+			yield* this.#list.map(([name]) => name);
+		}
+
+		*values() {
+			yield* this.#list.map(([, value]) => value);
+		}
+
+		[Symbol.iterator]() {
+			return this.entries();
+		}
+
+		toString() {
+			return ops.op_web_url_search_params_stringify(this.#list);
+		}
+	}
+
+	const URL_COMPONENTS = [
+		"href", "protocol", "username", "password", "host", "hostname", "port", "pathname", "search", "hash",
+	];
+
+	class URL {
+		#parts;
+		#searchParams;
+
+		constructor(url, base) {
+			try {
+				this.#parts = ops.op_web_url_parse(String(url), base === undefined ? null : String(base));
+			} catch (e) {
+				throw new TypeError(e.message);
+			}
+
+			this.#searchParams = new URLSearchParams(this.#parts.search);
+			linkParams(this.#searchParams, this);
+		}
+
+		static canParse(url, base) {
+			try {
+				new URL(url, base);
+				return true;
+			} catch {
+				return false;
+			}
+		}
+
+		static {
+			setSearch = (url, search) => {
+				url.#parts = ops.op_web_url_set(url.#parts.href, "search", search);
+			};
+			getComponent = (url, component) => url.#parts[component];
+			setComponent = (url, component, value) => url.#set(component, value);
+		}
+
+		#set(component, value) {
+			try {
+				this.#parts = ops.op_web_url_set(this.#parts.href, component, String(value));
+			} catch (e) {
+				throw new TypeError(e.message);
+			}
+
+			if (component === "href" || component === "search") {
+				resetParams(this.#searchParams, this.#parts.search);
+			}
+		}
+
+		get origin() {
+			return this.#parts.origin;
+		}
+
+		get searchParams() {
+			return this.#searchParams;
+		}
+
+		toString() {
+			return this.#parts.href;
+		}
+
+		toJSON() {
+			return this.#parts.href;
+		}
+	}
+
+	for (const component of URL_COMPONENTS) {
+		Object.defineProperty(URL.prototype, component, {
+			get() {
+				return getComponent(this, component);
+			},
+			set(value) {
+				setComponent(this, component, value);
+			},
+			enumerable: true,
+			configurable: true,
+		});
+	}
+
+	// ------------------------------------------------------------------------------------------------------------------
+	// Base64, cloning, crypto
+
+	function btoa(data) {
+		try {
+			return ops.op_web_btoa(String(data));
+		} catch (e) {
+			throw domError("InvalidCharacterError", e.message);
+		}
+	}
+
+	function atob(data) {
+		try {
+			return ops.op_web_atob(String(data));
+		} catch (e) {
+			throw domError("InvalidCharacterError", e.message);
+		}
+	}
+
+	// Clones through V8's serializer, which supports the same types as browsers (no functions, symbols, ...)
+	function structuredClone(value) {
+		let serialized;
+		try {
+			serialized = core.serialize(value);
+		} catch (e) {
+			throw domError("DataCloneError", e.message);
+		}
+
+		return core.deserialize(serialized);
+	}
+
+	const crypto = {
+		randomUUID: () => ops.op_web_random_uuid(),
+	};
+
+	const globals = {
+		TextEncoder,
+		TextDecoder,
+		URL,
+		URLSearchParams,
+		atob,
+		btoa,
+		structuredClone,
+		crypto,
+	};
+
+	for (const [name, value] of Object.entries(globals)) {
+		Object.defineProperty(globalThis, name, {
+			value,
+			enumerable: false,
+			writable: true,
+			configurable: true,
+		});
+	}
+})(globalThis);
@@ -1,8 +1,11 @@
 use std::{
 	error::Error,
-	fmt::{self, Display},
+	fmt::{self, Display, Write},
 };
 
+use deno_core::error::JsStackFrame;
+
+use crate::module_loader::ModuleResolutionError;
 use crate::AnyError;
 
 /// Represents an error ocurring during script execution
@@ -13,22 +16,149 @@ pub enum JsError {
 
 	/// Runtime errors occuring within a JS script
 	Runtime(AnyError),
+
+	/// A module import was rejected or could not be resolved by the module loader
+	ModuleResolution(ModuleResolutionError),
+}
+
+impl JsError {
+	/// Renders the error with a code frame: the offending source line, with a caret under the column, followed by the stack.
+	///
+	/// ```text
+	/// Uncaught Error: no tax rate for XX
+	///  --> plugins/tax.js:3:9
+	///   |
+	/// 3 | 		throw new Error(`no tax rate for ${country}`);
+	///   | 		      ^
+	///     at rate (plugins/tax.js:3:9)
+	/// ```
+	///
+	/// With `colored`, the output contains ANSI escape codes for terminals. Errors without a known source location
+	/// (e.g. JSON errors) are rendered as their plain message. The alternate format `{:#}` renders without colors.
+	pub fn render(&self, colored: bool) -> String {
+		let js_error = match self {
+			JsError::Runtime(e) => e.downcast_ref::<deno_core::error::JsError>(),
+			_ => None,
+		};
+
+		match js_error {
+			Some(js_error) => render_js_error(js_error, colored),
+			None => self.to_string(),
+		}
+	}
+}
+
+const RED: &str = "\x1b[31m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[34m";
+const GRAY: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+fn render_js_error(error: &deno_core::error::JsError, colored: bool) -> String {
+	let paint = |color: &str, text: &str| {
+		if colored {
+			format!("{color}{text}{RESET}")
+		} else {
+			text.to_string()
+		}
+	};
+
+	// Frames of the crate's own glue code are noise to script authors
+	let frames: Vec<&JsStackFrame> = error
+		.frames
+		.iter()
+		.filter(|frame| !frame.file_name.as_deref().map_or(false, is_internal_file))
+		.collect();
+
+	let mut out = paint(BOLD_RED, &error.exception_message);
+
+	// The source line belongs to this frame; there is nothing to show if it is glue code
+	let location_frame = error
+		.source_line_frame_index
+		.and_then(|i| error.frames.get(i))
+		.filter(|frame| !frame.file_name.as_deref().map_or(false, is_internal_file));
+
+	if let Some(frame) = location_frame {
+		if let (Some(file_name), Some(line), Some(column)) =
+			(&frame.file_name, frame.line_number, frame.column_number)
+		{
+			let line_label = line.to_string();
+			let gutter = " ".repeat(line_label.len());
+			let _ = write!(
+				out,
+				"\n{gutter}{} {file_name}:{line}:{column}",
+				paint(BLUE, "-->")
+			);
+
+			if let Some(source_line) = &error.source_line {
+				// Keep tabs, so that the caret lines up with the source line
+				let indent: String = source_line
+					.chars()
+					.take(column.max(1) as usize - 1)
+					.map(|c| if c == '\t' { '\t' } else { ' ' })
+					.collect();
+
+				let bar = paint(BLUE, "|");
+				let _ = write!(
+					out,
+					"\n{gutter} {bar}\n{} {bar} {source_line}\n{gutter} {bar} {indent}{}",
+					paint(BLUE, &line_label),
+					paint(RED, "^"),
+				);
+			}
+		}
+	}
+
+	for frame in frames {
+		let _ = write!(
+			out,
+			"\n{}",
+			paint(GRAY, &format!("    at {}", format_frame(frame)))
+		);
+	}
+
+	out
+}
+
+fn format_frame(frame: &JsStackFrame) -> String {
+	let location = match (&frame.file_name, frame.line_number, frame.column_number) {
+		(Some(file_name), Some(line), Some(column)) => format!("{file_name}:{line}:{column}"),
+		(Some(file_name), _, _) => file_name.clone(),
+		_ => "<anonymous>".to_string(),
+	};
+
+	match &frame.function_name {
+		Some(function_name) if !function_name.is_empty() => format!("{function_name} ({location})"),
+		_ => location,
+	}
+}
+
+fn is_internal_file(file_name: &str) -> bool {
+	file_name.starts_with("js_sandbox:") || file_name.starts_with("ext:")
 }
 
 impl Error for JsError {}
 
 impl Display for JsError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if f.alternate() {
+			return write!(f, "{}", self.render(false));
+		}
+
 		match self {
 			JsError::Json(e) => write!(f, "{}", e),
 			JsError::Runtime(e) => write!(f, "{}", e),
+			JsError::ModuleResolution(e) => write!(f, "{}", e),
 		}
 	}
 }
 
 impl From<AnyError> for JsError {
 	fn from(e: AnyError) -> JsError {
-		JsError::Runtime(e)
+		match e.downcast::<ModuleResolutionError>() {
+			Ok(e) => JsError::ModuleResolution(e),
+			Err(e) => JsError::Runtime(e),
+		}
 	}
 }
 
@@ -0,0 +1,469 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use deno_core::anyhow::{anyhow, Context};
+use deno_core::{op, OpDecl, OpState};
+use serde::{Deserialize, Serialize};
+
+use crate::permissions::PermissionState;
+use crate::{AnyError, JsValue};
+
+/// Persistent key-value storage for scripts, through `host.kv`. Enabled with [`ScriptBuilder::kv()`](crate::ScriptBuilder::kv).
+///
+/// Each script sees one namespace of a [`KvStore`], typically one per plugin, so that plugins sharing a store cannot read
+/// or overwrite each other's data. Values are JSON; the namespace is limited in [keys](Self::max_keys) and
+/// [bytes](Self::max_bytes).
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use js_sandbox::{HostKv, MemoryKvStore, Permissions, Script, AnyError};
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let store = Arc::new(MemoryKvStore::new());
+///
+/// 	let mut script = Script::builder()
+/// 		.kv(HostKv::new(store.clone(), "invoice-plugin"))
+/// 		.permissions(Permissions::new().allow_host_functions("kv"))
+/// 		.build_from_string("function run() { return host.kv.update('runs', (n) => (n ?? 0) + 1); }")?;
+///
+/// 	let _: u32 = script.call("run", ())?;
+/// 	let runs: u32 = script.call("run", ())?;
+/// 	assert_eq!(runs, 2);
+/// 	Ok(())
+/// }
+/// ```
+///
+/// In JS, `host.kv` offers these synchronous functions:
+/// * `get(key)`: the value, or `undefined` if there is none.
+/// * `set(key, value)`: stores a JSON-serializable value.
+/// * `delete(key)`: removes the value; returns whether there was one.
+/// * `list(prefix)`: entries `{ key, value }` whose key starts with `prefix`, sorted by key.
+/// * `update(key, fn)`: replaces the value by `fn(value)` atomically and returns the result; `undefined` deletes it.
+///   `fn` is run again if another script changed the value in the meantime, so it should not have side effects.
+///
+/// Scripts additionally need the permission for the host functions `"kv"`, see
+/// [`Permissions::allow_host_functions()`](crate::Permissions::allow_host_functions).
+#[derive(Clone)]
+pub struct HostKv {
+	store: Arc<dyn KvStore>,
+	namespace: String,
+	max_keys: usize,
+	max_bytes: usize,
+}
+
+impl HostKv {
+	/// Gives scripts access to `namespace` of `store`.
+	pub fn new(store: Arc<dyn KvStore>, namespace: impl Into<String>) -> Self {
+		Self {
+			store,
+			namespace: namespace.into(),
+			max_keys: 10_000,
+			max_bytes: 10 * 1024 * 1024,
+		}
+	}
+
+	/// Limits the number of keys in the namespace; 10 000 by default.
+	pub fn max_keys(mut self, keys: usize) -> Self {
+		self.max_keys = keys;
+		self
+	}
+
+	/// Limits the size of the namespace, counting keys and values as JSON; 10 MiB by default.
+	pub fn max_bytes(mut self, bytes: usize) -> Self {
+		self.max_bytes = bytes;
+		self
+	}
+
+	/// Writes `value` to `key` atomically if `condition` holds for the current value; `None` deletes the key.
+	/// Returns whether the value was written.
+	fn write(
+		&self,
+		key: &str,
+		value: Option<JsValue>,
+		condition: impl FnOnce(Option<&JsValue>) -> bool,
+	) -> Result<bool, AnyError> {
+		if key.is_empty() {
+			return Err(anyhow!("host.kv: key must not be empty"));
+		}
+
+		let mut condition = Some(condition);
+		let mut written = false;
+		self.store.update(&self.namespace, key, &mut |old, usage| {
+			let condition = condition.take().expect("update calls its function once");
+			if !condition(old) {
+				return Ok(old.cloned());
+			}
+
+			self.check_quota(usage, key, old, value.as_ref())?;
+			written = true;
+			Ok(value.clone())
+		})?;
+
+		Ok(written)
+	}
+
+	/// Fails if replacing `old` by `new` grows the namespace beyond its limits. Shrinking is always possible, even if the
+	/// namespace exceeds limits that were lowered.
+	fn check_quota(
+		&self,
+		usage: KvUsage,
+		key: &str,
+		old: Option<&JsValue>,
+		new: Option<&JsValue>,
+	) -> Result<(), AnyError> {
+		let old_bytes = old.map_or(0, |value| entry_size(key, value));
+		let new_bytes = new.map_or(0, |value| entry_size(key, value));
+
+		if old.is_none() && new.is_some() && usage.keys >= self.max_keys {
+			return Err(anyhow!(
+				"host.kv: namespace '{}' would exceed its limit of {} keys",
+				self.namespace,
+				self.max_keys
+			));
+		}
+		if new_bytes > old_bytes && usage.bytes - old_bytes + new_bytes > self.max_bytes {
+			return Err(anyhow!(
+				"host.kv: namespace '{}' would exceed its limit of {} bytes",
+				self.namespace,
+				self.max_bytes
+			));
+		}
+
+		Ok(())
+	}
+}
+
+impl fmt::Debug for HostKv {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("HostKv")
+			.field("namespace", &self.namespace)
+			.field("max_keys", &self.max_keys)
+			.field("max_bytes", &self.max_bytes)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Size of a namespace, as counted against the quotas of [`HostKv`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KvUsage {
+	/// Number of keys
+	pub keys: usize,
+
+	/// Length of all keys and values, the latter serialized as JSON
+	pub bytes: usize,
+}
+
+/// Storage behind `host.kv`, holding JSON values by namespace and key; implemented by [`MemoryKvStore`] and
+/// [`FileKvStore`].
+///
+/// Stores are shared between scripts, possibly on different threads, and must apply each call atomically.
+pub trait KvStore: Send + Sync {
+	/// Returns the value of `key` in `namespace`, if any.
+	fn get(&self, namespace: &str, key: &str) -> Result<Option<JsValue>, AnyError>;
+
+	/// Returns the entries of `namespace` whose keys start with `prefix`, sorted by key.
+	fn list(&self, namespace: &str, prefix: &str) -> Result<Vec<(String, JsValue)>, AnyError>;
+
+	/// Atomically replaces the value of `key` by the result of `f`, given the current value and the usage of `namespace`;
+	/// `None` removes the key. Nothing is written if `f` fails or returns the current value.
+	///
+	/// No other call may change `namespace` while `f` runs; `f` is called exactly once.
+	fn update(&self, namespace: &str, key: &str, f: &mut KvUpdateFn) -> Result<(), AnyError>;
+}
+
+/// Function computing the new value in [`KvStore::update()`]
+pub type KvUpdateFn<'a> =
+	dyn FnMut(Option<&JsValue>, KvUsage) -> Result<Option<JsValue>, AnyError> + 'a;
+
+/// Entries by namespace
+type Namespaces = BTreeMap<String, Entries>;
+
+/// Values by key of one namespace, along with their size; stored as plain JSON object
+#[derive(Debug, Default)]
+struct Entries {
+	values: BTreeMap<String, JsValue>,
+
+	/// Sum of [`entry_size()`] of all values, kept up to date so that writes need not recount the namespace
+	bytes: usize,
+}
+
+impl Entries {
+	fn usage(&self) -> KvUsage {
+		KvUsage {
+			keys: self.values.len(),
+			bytes: self.bytes,
+		}
+	}
+
+	/// Replaces the value of `key`; `None` removes it
+	fn set(&mut self, key: &str, value: Option<JsValue>) {
+		if let Some(old) = self.values.remove(key) {
+			self.bytes -= entry_size(key, &old);
+		}
+		if let Some(value) = value {
+			self.bytes += entry_size(key, &value);
+			self.values.insert(key.to_string(), value);
+		}
+	}
+}
+
+impl Serialize for Entries {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.values.serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for Entries {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let values = BTreeMap::<String, JsValue>::deserialize(deserializer)?;
+		let bytes = values
+			.iter()
+			.map(|(key, value)| entry_size(key, value))
+			.sum();
+
+		Ok(Self { values, bytes })
+	}
+}
+
+/// Store keeping values in memory, e.g. for tests or scripts that only need to share state while the process runs.
+#[derive(Debug, Default)]
+pub struct MemoryKvStore {
+	namespaces: Mutex<Namespaces>,
+}
+
+impl MemoryKvStore {
+	/// Creates an empty store.
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl KvStore for MemoryKvStore {
+	fn get(&self, namespace: &str, key: &str) -> Result<Option<JsValue>, AnyError> {
+		Ok(get(&lock(&self.namespaces), namespace, key))
+	}
+
+	fn list(&self, namespace: &str, prefix: &str) -> Result<Vec<(String, JsValue)>, AnyError> {
+		Ok(list(&lock(&self.namespaces), namespace, prefix))
+	}
+
+	fn update(&self, namespace: &str, key: &str, f: &mut KvUpdateFn) -> Result<(), AnyError> {
+		update(&mut lock(&self.namespaces), namespace, key, f)?;
+		Ok(())
+	}
+}
+
+/// Store persisting all namespaces in a JSON file, which is rewritten on every change.
+///
+/// Suited for the modest amounts of state that plugins keep, such as counters and markers of their last run. The file
+/// must not be used by several stores at once, neither in this nor in another process.
+#[derive(Debug)]
+pub struct FileKvStore {
+	path: PathBuf,
+	namespaces: Mutex<Namespaces>,
+}
+
+impl FileKvStore {
+	/// Opens the store in the file at `path`, which is created on the first write if it does not exist.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, AnyError> {
+		let path = path.as_ref().to_path_buf();
+		let namespaces = match std::fs::read(&path) {
+			Ok(bytes) => serde_json::from_slice(&bytes)
+				.with_context(|| format!("Invalid key-value store {}", path.display()))?,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Namespaces::new(),
+			Err(e) => {
+				return Err(anyhow!(
+					"Cannot read key-value store {}: {e}",
+					path.display()
+				))
+			}
+		};
+
+		Ok(Self {
+			path,
+			namespaces: Mutex::new(namespaces),
+		})
+	}
+
+	/// Writes to a temporary file first and flushes it to disk before replacing the store, so that a crash cannot leave
+	/// a truncated store behind
+	fn save(&self, namespaces: &Namespaces) -> Result<(), AnyError> {
+		let mut temp = self.path.clone().into_os_string();
+		temp.push(".tmp");
+
+		let json = serde_json::to_vec(namespaces)?;
+		File::create(&temp)
+			.and_then(|mut file| {
+				file.write_all(&json)?;
+				file.sync_all()
+			})
+			.and_then(|_| std::fs::rename(&temp, &self.path))
+			.with_context(|| format!("Cannot write key-value store {}", self.path.display()))
+	}
+}
+
+impl KvStore for FileKvStore {
+	fn get(&self, namespace: &str, key: &str) -> Result<Option<JsValue>, AnyError> {
+		Ok(get(&lock(&self.namespaces), namespace, key))
+	}
+
+	fn list(&self, namespace: &str, prefix: &str) -> Result<Vec<(String, JsValue)>, AnyError> {
+		Ok(list(&lock(&self.namespaces), namespace, prefix))
+	}
+
+	fn update(&self, namespace: &str, key: &str, f: &mut KvUpdateFn) -> Result<(), AnyError> {
+		let mut namespaces = lock(&self.namespaces);
+		let previous = get(&namespaces, namespace, key);
+		if !update(&mut namespaces, namespace, key, f)? {
+			return Ok(());
+		}
+
+		// Keep memory and file consistent if the file cannot be written
+		if let Err(e) = self.save(&namespaces) {
+			let entries = namespaces.entry(namespace.to_string()).or_default();
+			entries.set(key, previous);
+			return Err(e);
+		}
+
+		Ok(())
+	}
+}
+
+fn lock(namespaces: &Mutex<Namespaces>) -> MutexGuard<Namespaces> {
+	// Each update is applied as a whole, so the data is consistent even if a thread panicked while holding the lock
+	namespaces.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn get(namespaces: &Namespaces, namespace: &str, key: &str) -> Option<JsValue> {
+	namespaces.get(namespace)?.values.get(key).cloned()
+}
+
+fn list(namespaces: &Namespaces, namespace: &str, prefix: &str) -> Vec<(String, JsValue)> {
+	let Some(entries) = namespaces.get(namespace) else {
+		return Vec::new();
+	};
+
+	entries
+		.values
+		.range(prefix.to_string()..)
+		.take_while(|(key, _)| key.starts_with(prefix))
+		.map(|(key, value)| (key.clone(), value.clone()))
+		.collect()
+}
+
+/// Applies `f` to the value of `key`; returns whether the value changed.
+fn update(
+	namespaces: &mut Namespaces,
+	namespace: &str,
+	key: &str,
+	f: &mut KvUpdateFn,
+) -> Result<bool, AnyError> {
+	let entries = namespaces.entry(namespace.to_string()).or_default();
+
+	let old = entries.values.get(key);
+	let new = f(old, entries.usage())?;
+	if new.as_ref() == old {
+		return Ok(false);
+	}
+
+	entries.set(key, new);
+	if entries.values.is_empty() {
+		namespaces.remove(namespace);
+	}
+
+	Ok(true)
+}
+
+fn entry_size(key: &str, value: &JsValue) -> usize {
+	key.len() + value.to_string().len()
+}
+
+fn host_kv(state: &mut OpState) -> Result<HostKv, AnyError> {
+	let kv = state
+		.try_borrow::<HostKv>()
+		.ok_or_else(|| {
+			anyhow!("host.kv is not enabled; build the script with ScriptBuilder::kv()")
+		})?
+		.clone();
+
+	state
+		.borrow_mut::<PermissionState>()
+		.check_host_functions("kv")?;
+	Ok(kv)
+}
+
+/// Ops for kv.js, registered with the crate's other ops
+pub(crate) fn ops() -> Vec<OpDecl> {
+	vec![
+		op_kv_get::decl(),
+		op_kv_set::decl(),
+		op_kv_delete::decl(),
+		op_kv_list::decl(),
+		op_kv_compare_and_set::decl(),
+	]
+}
+
+/// A value that may be `null`, wrapped to tell it apart from a missing one
+#[derive(Serialize, Deserialize)]
+struct Slot {
+	value: JsValue,
+}
+
+#[derive(Serialize)]
+struct KvEntry {
+	key: String,
+	value: JsValue,
+}
+
+#[op]
+fn op_kv_get(state: &mut OpState, key: String) -> Result<Option<Slot>, AnyError> {
+	let kv = host_kv(state)?;
+	let value = kv.store.get(&kv.namespace, &key)?;
+	Ok(value.map(|value| Slot { value }))
+}
+
+#[op]
+fn op_kv_set(state: &mut OpState, key: String, value: JsValue) -> Result<(), AnyError> {
+	host_kv(state)?.write(&key, Some(value), |_| true)?;
+	Ok(())
+}
+
+#[op]
+fn op_kv_delete(state: &mut OpState, key: String) -> Result<bool, AnyError> {
+	let mut existed = false;
+	host_kv(state)?.write(&key, None, |old| {
+		existed = old.is_some();
+		true
+	})?;
+	Ok(existed)
+}
+
+#[op]
+fn op_kv_list(state: &mut OpState, prefix: String) -> Result<Vec<KvEntry>, AnyError> {
+	let kv = host_kv(state)?;
+	let entries = kv.store.list(&kv.namespace, &prefix)?;
+	Ok(entries
+		.into_iter()
+		.map(|(key, value)| KvEntry { key, value })
+		.collect())
+}
+
+/// Writes `value` (`null` to delete) if the current value still is `expected` (`null` if missing). Returns whether it did.
+#[op]
+fn op_kv_compare_and_set(
+	state: &mut OpState,
+	key: String,
+	expected: Option<Slot>,
+	value: Option<Slot>,
+) -> Result<bool, AnyError> {
+	let expected = expected.map(|slot| slot.value);
+	host_kv(state)?.write(&key, value.map(|slot| slot.value), |old| {
+		old == expected.as_ref()
+	})
+}
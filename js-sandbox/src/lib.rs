@@ -152,9 +152,24 @@
 //! [serde_json]: https://docs.serde.rs/serde_json
 
 pub use call_args::CallArgs;
+pub use fetch::{Fetch, FetchFuture, FetchRequest, FetchResponse, FetchTransport};
+#[cfg(feature = "http")]
+pub use fetch::HttpTransport;
+pub use host_fs::HostFs;
 pub use js_sandbox_macros::js_api;
+pub use kv::{FileKvStore, HostKv, KvStore, KvUpdateFn, KvUsage, MemoryKvStore};
+pub use module_loader::{ImportMap, JailedFsModuleLoader, MemoryModuleLoader};
+pub use node::NodeCompat;
+pub use permissions::{Access, PermissionDenied, Permissions};
+pub use pool::{PoolMetrics, ScriptPool, ScriptPoolBuilder};
 pub use script::*;
+pub use script_builder::ScriptBuilder;
+pub use script_handle::ScriptHandle;
+pub use sql::{HostSql, SqlExecutor, SqlRow};
+#[cfg(feature = "sqlite")]
+pub use sql::SqliteExecutor;
 pub use util::eval_json;
+pub use virtual_fs::VirtualFs;
 
 /// Represents a value passed to or from JavaScript.
 ///
@@ -173,10 +188,31 @@ pub type AnyError = deno_core::error::AnyError;
 /// Wrapper type representing a result that can result in a JS runtime error
 pub type JsResult<T> = Result<T, JsError>;
 
+// the only base64 codec: atob()/btoa(), Buffer and base64 data: URLs all go through it
+mod base64;
 mod call_args;
+mod deterministic;
+mod fetch;
+mod host_fs;
 mod js_error;
+mod kv;
+mod node;
+mod permissions;
+mod pool;
 mod script;
+mod script_builder;
+mod script_handle;
+mod snapshot;
+mod source_map;
+mod sql;
+mod timers;
+#[cfg(feature = "typescript")]
+mod typescript;
 mod util;
+mod virtual_fs;
+#[cfg(feature = "web")]
+mod web;
 pub mod exposed_func;
 pub mod api;
+pub mod module_loader;
 pub mod run_time;
@@ -0,0 +1,235 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use deno_core::url::Url;
+use deno_core::{ModuleLoader, ModuleSourceFuture, ModuleSpecifier, ResolutionKind};
+use serde::Deserialize;
+
+use super::ModuleResolutionError;
+use crate::AnyError;
+
+/// Maps bare module specifiers such as `"@inoerp/sdk"` to module locations.
+///
+/// Follows the format of [import maps] in browsers and Deno:
+/// ```json
+/// {
+///   "imports": {
+///     "@inoerp/sdk": "./sdk/index.js",
+///     "@inoerp/sdk/": "./sdk/"
+///   },
+///   "scopes": {
+///     "./legacy/": { "@inoerp/sdk": "./sdk-v1/index.js" }
+///   }
+/// }
+/// ```
+///
+/// A key ending in `/` maps all specifiers starting with it. Mappings in `"scopes"` only apply to modules whose URL starts with
+/// the scope, and take precedence over the top-level `"imports"`.
+///
+/// Relative addresses and scopes are resolved against the base URL. Unless set with [`with_base_url()`](Self::with_base_url),
+/// this is the root of the module loader in use: the working directory for the default loader, or the root of a
+/// [`MemoryModuleLoader`](super::MemoryModuleLoader) or [`JailedFsModuleLoader`](super::JailedFsModuleLoader).
+/// Mapped specifiers are passed on to the module loader, so they are subject to the same restrictions as any other import.
+///
+/// [import maps]: https://github.com/WICG/import-maps
+#[derive(Clone, Debug, Default)]
+pub struct ImportMap {
+	imports: BTreeMap<String, String>,
+	scopes: BTreeMap<String, BTreeMap<String, String>>,
+	base_url: Option<Url>,
+}
+
+#[derive(Deserialize)]
+struct ImportMapJson {
+	#[serde(default)]
+	imports: BTreeMap<String, String>,
+	#[serde(default)]
+	scopes: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl ImportMap {
+	/// Parses an import map in JSON format.
+	pub fn from_json(json: &str) -> Result<Self, AnyError> {
+		let parsed: ImportMapJson = serde_json::from_str(json)?;
+
+		Ok(Self {
+			imports: parsed.imports,
+			scopes: parsed.scopes,
+			base_url: None,
+		})
+	}
+
+	/// Adds a mapping to the top-level `"imports"`.
+	pub fn insert(&mut self, specifier: &str, address: &str) {
+		self.imports
+			.insert(specifier.to_string(), address.to_string());
+	}
+
+	/// Adds a mapping that only applies to modules whose URL starts with `scope`.
+	pub fn insert_scoped(&mut self, scope: &str, specifier: &str, address: &str) {
+		self.scopes
+			.entry(scope.to_string())
+			.or_default()
+			.insert(specifier.to_string(), address.to_string());
+	}
+
+	/// Resolves relative addresses and scopes against `base_url` instead of the module loader's root.
+	pub fn with_base_url(mut self, base_url: &str) -> Result<Self, AnyError> {
+		self.base_url = Some(Url::parse(base_url)?);
+		Ok(self)
+	}
+
+	pub(crate) fn base_url(&self) -> Option<&Url> {
+		self.base_url.as_ref()
+	}
+}
+
+/// Specifier side of a mapping, normalized for lookup
+enum MapKey {
+	Bare(String),
+	Url(String),
+}
+
+struct Mapping {
+	key: MapKey,
+	address: Url,
+}
+
+/// Module loader applying an import map before delegating to another loader.
+pub(crate) struct ImportMapLoader {
+	inner: Rc<dyn ModuleLoader>,
+	base_url: Url,
+	/// Scoped mappings, most specific scope first
+	scopes: Vec<(String, Vec<Mapping>)>,
+	imports: Vec<Mapping>,
+}
+
+impl ImportMapLoader {
+	pub fn new(
+		inner: Rc<dyn ModuleLoader>,
+		map: &ImportMap,
+		base_url: Url,
+	) -> Result<Self, AnyError> {
+		let imports = Self::parse_mappings(&map.imports, &base_url)?;
+
+		let mut scopes = Vec::new();
+		for (scope, mappings) in map.scopes.iter() {
+			let scope_url = base_url.join(scope)?;
+			scopes.push((
+				scope_url.to_string(),
+				Self::parse_mappings(mappings, &base_url)?,
+			));
+		}
+		// Stable sort, so scopes of equal length keep the order of their keys
+		scopes.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+
+		Ok(Self {
+			inner,
+			base_url,
+			scopes,
+			imports,
+		})
+	}
+
+	fn parse_mappings(
+		mappings: &BTreeMap<String, String>,
+		base_url: &Url,
+	) -> Result<Vec<Mapping>, AnyError> {
+		let mut result = Vec::new();
+		for (specifier, address) in mappings.iter() {
+			let key = if is_url_like(specifier) {
+				MapKey::Url(base_url.join(specifier)?.to_string())
+			} else {
+				MapKey::Bare(specifier.clone())
+			};
+
+			let address = base_url.join(address)?;
+			if specifier.ends_with('/') && !address.as_str().ends_with('/') {
+				return Err(ModuleResolutionError::new(
+					specifier.as_str(),
+					format!("import map address \"{address}\" must end with '/', like its key"),
+				)
+				.into());
+			}
+
+			result.push(Mapping { key, address });
+		}
+
+		// Longest keys first, so that the most specific prefix wins; ties keep the order of the keys
+		result.sort_by_key(|m| match &m.key {
+			MapKey::Bare(s) | MapKey::Url(s) => std::cmp::Reverse(s.len()),
+		});
+		Ok(result)
+	}
+
+	/// Returns the mapped URL for `specifier`, or `None` if no mapping applies.
+	fn apply(&self, specifier: &str, referrer: &str) -> Result<Option<Url>, AnyError> {
+		let specifier_url = if is_url_like(specifier) {
+			let base = Url::parse(referrer).unwrap_or_else(|_| self.base_url.clone());
+			Some(base.join(specifier)?.to_string())
+		} else {
+			None
+		};
+
+		let scoped = self
+			.scopes
+			.iter()
+			.filter(|(scope, _)| referrer.starts_with(scope.as_str()))
+			.map(|(_, mappings)| mappings);
+
+		for mappings in scoped.chain(std::iter::once(&self.imports)) {
+			for mapping in mappings {
+				let (key, candidate) = match (&mapping.key, &specifier_url) {
+					(MapKey::Bare(key), None) => (key, specifier),
+					(MapKey::Url(key), Some(url)) => (key, url.as_str()),
+					_ => continue,
+				};
+
+				if candidate == key {
+					return Ok(Some(mapping.address.clone()));
+				}
+				if key.ends_with('/') {
+					if let Some(rest) = candidate.strip_prefix(key.as_str()) {
+						return Ok(Some(mapping.address.join(rest)?));
+					}
+				}
+			}
+		}
+
+		Ok(None)
+	}
+}
+
+impl ModuleLoader for ImportMapLoader {
+	fn resolve(
+		&self,
+		specifier: &str,
+		referrer: &str,
+		kind: ResolutionKind,
+	) -> Result<ModuleSpecifier, AnyError> {
+		match self.apply(specifier, referrer)? {
+			Some(mapped) => self.inner.resolve(mapped.as_str(), referrer, kind),
+			None => self.inner.resolve(specifier, referrer, kind),
+		}
+	}
+
+	fn load(
+		&self,
+		module_specifier: &ModuleSpecifier,
+		maybe_referrer: Option<&ModuleSpecifier>,
+		is_dyn_import: bool,
+	) -> Pin<Box<ModuleSourceFuture>> {
+		self.inner
+			.load(module_specifier, maybe_referrer, is_dyn_import)
+	}
+}
+
+fn is_url_like(specifier: &str) -> bool {
+	specifier.starts_with("./")
+		|| specifier.starts_with("../")
+		|| specifier.starts_with('/')
+		|| Url::parse(specifier).is_ok()
+}
@@ -0,0 +1,299 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use deno_core::futures::future;
+use deno_core::url::Url;
+use deno_core::{
+	ModuleLoader, ModuleSource, ModuleSourceFuture, ModuleSpecifier, ModuleType, ResolutionKind,
+};
+
+use super::ModuleResolutionError;
+use crate::{base64, AnyError};
+
+/// Loads ES modules from a single directory on disk, and nothing outside of it.
+///
+/// Every `file:` specifier must point into the root directory, after resolving `..` segments and symlinks. Anything else is
+/// rejected with a [`ModuleResolutionError`]:
+/// * Paths escaping the root, such as `../../etc/passwd`, `/etc/passwd` or a symlink to a directory outside.
+/// * `data:` URLs, unless enabled with [`allow_data_urls()`](Self::allow_data_urls).
+/// * Remote URLs (`https:` etc.), unless they start with a prefix registered through [`allow_remote()`](Self::allow_remote).
+///   This loader does not access the network itself; allowed remote modules are delegated to the loader set with
+///   [`remote_loader()`](Self::remote_loader).
+///
+/// The main module is given as path relative to the root (e.g. `"main.js"`).
+///
+/// ```rust,no_run
+/// use js_sandbox::{JailedFsModuleLoader, Script, AnyError};
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let loader = JailedFsModuleLoader::new("plugins/tenant-42")?;
+///
+/// 	let mut script = Script::builder()
+/// 		.module_loader(loader)
+/// 		.build_from_module("main.js")?;
+///
+/// 	let _: () = script.call("run", ())?;
+/// 	Ok(())
+/// }
+/// ```
+pub struct JailedFsModuleLoader {
+	/// Canonical path of the root directory
+	root: PathBuf,
+	root_url: Url,
+	allow_data_urls: bool,
+	remote_prefixes: Vec<String>,
+	remote_loader: Option<Rc<dyn ModuleLoader>>,
+}
+
+impl JailedFsModuleLoader {
+	/// Creates a loader confined to the directory `root`.
+	///
+	/// Fails if `root` does not exist or is not a directory.
+	pub fn new(root: impl AsRef<Path>) -> Result<Self, AnyError> {
+		let root = root.as_ref().canonicalize()?;
+		if !root.is_dir() {
+			return Err(ModuleResolutionError::new(
+				root.to_string_lossy(),
+				"module root is not a directory",
+			)
+			.into());
+		}
+
+		let root_url = Url::from_directory_path(&root).map_err(|_| {
+			ModuleResolutionError::new(root.to_string_lossy(), "module root is not a valid URL")
+		})?;
+
+		Ok(Self {
+			root,
+			root_url,
+			allow_data_urls: false,
+			remote_prefixes: Vec::new(),
+			remote_loader: None,
+		})
+	}
+
+	/// Accepts `data:` URLs as module specifiers, e.g. `data:text/javascript,export default 42;`. Disabled by default.
+	pub fn allow_data_urls(mut self, allow: bool) -> Self {
+		self.allow_data_urls = allow;
+		self
+	}
+
+	/// Accepts remote specifiers starting with `url_prefix`, e.g. `"https://cdn.example.com/sdk/"`.
+	///
+	/// Can be called multiple times. Allowed remote modules are loaded by the [`remote_loader()`](Self::remote_loader).
+	pub fn allow_remote(mut self, url_prefix: &str) -> Self {
+		self.remote_prefixes.push(url_prefix.to_string());
+		self
+	}
+
+	/// Loads allowed remote modules through `loader`. Without one, imports of remote modules fail at load time.
+	pub fn remote_loader<L>(mut self, loader: L) -> Self
+	where
+		L: ModuleLoader + 'static,
+	{
+		self.remote_loader = Some(Rc::new(loader));
+		self
+	}
+
+	/// The canonical path of the root directory.
+	pub fn root(&self) -> &Path {
+		&self.root
+	}
+
+	/// Accepts or rejects an already resolved URL.
+	fn check_url(&self, url: Url) -> Result<ModuleSpecifier, AnyError> {
+		let scheme = url.scheme().to_string();
+		let reason = match scheme.as_str() {
+			"file" => {
+				self.jailed_path(&url)?;
+				return Ok(url);
+			}
+			"data" if self.allow_data_urls => return Ok(url),
+			"data" => "data: URLs are not allowed".to_string(),
+			_ if self.is_allowed_remote(&url) => return Ok(url),
+			scheme => format!("{scheme}: modules are not in the import allowlist"),
+		};
+
+		Err(ModuleResolutionError::new(url.as_str(), reason).into())
+	}
+
+	fn is_allowed_remote(&self, url: &Url) -> bool {
+		self.remote_prefixes
+			.iter()
+			.any(|prefix| url.as_str().starts_with(prefix.as_str()))
+	}
+
+	/// Returns the file path for `url`, if it lies within the root directory.
+	///
+	/// Existing files are checked with symlinks resolved. Paths that do not exist (yet) are checked lexically;
+	/// they are checked again when loaded.
+	fn jailed_path(&self, url: &Url) -> Result<PathBuf, AnyError> {
+		let path = url
+			.to_file_path()
+			.map_err(|_| ModuleResolutionError::new(url.as_str(), "not a valid file path"))?;
+
+		let real_path = match path_within(&path, &self.root) {
+			Ok(real_path) => real_path,
+			Err(_) => path.starts_with(&self.root).then_some(path),
+		};
+
+		real_path.ok_or_else(|| {
+			ModuleResolutionError::new(
+				url.as_str(),
+				format!("path is outside of the module root {}", self.root.display()),
+			)
+			.into()
+		})
+	}
+
+	fn load_file(&self, specifier: &ModuleSpecifier) -> Result<ModuleSource, AnyError> {
+		let path = self.jailed_path(specifier)?;
+		let code = std::fs::read_to_string(&path).map_err(|e| {
+			ModuleResolutionError::new(specifier.as_str(), format!("cannot read file: {e}"))
+		})?;
+
+		let module_type = match path.extension() {
+			Some(ext) if ext.eq_ignore_ascii_case("json") => ModuleType::Json,
+			_ => ModuleType::JavaScript,
+		};
+
+		Ok(ModuleSource::new(module_type, code.into(), specifier))
+	}
+
+	fn load_data_url(&self, specifier: &ModuleSpecifier) -> Result<ModuleSource, AnyError> {
+		let (media_type, code) = decode_data_url(specifier.as_str())
+			.ok_or_else(|| ModuleResolutionError::new(specifier.as_str(), "malformed data: URL"))?;
+
+		let module_type = if media_type.ends_with("json") {
+			ModuleType::Json
+		} else {
+			ModuleType::JavaScript
+		};
+
+		Ok(ModuleSource::new(module_type, code.into(), specifier))
+	}
+}
+
+impl ModuleLoader for JailedFsModuleLoader {
+	fn resolve(
+		&self,
+		specifier: &str,
+		referrer: &str,
+		kind: ResolutionKind,
+	) -> Result<ModuleSpecifier, AnyError> {
+		if let Ok(url) = Url::parse(specifier) {
+			return self.check_url(url);
+		}
+
+		let is_relative = specifier.starts_with("./")
+			|| specifier.starts_with("../")
+			|| specifier.starts_with('/');
+
+		let url = match Url::parse(referrer) {
+			// Relative imports from a data: URL have no meaningful base
+			Ok(base) if base.scheme() == "data" => {
+				return Err(ModuleResolutionError::new(
+					specifier,
+					"relative imports are not possible from data: URLs",
+				)
+				.into());
+			}
+			Ok(base) if is_relative => base.join(specifier)?,
+
+			// Main module, or import from outside the loader: relative to root
+			_ if is_relative || matches!(kind, ResolutionKind::MainModule) => {
+				self.root_url.join(specifier)?
+			}
+			_ => {
+				return Err(ModuleResolutionError::new(
+					specifier,
+					format!("bare specifier not prefixed with / or ./ or ../ (imported from \"{referrer}\")"),
+				)
+				.into());
+			}
+		};
+
+		self.check_url(url)
+	}
+
+	fn load(
+		&self,
+		module_specifier: &ModuleSpecifier,
+		maybe_referrer: Option<&ModuleSpecifier>,
+		is_dyn_import: bool,
+	) -> Pin<Box<ModuleSourceFuture>> {
+		let result = match module_specifier.scheme() {
+			"file" => self.load_file(module_specifier),
+			"data" if self.allow_data_urls => self.load_data_url(module_specifier),
+			_ if self.is_allowed_remote(module_specifier) => match &self.remote_loader {
+				Some(loader) => {
+					return loader.load(module_specifier, maybe_referrer, is_dyn_import)
+				}
+				None => Err(ModuleResolutionError::new(
+					module_specifier.as_str(),
+					"remote module is allowed, but no remote loader is configured",
+				)
+				.into()),
+			},
+			_ => Err(ModuleResolutionError::new(
+				module_specifier.as_str(),
+				"not in the import allowlist",
+			)
+			.into()),
+		};
+
+		Box::pin(future::ready(result))
+	}
+}
+
+/// Resolves symlinks in `path` and returns the result if it lies within `root`, which must be canonical.
+///
+/// `Ok(None)` if the path leads outside of `root`; fails if the path cannot be resolved, e.g. because it does not exist.
+pub(crate) fn path_within(path: &Path, root: &Path) -> std::io::Result<Option<PathBuf>> {
+	let real_path = path.canonicalize()?;
+	Ok(real_path.starts_with(root).then_some(real_path))
+}
+
+/// Splits a `data:` URL into media type and decoded content. Supports percent-encoded and base64 payloads.
+pub(crate) fn decode_data_url(url: &str) -> Option<(String, String)> {
+	let rest = url.strip_prefix("data:")?;
+	let (header, payload) = rest.split_once(',')?;
+
+	let (media_type, is_base64) = match header.strip_suffix(";base64") {
+		Some(media_type) => (media_type, true),
+		None => (header, false),
+	};
+
+	let bytes = if is_base64 {
+		base64::decode(&percent_decode(payload)?)?
+	} else {
+		percent_decode(payload)?
+	};
+
+	let media_type = media_type.split(';').next().unwrap_or_default();
+	Some((
+		media_type.to_ascii_lowercase(),
+		String::from_utf8(bytes).ok()?,
+	))
+}
+
+/// Decodes `%XX` escapes, e.g. of a URL path; `None` if an escape is malformed.
+pub(crate) fn percent_decode(input: &str) -> Option<Vec<u8>> {
+	let mut bytes = Vec::with_capacity(input.len());
+	let mut iter = input.bytes();
+
+	while let Some(b) = iter.next() {
+		if b == b'%' {
+			let hex = [iter.next()?, iter.next()?];
+			let hex = std::str::from_utf8(&hex).ok()?;
+			bytes.push(u8::from_str_radix(hex, 16).ok()?);
+		} else {
+			bytes.push(b);
+		}
+	}
+
+	Some(bytes)
+}
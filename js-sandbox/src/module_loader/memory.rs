@@ -0,0 +1,160 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+
+use deno_core::futures::future;
+use deno_core::url::Url;
+use deno_core::{
+	ModuleLoader, ModuleSource, ModuleSourceFuture, ModuleSpecifier, ModuleType, ResolutionKind,
+};
+
+use super::ModuleResolutionError;
+use crate::AnyError;
+
+/// Serves ES modules from memory instead of the file system.
+///
+/// Each module is registered under a path like `"main.js"` or `"lib/util.js"`, and is identified by the specifier
+/// `memory:///main.js` or `memory:///lib/util.js` respectively. Imports between modules are resolved relative to the
+/// importing module, the same way as for files. The main module can be given as plain path (`"main.js"`).
+///
+/// Sources can come from anywhere, e.g. a database. To embed a plugin library into the binary, combine this loader with
+/// [`include_str!`]:
+///
+/// ```rust
+/// # macro_rules! include_str { ( $($tt:tt)* ) => { "" } }
+/// use js_sandbox::MemoryModuleLoader;
+///
+/// let loader: MemoryModuleLoader = [
+/// 	("main.js", include_str!("plugins/main.js")),
+/// 	("lib/money.js", include_str!("plugins/lib/money.js")),
+/// ]
+/// .into_iter()
+/// .collect();
+/// ```
+#[derive(Default)]
+pub struct MemoryModuleLoader {
+	/// Maps the URL path (e.g. `/lib/util.js`) to the module's source code
+	modules: HashMap<String, String>,
+}
+
+impl MemoryModuleLoader {
+	/// URL scheme of the specifiers served by this loader.
+	pub const SCHEME: &'static str = "memory";
+
+	/// Creates a loader serving the given modules, keyed by path.
+	pub fn new(modules: HashMap<String, String>) -> Self {
+		modules.into_iter().collect()
+	}
+
+	/// Adds a module at `path`, replacing any previous module there.
+	pub fn insert(&mut self, path: &str, code: impl Into<String>) {
+		let specifier = Self::specifier(path);
+		self.modules
+			.insert(specifier.path().to_string(), code.into());
+	}
+
+	/// Checks whether a module is registered at `path`.
+	pub fn contains(&self, path: &str) -> bool {
+		self.modules.contains_key(Self::specifier(path).path())
+	}
+
+	/// Returns the specifier of the module at `path`, e.g. `memory:///lib/util.js` for `"lib/util.js"`.
+	///
+	/// `.` and `..` segments are resolved; a path can never point above the root.
+	pub fn specifier(path: &str) -> ModuleSpecifier {
+		let mut segments: Vec<&str> = Vec::new();
+		for segment in path.split('/') {
+			match segment {
+				"" | "." => {}
+				".." => {
+					segments.pop();
+				}
+				_ => segments.push(segment),
+			}
+		}
+
+		let url = format!("{}:///{}", Self::SCHEME, segments.join("/"));
+		Url::parse(&url).expect("normalized path forms a valid URL")
+	}
+
+	fn load_source(&self, specifier: &ModuleSpecifier) -> Result<ModuleSource, AnyError> {
+		if specifier.scheme() != Self::SCHEME {
+			return Err(ModuleResolutionError::new(
+				specifier.as_str(),
+				"not served by the in-memory module loader",
+			)
+			.into());
+		}
+
+		let code = self.modules.get(specifier.path()).ok_or_else(|| {
+			ModuleResolutionError::new(specifier.as_str(), "no module registered at this path")
+		})?;
+
+		let module_type = if specifier.path().ends_with(".json") {
+			ModuleType::Json
+		} else {
+			ModuleType::JavaScript
+		};
+
+		Ok(ModuleSource::new(
+			module_type,
+			code.clone().into(),
+			specifier,
+		))
+	}
+}
+
+impl<K, V> FromIterator<(K, V)> for MemoryModuleLoader
+where
+	K: AsRef<str>,
+	V: Into<String>,
+{
+	fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+		let mut loader = Self::default();
+		for (path, code) in iter {
+			loader.insert(path.as_ref(), code);
+		}
+		loader
+	}
+}
+
+impl ModuleLoader for MemoryModuleLoader {
+	fn resolve(
+		&self,
+		specifier: &str,
+		referrer: &str,
+		kind: ResolutionKind,
+	) -> Result<ModuleSpecifier, AnyError> {
+		// Absolute URLs are taken as-is; load() rejects those of other schemes
+		if let Ok(url) = Url::parse(specifier) {
+			return Ok(url);
+		}
+
+		let is_relative = specifier.starts_with("./")
+			|| specifier.starts_with("../")
+			|| specifier.starts_with('/');
+
+		match Url::parse(referrer) {
+			Ok(base) if is_relative && base.scheme() == Self::SCHEME => Ok(base.join(specifier)?),
+			_ if is_relative || matches!(kind, ResolutionKind::MainModule) => {
+				Ok(Self::specifier(specifier))
+			}
+			_ => Err(ModuleResolutionError::new(
+				specifier,
+				format!("bare specifier not prefixed with / or ./ or ../ (imported from \"{referrer}\")"),
+			)
+			.into()),
+		}
+	}
+
+	fn load(
+		&self,
+		module_specifier: &ModuleSpecifier,
+		_maybe_referrer: Option<&ModuleSpecifier>,
+		_is_dyn_import: bool,
+	) -> Pin<Box<ModuleSourceFuture>> {
+		let result = self.load_source(module_specifier);
+		Box::pin(future::ready(result))
+	}
+}
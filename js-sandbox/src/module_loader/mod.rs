@@ -0,0 +1,74 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+//! Module loaders that can be passed to [`ScriptBuilder::module_loader()`](crate::ScriptBuilder::module_loader).
+//!
+//! Any type implementing Deno's [`ModuleLoader`] trait can be used; the types in here cover the common cases of a sandbox.
+//!
+//! Besides JavaScript, modules can import data files:
+//! * JSON files, whichever loader is in use: `import config from "./config.json" with { type: "json" };`
+//! * Any text file as string, with [`ScriptBuilder::text_imports()`](crate::ScriptBuilder::text_imports) and a custom
+//!   loader: `import template from "./invoice.html" with { type: "text" };`
+//!
+//! Both are resolved and loaded like any other module, so the loader's restrictions (e.g. the root of a
+//! [`JailedFsModuleLoader`]) apply to them as well.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+
+pub use deno_core::{ModuleLoader, ModuleSource, ModuleSpecifier, ModuleType, ResolutionKind};
+
+pub use import_map::ImportMap;
+pub use jailed::JailedFsModuleLoader;
+pub use memory::MemoryModuleLoader;
+
+pub(crate) use import_map::ImportMapLoader;
+pub(crate) use jailed::{decode_data_url, path_within};
+pub(crate) use raw::{is_raw_import, RawModuleLoader};
+pub(crate) use virtual_fs::VirtualFsModuleLoader;
+
+mod import_map;
+mod jailed;
+mod memory;
+mod raw;
+mod virtual_fs;
+
+/// A module specifier that a loader refused or failed to resolve.
+///
+/// Loaders in this crate report all rejected imports with this type. When loading a script fails because of it, the error
+/// surfaces as [`JsError::ModuleResolution`](crate::JsError::ModuleResolution).
+#[derive(Debug)]
+pub struct ModuleResolutionError {
+	specifier: String,
+	reason: String,
+}
+
+impl ModuleResolutionError {
+	pub fn new(specifier: impl Into<String>, reason: impl Into<String>) -> Self {
+		Self {
+			specifier: specifier.into(),
+			reason: reason.into(),
+		}
+	}
+
+	/// The specifier as written in the import, or the resolved URL if the failure occurred while loading.
+	pub fn specifier(&self) -> &str {
+		&self.specifier
+	}
+
+	/// Why the specifier was rejected.
+	pub fn reason(&self) -> &str {
+		&self.reason
+	}
+}
+
+impl Error for ModuleResolutionError {}
+
+impl Display for ModuleResolutionError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"Cannot resolve module \"{}\": {}",
+			self.specifier, self.reason
+		)
+	}
+}
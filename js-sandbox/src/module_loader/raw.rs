@@ -0,0 +1,280 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use deno_core::futures::FutureExt;
+use deno_core::{
+	ModuleLoader, ModuleSource, ModuleSourceFuture, ModuleSpecifier, ModuleType, ResolutionKind,
+};
+
+use super::ModuleResolutionError;
+use crate::AnyError;
+
+/// Query that marks an import as raw text; text imports are rewritten to it before Deno sees them.
+const RAW_QUERY: &str = "raw";
+
+/// Whether `specifier` imports the module's source as text, rather than evaluating it.
+pub(crate) fn is_raw_import(specifier: &ModuleSpecifier) -> bool {
+	specifier.query().map_or(false, |query| {
+		query.split(['=', '&']).next() == Some(RAW_QUERY)
+	})
+}
+
+/// Module loader for text imports: `import template from "./invoice.html" with { type: "text" }` and
+/// `import("./invoice.html", { with: { type: "text" } })` yield a module whose default export is the file content.
+///
+/// Deno only accepts `"json"` as import type, so the loaded modules are rewritten to mark text imports with a
+/// `?raw=<token>` query instead, the token being random per loader. Scripts cannot use the query themselves: any other
+/// `?raw` query is rejected. The content itself is still resolved and loaded by the inner loader, which applies its
+/// rules regardless of the query.
+pub(crate) struct RawModuleLoader {
+	inner: Rc<dyn ModuleLoader>,
+
+	/// `raw=<token>`, the query of rewritten text imports
+	query: String,
+}
+
+impl RawModuleLoader {
+	pub fn new(inner: Rc<dyn ModuleLoader>) -> Self {
+		// Short enough to fit in place of the shortest import attributes, see rewrite_text_imports()
+		let token = RandomState::new().build_hasher().finish() & 0xff_ffff;
+
+		Self {
+			inner,
+			query: format!("{RAW_QUERY}={token:06x}"),
+		}
+	}
+}
+
+impl ModuleLoader for RawModuleLoader {
+	fn resolve(
+		&self,
+		specifier: &str,
+		referrer: &str,
+		kind: ResolutionKind,
+	) -> Result<ModuleSpecifier, AnyError> {
+		let resolved = self.inner.resolve(specifier, referrer, kind)?;
+		if is_raw_import(&resolved) && resolved.query() != Some(self.query.as_str()) {
+			return Err(ModuleResolutionError::new(
+				specifier,
+				"the query \"?raw\" is reserved; import text with { type: \"text\" } instead",
+			)
+			.into());
+		}
+
+		Ok(resolved)
+	}
+
+	fn load(
+		&self,
+		module_specifier: &ModuleSpecifier,
+		maybe_referrer: Option<&ModuleSpecifier>,
+		is_dyn_import: bool,
+	) -> Pin<Box<ModuleSourceFuture>> {
+		let future = self
+			.inner
+			.load(module_specifier, maybe_referrer, is_dyn_import);
+
+		let specifier = module_specifier.clone();
+		let query = self.query.clone();
+		async move {
+			let mut source = future.await?;
+
+			if specifier.query() == Some(query.as_str()) {
+				let text = serde_json::to_string(source.code.as_str())?;
+				return Ok(ModuleSource::new(
+					ModuleType::JavaScript,
+					format!("export default {text};").into(),
+					&specifier,
+				));
+			}
+
+			if source.module_type == ModuleType::JavaScript {
+				if let Some(code) = rewrite_text_imports(source.code.as_str(), &query) {
+					source.code = code.into();
+				}
+			}
+			Ok(source)
+		}
+		.boxed_local()
+	}
+}
+
+/// Replaces the text imports in `code` with imports marked by `query`; `None` if there are none.
+///
+/// Only string literals following `from`, `import` or `import(` are taken as specifiers, skipping comments and other
+/// literals, and only if a `type: "text"` attribute follows. Specifiers that already have a query are left alone, so Deno
+/// rejects them as usual. The attributes are blanked out rather than removed, keeping the line and column of all code
+/// after them.
+fn rewrite_text_imports(code: &str, query: &str) -> Option<String> {
+	let bytes = code.as_bytes();
+	let mut result = String::new();
+	let mut copied = 0;
+	let mut pos = 0;
+
+	while pos < bytes.len() {
+		let rest = &bytes[pos..];
+		if rest.starts_with(b"//") {
+			pos += rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+			continue;
+		}
+		if rest.starts_with(b"/*") {
+			pos += rest[2..]
+				.windows(2)
+				.position(|end| end == b"*/")
+				.map_or(rest.len(), |len| len + 4);
+			continue;
+		}
+
+		let open = pos;
+		let quote = match bytes[open] {
+			quote @ (b'"' | b'\'' | b'`') => quote,
+			_ => {
+				pos += 1;
+				continue;
+			}
+		};
+		let Some(close) = literal_end(bytes, open) else {
+			pos += 1;
+			continue;
+		};
+		pos = close + 1;
+
+		let specifier = &code[open + 1..close];
+		if quote == b'`' || specifier.contains(['?', '#', '\\']) || !follows_import(&code[..open]) {
+			continue;
+		}
+
+		if let Some(len) = text_attributes(&code[close + 1..]) {
+			let attributes = &code[close + 1..close + 1 + len];
+			let mut blank: String = attributes
+				.chars()
+				.map(|c| if c == '\r' || c == '\n' { c } else { ' ' })
+				.collect();
+			let first_line = blank.find(['\r', '\n']).unwrap_or(blank.len());
+			blank.replace_range(..first_line.min(query.len() + 1), "");
+
+			let quote = quote as char;
+			result.push_str(&code[copied..open]);
+			result.push_str(&format!("{quote}{specifier}?{query}{quote}{blank}"));
+			copied = close + 1 + len;
+			pos = copied;
+		}
+	}
+
+	if result.is_empty() {
+		return None;
+	}
+	result.push_str(&code[copied..]);
+	Some(result)
+}
+
+/// Position of the quote closing the literal opened at `open`; `None` if it is unterminated. Strings end at a line
+/// break, template literals do not.
+fn literal_end(bytes: &[u8], open: usize) -> Option<usize> {
+	let quote = bytes[open];
+	let mut pos = open + 1;
+
+	while let Some(&b) = bytes.get(pos) {
+		match b {
+			b'\\' => pos += 2,
+			b'\n' if quote != b'`' => return None,
+			_ if b == quote => return Some(pos),
+			_ => pos += 1,
+		}
+	}
+	None
+}
+
+/// Whether `code` ends with a keyword after which a module specifier can follow
+fn follows_import(code: &str) -> bool {
+	let code = code.trim_end();
+	match code.strip_suffix('(') {
+		Some(call) => ends_with_word(call.trim_end(), "import"),
+		None => ends_with_word(code, "from") || ends_with_word(code, "import"),
+	}
+}
+
+fn ends_with_word(code: &str, word: &str) -> bool {
+	code.strip_suffix(word).map_or(false, |before| {
+		!before.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$' || c == '.')
+	})
+}
+
+/// Length of the import attributes at the start of `code` if they are `with { type: "text" }` (static imports) or
+/// `, { with: { type: "text" } }` (dynamic imports).
+fn text_attributes(code: &str) -> Option<usize> {
+	let mut cursor = Cursor { code, pos: 0 };
+
+	let matched = if cursor.word("with") || cursor.word("assert") {
+		cursor.text_type()
+	} else {
+		cursor.token(",")
+			&& cursor.token("{")
+			&& (cursor.key("with") || cursor.key("assert"))
+			&& cursor.token(":")
+			&& cursor.text_type()
+			&& cursor.optional(",")
+			&& cursor.token("}")
+	};
+
+	matched.then_some(cursor.pos)
+}
+
+/// Matches tokens of import attributes, skipping whitespace before each
+struct Cursor<'a> {
+	code: &'a str,
+	pos: usize,
+}
+
+impl Cursor<'_> {
+	fn token(&mut self, token: &str) -> bool {
+		let rest = &self.code[self.pos..];
+		let trimmed = rest.trim_start();
+		if !trimmed.starts_with(token) {
+			return false;
+		}
+
+		self.pos += rest.len() - trimmed.len() + token.len();
+		true
+	}
+
+	/// Always matches, whether `token` is present or not
+	fn optional(&mut self, token: &str) -> bool {
+		self.token(token);
+		true
+	}
+
+	/// A keyword, not followed by further identifier characters
+	fn word(&mut self, word: &str) -> bool {
+		let pos = self.pos;
+		let at_boundary = |cursor: &Self| {
+			!cursor.code[cursor.pos..]
+				.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$')
+		};
+
+		if self.token(word) && at_boundary(self) {
+			return true;
+		}
+		self.pos = pos;
+		false
+	}
+
+	/// An object key, bare or quoted
+	fn key(&mut self, key: &str) -> bool {
+		self.word(key) || self.token(&format!("\"{key}\"")) || self.token(&format!("'{key}'"))
+	}
+
+	/// `{ type: "text" }`
+	fn text_type(&mut self) -> bool {
+		self.token("{")
+			&& self.key("type")
+			&& self.token(":")
+			&& (self.token("\"text\"") || self.token("'text'"))
+			&& self.optional(",")
+			&& self.token("}")
+	}
+}
@@ -0,0 +1,88 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::pin::Pin;
+use std::rc::Rc;
+
+use deno_core::futures::future;
+use deno_core::url::Url;
+use deno_core::{
+	ModuleLoader, ModuleSource, ModuleSourceFuture, ModuleSpecifier, ModuleType, ResolutionKind,
+};
+
+use super::jailed::percent_decode;
+use super::ModuleResolutionError;
+use crate::{AnyError, VirtualFs};
+
+/// Module loader serving `vfs:` URLs from a [`VirtualFs`], and delegating everything else to the inner loader.
+pub(crate) struct VirtualFsModuleLoader {
+	inner: Rc<dyn ModuleLoader>,
+	vfs: VirtualFs,
+}
+
+impl VirtualFsModuleLoader {
+	pub const SCHEME: &'static str = "vfs";
+
+	pub fn new(inner: Rc<dyn ModuleLoader>, vfs: VirtualFs) -> Self {
+		Self { inner, vfs }
+	}
+
+	fn load_source(&self, specifier: &ModuleSpecifier) -> Result<ModuleSource, AnyError> {
+		let path = percent_decode(specifier.path())
+			.and_then(|bytes| String::from_utf8(bytes).ok())
+			.ok_or_else(|| ModuleResolutionError::new(specifier.as_str(), "malformed path"))?;
+		let bytes = self.vfs.get(&path).ok_or_else(|| {
+			ModuleResolutionError::new(specifier.as_str(), "no file in the virtual file system")
+		})?;
+		let code = String::from_utf8(bytes).map_err(|_| {
+			ModuleResolutionError::new(specifier.as_str(), "module is not valid UTF-8")
+		})?;
+
+		let module_type = if path.ends_with(".json") {
+			ModuleType::Json
+		} else {
+			ModuleType::JavaScript
+		};
+
+		Ok(ModuleSource::new(module_type, code.into(), specifier))
+	}
+}
+
+impl ModuleLoader for VirtualFsModuleLoader {
+	fn resolve(
+		&self,
+		specifier: &str,
+		referrer: &str,
+		kind: ResolutionKind,
+	) -> Result<ModuleSpecifier, AnyError> {
+		// Handled here, as the inner loader may reject unknown schemes
+		if let Ok(url) = Url::parse(specifier) {
+			if url.scheme() == Self::SCHEME {
+				return Ok(url);
+			}
+		}
+
+		let is_relative = specifier.starts_with("./")
+			|| specifier.starts_with("../")
+			|| specifier.starts_with('/');
+
+		match Url::parse(referrer) {
+			Ok(base) if is_relative && base.scheme() == Self::SCHEME => Ok(base.join(specifier)?),
+			_ => self.inner.resolve(specifier, referrer, kind),
+		}
+	}
+
+	fn load(
+		&self,
+		module_specifier: &ModuleSpecifier,
+		maybe_referrer: Option<&ModuleSpecifier>,
+		is_dyn_import: bool,
+	) -> Pin<Box<ModuleSourceFuture>> {
+		if module_specifier.scheme() != Self::SCHEME {
+			return self
+				.inner
+				.load(module_specifier, maybe_referrer, is_dyn_import);
+		}
+
+		Box::pin(future::ready(self.load_source(module_specifier)))
+	}
+}
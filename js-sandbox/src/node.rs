@@ -0,0 +1,245 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::rc::Rc;
+
+use deno_core::anyhow::anyhow;
+use deno_core::futures::executor::block_on;
+use deno_core::url::Url;
+use deno_core::{
+	op, JsBuffer, ModuleLoader, ModuleSpecifier, ModuleType, OpDecl, OpState, ResolutionKind,
+	ToJsBuffer,
+};
+use serde::Serialize;
+
+use crate::permissions::PermissionState;
+use crate::{base64, AnyError};
+
+/// Node.js compatibility for library code, enabled with [`ScriptBuilder::node_compat()`](crate::ScriptBuilder::node_compat).
+///
+/// Scripts get a `process` object, `Buffer` and a CommonJS `require()`:
+/// * `process.env` contains only the host's environment variables that are allowed with [`allow_env()`](Self::allow_env)
+///   or [`Permissions::allow_env()`](crate::Permissions::allow_env). Reading any other variable yields `undefined` and is
+///   recorded as denial. Scripts can modify `process.env`, without affecting the host. `process.exit()` throws an error.
+/// * `Buffer` is a `Uint8Array` with the common parts of Node's API, supporting the encodings `utf8`, `hex`, `base64`,
+///   `latin1` and `ascii`.
+/// * `require()` loads modules through the script's module loader, so its restrictions and import map apply; files
+///   additionally need read permission. Relative specifiers are resolved against the requiring module; at top level,
+///   against the working directory or, with a custom loader, against `"."`. Extensions `.js` and `.json` and `index.js`
+///   files are tried if the specifier has no extension.
+///
+/// Modules are loaded synchronously, so module loaders must complete without waiting for I/O on the runtime.
+/// Node's built-in modules such as `fs` are not available.
+#[derive(Clone, Debug, Default)]
+pub struct NodeCompat {
+	env_allowlist: Vec<String>,
+}
+
+impl NodeCompat {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Exposes the host's environment variable `name` in `process.env`, if it is set.
+	pub fn allow_env(mut self, name: impl Into<String>) -> Self {
+		self.env_allowlist.push(name.into());
+		self
+	}
+}
+
+/// Node compatibility settings and module loader of a script, stored in its `OpState`
+pub(crate) struct NodeState {
+	config: NodeCompat,
+	loader: Rc<dyn ModuleLoader>,
+
+	/// Referrer for `require()` calls outside of CommonJS modules
+	base: String,
+}
+
+impl NodeState {
+	pub fn new(
+		config: NodeCompat,
+		loader: Rc<dyn ModuleLoader>,
+		custom_loader: bool,
+	) -> Result<Self, AnyError> {
+		let base = if custom_loader {
+			".".to_string()
+		} else {
+			let cwd = std::env::current_dir()?;
+			Url::from_directory_path(&cwd)
+				.map_err(|_| anyhow!("CWD {} is not a valid URL", cwd.display()))?
+				.to_string()
+		};
+
+		Ok(Self {
+			config,
+			loader,
+			base,
+		})
+	}
+
+	fn is_env_allowed(&self, name: &str) -> bool {
+		self.config
+			.env_allowlist
+			.iter()
+			.any(|allowed| allowed == name)
+	}
+}
+
+/// Resolves and loads `specifier`, trying the usual extensions if it has none. Files need read permission.
+fn require(
+	state: &mut OpState,
+	specifier: &str,
+	referrer: Option<&str>,
+) -> Result<(ModuleSpecifier, ModuleType, String), AnyError> {
+	let node = node_state(state)?;
+	let loader = node.loader.clone();
+	let referrer = referrer.map_or_else(|| node.base.clone(), str::to_string);
+
+	let file_name = specifier.rsplit('/').next().unwrap_or_default();
+	let candidates = if file_name.contains('.') || file_name.is_empty() {
+		vec![specifier.to_string()]
+	} else {
+		vec![
+			specifier.to_string(),
+			format!("{specifier}.js"),
+			format!("{specifier}.json"),
+			format!("{specifier}/index.js"),
+		]
+	};
+
+	let mut first_error = None;
+	for candidate in candidates {
+		let url = match loader.resolve(&candidate, &referrer, ResolutionKind::Import) {
+			Ok(url) => url,
+			Err(e) => {
+				first_error.get_or_insert(e);
+				continue;
+			}
+		};
+
+		if let Ok(path) = url.to_file_path() {
+			state.borrow_mut::<PermissionState>().check_read(&path)?;
+		}
+
+		let loaded = block_on(loader.load(&url, None, false))
+			.map(|source| (url, source.module_type, source.code.as_str().to_string()));
+
+		match loaded {
+			Ok(loaded) => return Ok(loaded),
+			Err(e) => {
+				first_error.get_or_insert(e);
+			}
+		}
+	}
+
+	Err(first_error.expect("at least one candidate"))
+}
+
+/// Ops for node.js, registered with the crate's other ops
+pub(crate) fn ops() -> Vec<OpDecl> {
+	vec![
+		op_node_env_get::decl(),
+		op_node_env_keys::decl(),
+		op_node_require::decl(),
+		op_node_buffer_encode::decl(),
+		op_node_buffer_decode::decl(),
+	]
+}
+
+fn node_state(state: &OpState) -> Result<&NodeState, AnyError> {
+	state.try_borrow::<NodeState>().ok_or_else(|| {
+		anyhow!(
+			"Node compatibility is not enabled; build the script with ScriptBuilder::node_compat()"
+		)
+	})
+}
+
+/// Value of an allowed variable; reading others yields `None`, but is recorded as denial
+#[op]
+fn op_node_env_get(state: &mut OpState, name: String) -> Result<Option<String>, AnyError> {
+	let allowed = node_state(state)?.is_env_allowed(&name)
+		|| state
+			.borrow_mut::<PermissionState>()
+			.check_env(&name)
+			.is_ok();
+
+	if !allowed {
+		return Ok(None);
+	}
+
+	Ok(std::env::var(&name).ok())
+}
+
+/// Names of the variables that are both allowed and set; may contain duplicates
+#[op]
+fn op_node_env_keys(state: &mut OpState) -> Result<Vec<String>, AnyError> {
+	let node = node_state(state)?;
+	let permissions = state.borrow::<PermissionState>().permissions();
+
+	let keys = node
+		.config
+		.env_allowlist
+		.iter()
+		.chain(permissions.env_vars())
+		.filter(|name| std::env::var_os(name).is_some())
+		.cloned()
+		.collect();
+	Ok(keys)
+}
+
+#[derive(Serialize)]
+struct RequiredModule {
+	url: String,
+	code: String,
+	json: bool,
+}
+
+#[op]
+fn op_node_require(
+	state: &mut OpState,
+	specifier: String,
+	referrer: Option<String>,
+) -> Result<RequiredModule, AnyError> {
+	let (url, module_type, code) = require(state, &specifier, referrer.as_deref())?;
+
+	Ok(RequiredModule {
+		json: module_type == ModuleType::Json || url.path().ends_with(".json"),
+		url: url.to_string(),
+		code,
+	})
+}
+
+#[op]
+fn op_node_buffer_encode(text: String, encoding: String) -> Result<ToJsBuffer, AnyError> {
+	let bytes = match encoding.as_str() {
+		"utf8" => text.into_bytes(),
+		"latin1" | "ascii" => text.chars().map(|c| c as u32 as u8).collect(),
+		"hex" => {
+			// Like Node, stop at the first invalid pair
+			let digits: Vec<u32> = text.chars().map_while(|c| c.to_digit(16)).collect();
+			digits
+				.chunks_exact(2)
+				.map(|pair| (pair[0] * 16 + pair[1]) as u8)
+				.collect()
+		}
+		"base64" => base64::decode(text.as_bytes())
+			.ok_or_else(|| anyhow!("Buffer: string is not correctly base64-encoded"))?,
+		_ => return Err(anyhow!("Unknown encoding: {encoding}")),
+	};
+
+	Ok(bytes.into())
+}
+
+#[op]
+fn op_node_buffer_decode(bytes: JsBuffer, encoding: String) -> Result<String, AnyError> {
+	let text = match encoding.as_str() {
+		"utf8" => String::from_utf8_lossy(&bytes).into_owned(),
+		"latin1" => bytes.iter().map(|&b| char::from(b)).collect(),
+		"ascii" => bytes.iter().map(|&b| char::from(b & 0x7f)).collect(),
+		"hex" => bytes.iter().map(|b| format!("{b:02x}")).collect(),
+		"base64" => base64::encode(&bytes),
+		_ => return Err(anyhow!("Unknown encoding: {encoding}")),
+	};
+
+	Ok(text)
+}
@@ -0,0 +1,286 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::AnyError;
+
+/// Rights of a script to access host resources, checked by every op that reaches outside the sandbox.
+///
+/// Nothing is allowed by default. Each `allow_*()` method grants one resource:
+///
+/// ```rust
+/// use js_sandbox::Permissions;
+///
+/// let permissions = Permissions::new()
+/// 	.allow_read("plugins/tenant-42/data")
+/// 	.allow_net("api.example.com")
+/// 	.allow_env("TZ")
+/// 	.allow_host_functions("kv");
+/// ```
+///
+/// A denied access throws a `PermissionDenied` error in JS (`host.PermissionDenied`) and is recorded in the script's denial
+/// log, see [`Script::take_denials()`](crate::Script::take_denials). Modules imported with `import` are not subject to
+/// these checks; the module loader decides which modules a script can load.
+#[derive(Clone, Debug, Default)]
+pub struct Permissions {
+	read: Vec<PathBuf>,
+	write: Vec<PathBuf>,
+	net: Vec<String>,
+	env: Vec<String>,
+	host_functions: Vec<String>,
+}
+
+impl Permissions {
+	/// Creates permissions that allow nothing.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Allows reading `path` and, if it is a directory, everything below it. Relative paths refer to the working directory.
+	pub fn allow_read(mut self, path: impl AsRef<Path>) -> Self {
+		self.read.push(normalize_path(path.as_ref()));
+		self
+	}
+
+	/// Allows writing `path` and, if it is a directory, everything below it. Relative paths refer to the working directory.
+	pub fn allow_write(mut self, path: impl AsRef<Path>) -> Self {
+		self.write.push(normalize_path(path.as_ref()));
+		self
+	}
+
+	/// Allows connections to `host`, on any port (`"api.example.com"`) or a specific one (`"api.example.com:8443"`).
+	pub fn allow_net(mut self, host: impl Into<String>) -> Self {
+		self.net.push(host.into().to_ascii_lowercase());
+		self
+	}
+
+	/// Allows reading the host's environment variable `name`.
+	pub fn allow_env(mut self, name: impl Into<String>) -> Self {
+		self.env.push(name.into());
+		self
+	}
+
+	/// Allows calling the host functions of `group`, e.g. `"kv"` or the group of an
+	/// [`ExposedFunction`](crate::exposed_func::ExposedFunction).
+	pub fn allow_host_functions(mut self, group: impl Into<String>) -> Self {
+		self.host_functions.push(group.into());
+		self
+	}
+
+	/// Whether `path` may be read.
+	pub fn allows_read(&self, path: impl AsRef<Path>) -> bool {
+		let path = normalize_path(path.as_ref());
+		self.read.iter().any(|allowed| path.starts_with(allowed))
+	}
+
+	/// Whether `path` may be written. No built-in API writes files; this is for host functions that do.
+	pub fn allows_write(&self, path: impl AsRef<Path>) -> bool {
+		let path = normalize_path(path.as_ref());
+		self.write.iter().any(|allowed| path.starts_with(allowed))
+	}
+
+	/// Whether connections to `host` on `port` are allowed. IPv6 addresses are written in brackets, like in URLs.
+	pub fn allows_net(&self, host: &str, port: Option<u16>) -> bool {
+		let host = host.to_ascii_lowercase();
+		self.net.iter().any(|allowed| {
+			let (allowed_host, allowed_port) = split_port(allowed);
+			allowed_host == host
+				&& allowed_port.map_or(true, |allowed_port| Some(allowed_port) == port)
+		})
+	}
+
+	/// Whether the environment variable `name` may be read.
+	pub fn allows_env(&self, name: &str) -> bool {
+		self.env.iter().any(|allowed| allowed == name)
+	}
+
+	/// Whether the host functions of `group` may be called.
+	pub fn allows_host_functions(&self, group: &str) -> bool {
+		self.host_functions.iter().any(|allowed| allowed == group)
+	}
+
+	/// Environment variables that may be read
+	pub(crate) fn env_vars(&self) -> &[String] {
+		&self.env
+	}
+}
+
+/// Kind of access to a host resource
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+	Read,
+	Write,
+	Net,
+	Env,
+	HostFunction,
+}
+
+impl fmt::Display for Access {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			Access::Read => "read",
+			Access::Write => "write",
+			Access::Net => "net",
+			Access::Env => "env",
+			Access::HostFunction => "host function",
+		};
+		f.write_str(name)
+	}
+}
+
+/// An access denied by the script's [`Permissions`]; the error thrown in JS and an entry of the denial log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PermissionDenied {
+	/// Kind of the denied access
+	pub access: Access,
+
+	/// The path, host, variable or group that was accessed
+	pub resource: String,
+}
+
+impl fmt::Display for PermissionDenied {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"Requires {} access to \"{}\"",
+			self.access, self.resource
+		)
+	}
+}
+
+impl Error for PermissionDenied {}
+
+/// Permissions of a script and its denial log, stored in its `OpState`
+pub(crate) struct PermissionState {
+	permissions: Permissions,
+	denials: Vec<PermissionDenied>,
+}
+
+impl PermissionState {
+	/// Denials beyond this are not recorded, so that a misbehaving script cannot grow the log without bounds
+	const MAX_DENIALS: usize = 1000;
+
+	pub fn new(permissions: Permissions) -> Self {
+		Self {
+			permissions,
+			denials: Vec::new(),
+		}
+	}
+
+	pub fn permissions(&self) -> &Permissions {
+		&self.permissions
+	}
+
+	pub fn set_permissions(&mut self, permissions: Permissions) {
+		self.permissions = permissions;
+	}
+
+	pub fn take_denials(&mut self) -> Vec<PermissionDenied> {
+		std::mem::take(&mut self.denials)
+	}
+
+	pub fn check_read(&mut self, path: &Path) -> Result<(), PermissionDenied> {
+		let allowed = self.permissions.allows_read(path);
+		self.check(allowed, Access::Read, || path.display().to_string())
+	}
+
+	pub fn check_net(&mut self, host: &str, port: Option<u16>) -> Result<(), PermissionDenied> {
+		let allowed = self.permissions.allows_net(host, port);
+		self.check(allowed, Access::Net, || match port {
+			Some(port) => format!("{host}:{port}"),
+			None => host.to_string(),
+		})
+	}
+
+	pub fn check_env(&mut self, name: &str) -> Result<(), PermissionDenied> {
+		let allowed = self.permissions.allows_env(name);
+		self.check(allowed, Access::Env, || name.to_string())
+	}
+
+	pub fn check_host_functions(&mut self, group: &str) -> Result<(), PermissionDenied> {
+		let allowed = self.permissions.allows_host_functions(group);
+		self.check(allowed, Access::HostFunction, || group.to_string())
+	}
+
+	fn check(
+		&mut self,
+		allowed: bool,
+		access: Access,
+		resource: impl FnOnce() -> String,
+	) -> Result<(), PermissionDenied> {
+		if allowed {
+			return Ok(());
+		}
+
+		let denied = PermissionDenied {
+			access,
+			resource: resource(),
+		};
+		if self.denials.len() < Self::MAX_DENIALS {
+			self.denials.push(denied.clone());
+		}
+		Err(denied)
+	}
+}
+
+/// JS error class for errors returned by ops: `PermissionDenied` for denied accesses, `Error` for anything else.
+pub(crate) fn get_error_class(error: &AnyError) -> &'static str {
+	if error.downcast_ref::<PermissionDenied>().is_some() {
+		"PermissionDenied"
+	} else {
+		"Error"
+	}
+}
+
+/// Splits `"host:port"` into its parts; the port is `None` if there is none or it is not a number.
+fn split_port(host: &str) -> (&str, Option<u16>) {
+	match host.rsplit_once(':') {
+		Some((name, port)) => match port.parse() {
+			Ok(port) => (name, Some(port)),
+			Err(_) => (host, None),
+		},
+		None => (host, None),
+	}
+}
+
+/// Makes `path` absolute and resolves `.` and `..` without touching the file system, then resolves symlinks in the part
+/// of the path that exists. This way, neither `..` nor links lead out of an allowed directory.
+fn normalize_path(path: &Path) -> PathBuf {
+	let path = if path.is_absolute() {
+		path.to_path_buf()
+	} else {
+		std::env::current_dir().unwrap_or_default().join(path)
+	};
+
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => {
+				normalized.pop();
+			}
+			other => normalized.push(other),
+		}
+	}
+
+	let mut existing = normalized.as_path();
+	let mut missing = Vec::new();
+	loop {
+		if let Ok(canonical) = existing.canonicalize() {
+			return missing
+				.iter()
+				.rev()
+				.fold(canonical, |path, name| path.join(name));
+		}
+
+		match (existing.parent(), existing.file_name()) {
+			(Some(parent), Some(name)) => {
+				missing.push(name);
+				existing = parent;
+			}
+			_ => return normalized,
+		}
+	}
+}
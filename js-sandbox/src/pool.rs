@@ -0,0 +1,289 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use deno_core::anyhow::anyhow;
+use serde::de::DeserializeOwned;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{CallArgs, JsError, JsValue, Script};
+
+/// Creates the script of a pool worker. Called once per worker at startup, and again whenever a script is recycled.
+type ScriptFactory = dyn Fn() -> Result<Script, JsError> + Send + Sync;
+
+/// A function call waiting for a free worker
+struct Job {
+	fn_name: String,
+	json_args: String,
+	reply: oneshot::Sender<Result<JsValue, JsError>>,
+}
+
+/// A fixed number of worker threads, each owning a ready-to-use [`Script`] created from the same source.
+///
+/// A [`Script`] is bound to the thread that created it. The pool is `Send + Sync` and can be shared (e.g. in an `Arc` or
+/// as server state); its async [`call()`](Self::call) runs the function on the next free worker and resolves once it has
+/// returned. Calls queue up while all workers are busy.
+///
+/// Scripts are replaced with fresh ones created by the same factory:
+/// * after a configurable number of calls, so that state accumulated by scripts does not grow without limit,
+/// * after a call failed with a runtime error (including timeouts), as the script may be left in an inconsistent state.
+///
+/// ```rust
+/// use js_sandbox::{Script, ScriptPool, AnyError};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), AnyError> {
+/// let pool = ScriptPool::builder()
+/// 	.workers(4)
+/// 	.max_calls(1000)
+/// 	.build(|| Script::from_string("function sub(a, b) { return a - b; }"))?;
+///
+/// let result: i32 = pool.call("sub", (7, 5)).await?;
+/// assert_eq!(result, 2);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ScriptPool {
+	jobs: mpsc::UnboundedSender<Job>,
+	metrics: Arc<Metrics>,
+	workers: usize,
+}
+
+/// Configures a [`ScriptPool`] before its workers are started.
+pub struct ScriptPoolBuilder {
+	workers: usize,
+	max_calls: Option<u64>,
+}
+
+/// Counters describing a [`ScriptPool`] at a point in time, see [`ScriptPool::metrics()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+	/// Number of worker threads
+	pub workers: usize,
+	/// Workers currently running a call
+	pub busy: usize,
+	/// Calls waiting for a free worker
+	pub queued: usize,
+	/// Calls completed, successfully or not
+	pub calls: u64,
+	/// Calls that returned an error
+	pub errors: u64,
+	/// Scripts replaced by a fresh one, after reaching the call limit or failing
+	pub recycled: u64,
+}
+
+#[derive(Default)]
+struct Metrics {
+	busy: AtomicUsize,
+	queued: AtomicUsize,
+	calls: AtomicU64,
+	errors: AtomicU64,
+	recycled: AtomicU64,
+}
+
+impl ScriptPool {
+	/// Returns a builder to configure the pool; see [`ScriptPoolBuilder`].
+	pub fn builder() -> ScriptPoolBuilder {
+		ScriptPoolBuilder::new()
+	}
+
+	/// Invokes a JavaScript function on the next free worker, like [`Script::call()`].
+	///
+	/// Successive calls may run on different workers, so functions should not rely on state from previous calls.
+	pub async fn call<A, R>(&self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		let (reply, response) = oneshot::channel();
+		let job = Job {
+			fn_name: fn_name.to_string(),
+			json_args: args_tuple.into_arg_string()?,
+			reply,
+		};
+
+		self.metrics.queued.fetch_add(1, Ordering::Relaxed);
+		if self.jobs.send(job).is_err() {
+			self.metrics.queued.fetch_sub(1, Ordering::Relaxed);
+			return Err(JsError::Runtime(anyhow!("script pool has shut down")));
+		}
+
+		let json_result = response.await.map_err(|_| {
+			JsError::Runtime(anyhow!("script pool worker stopped during the call"))
+		})??;
+
+		Ok(serde_json::from_value(json_result)?)
+	}
+
+	/// Returns the current counters of the pool.
+	pub fn metrics(&self) -> PoolMetrics {
+		PoolMetrics {
+			workers: self.workers,
+			busy: self.metrics.busy.load(Ordering::Relaxed),
+			queued: self.metrics.queued.load(Ordering::Relaxed),
+			calls: self.metrics.calls.load(Ordering::Relaxed),
+			errors: self.metrics.errors.load(Ordering::Relaxed),
+			recycled: self.metrics.recycled.load(Ordering::Relaxed),
+		}
+	}
+}
+
+impl ScriptPoolBuilder {
+	/// Creates a builder with default settings: one worker per CPU, and scripts that are never recycled after a number of calls.
+	pub fn new() -> Self {
+		let workers = thread::available_parallelism().map_or(1, |n| n.get());
+
+		Self {
+			workers,
+			max_calls: None,
+		}
+	}
+
+	/// Number of worker threads, each with its own script.
+	///
+	/// Panics if `workers` is zero.
+	pub fn workers(mut self, workers: usize) -> Self {
+		assert!(workers > 0);
+
+		self.workers = workers;
+		self
+	}
+
+	/// Replaces a worker's script after it has handled `max_calls` calls.
+	///
+	/// Panics if `max_calls` is zero.
+	pub fn max_calls(mut self, max_calls: u64) -> Self {
+		assert!(max_calls > 0);
+
+		self.max_calls = Some(max_calls);
+		self
+	}
+
+	/// Starts the workers, each of which creates its script with `factory`.
+	///
+	/// Returns once all scripts are ready. Fails if `factory` fails for any of them, e.g. due to a syntax error.
+	pub fn build<F>(self, factory: F) -> Result<ScriptPool, JsError>
+	where
+		F: Fn() -> Result<Script, JsError> + Send + Sync + 'static,
+	{
+		let factory: Arc<ScriptFactory> = Arc::new(factory);
+		let metrics = Arc::new(Metrics::default());
+
+		let (jobs, job_receiver) = mpsc::unbounded_channel();
+		let job_receiver = Arc::new(Mutex::new(job_receiver));
+		let (ready_sender, ready) = std::sync::mpsc::channel();
+
+		for i in 0..self.workers {
+			let worker = Worker {
+				factory: factory.clone(),
+				jobs: job_receiver.clone(),
+				metrics: metrics.clone(),
+				max_calls: self.max_calls,
+			};
+			let ready_sender = ready_sender.clone();
+
+			thread::Builder::new()
+				.name(format!("js-sandbox-pool-{i}"))
+				.spawn(move || worker.run(ready_sender))
+				.map_err(|e| JsError::Runtime(e.into()))?;
+		}
+		drop(ready_sender);
+
+		// Dropping `jobs` on failure stops the workers that did start
+		for _ in 0..self.workers {
+			match ready.recv() {
+				Ok(Ok(())) => {}
+				Ok(Err(e)) => return Err(e),
+				Err(_) => return Err(JsError::Runtime(anyhow!("script pool worker panicked"))),
+			}
+		}
+
+		Ok(ScriptPool {
+			jobs,
+			metrics,
+			workers: self.workers,
+		})
+	}
+}
+
+impl Default for ScriptPoolBuilder {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+struct Worker {
+	factory: Arc<ScriptFactory>,
+	jobs: Arc<Mutex<mpsc::UnboundedReceiver<Job>>>,
+	metrics: Arc<Metrics>,
+	max_calls: Option<u64>,
+}
+
+impl Worker {
+	fn run(self, ready: std::sync::mpsc::Sender<Result<(), JsError>>) {
+		let mut script = match (self.factory)() {
+			Ok(script) => {
+				let _ = ready.send(Ok(()));
+				Some(script)
+			}
+			Err(e) => {
+				let _ = ready.send(Err(e));
+				return;
+			}
+		};
+		let mut calls = 0;
+
+		while let Some(job) = self.next_job() {
+			self.metrics.queued.fetch_sub(1, Ordering::Relaxed);
+			self.metrics.busy.fetch_add(1, Ordering::Relaxed);
+
+			// A previous re-creation may have failed; try again, and report the failure to the caller otherwise. A script
+			// that panicked is dropped, and the worker carries on with a fresh one.
+			let result = match script.take().map_or_else(|| (self.factory)(), Ok) {
+				Ok(mut current) => {
+					let fn_name = &job.fn_name;
+					let json_args = job.json_args;
+					panic::catch_unwind(AssertUnwindSafe(move || {
+						let result = current.call_impl(fn_name, json_args);
+						(current, result)
+					}))
+					.map(|(current, result)| {
+						script = Some(current);
+						result
+					})
+					.unwrap_or_else(|_| {
+						Err(JsError::Runtime(anyhow!(
+							"script panicked while calling '{fn_name}'"
+						)))
+					})
+				}
+				Err(e) => Err(e),
+			};
+			calls += 1;
+
+			let failed = matches!(result, Err(JsError::Runtime(_)));
+			if result.is_err() {
+				self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+			}
+			self.metrics.calls.fetch_add(1, Ordering::Relaxed);
+			self.metrics.busy.fetch_sub(1, Ordering::Relaxed);
+			let _ = job.reply.send(result);
+
+			let exhausted = self.max_calls.map_or(false, |max| calls >= max);
+			if failed || exhausted {
+				script = (self.factory)().ok();
+				calls = 0;
+				self.metrics.recycled.fetch_add(1, Ordering::Relaxed);
+			}
+		}
+	}
+
+	/// Waits for the next job; `None` once the pool is dropped.
+	fn next_job(&self) -> Option<Job> {
+		let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+		jobs.blocking_recv()
+	}
+}
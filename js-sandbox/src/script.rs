@@ -1,26 +1,62 @@
 // Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
 
-use std::borrow::Cow;
+use std::future::Future;
 use std::path::Path;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Once, OnceLock};
+use std::task::Poll;
 use std::{thread, time::Duration};
 
+use tokio::runtime::RuntimeFlavor;
+
 use deno_core::v8::{FunctionCallbackArguments, HandleScope, ReturnValue};
-use deno_core::{futures, op, v8, Extension, JsBuffer, JsRuntime, OpState};
+use deno_core::{futures, op, v8, Extension, JsRuntime, JsRuntimeForSnapshot, OpState, Snapshot};
 use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
+use crate::deterministic::{op_deterministic_date_now, op_deterministic_random, Deterministic};
 use crate::exposed_func::{
-	DefaultExposedFunction, ExposedFunction, ExposedObject, SqlSelectExposedFunction, ExposedObject1,
+	DefaultExposedFunction, ExposedFunction, ExposedObject, ExposedObject1,
+	SqlSelectExposedFunction,
+};
+use crate::fetch::FetchState;
+use crate::node::NodeState;
+use crate::permissions::{self, PermissionState};
+use crate::snapshot::SnapshotData;
+use crate::source_map::SourceMapStore;
+use crate::timers::{op_timer_cancel, op_timer_now, op_timer_start, op_timer_wait, Timers};
+use crate::{
+	AnyError, CallArgs, JsError, JsValue, PermissionDenied, Permissions, ScriptBuilder,
+	ScriptHandle,
 };
-use crate::{AnyError, CallArgs, JsError, JsValue};
 
-use deno_core::anyhow::Context;
 use deno_core::anyhow::Error;
+use deno_core::anyhow::{anyhow, Context};
+use deno_core::error::JsStackFrame;
 use deno_core::FsModuleLoader;
 use deno_core::RuntimeOptions;
+use deno_core::{ModuleLoader, ModuleSpecifier, ResolutionKind};
 
 use std::cell::RefCell;
 
+const HOST_JS: &str = include_str!("js/host.js");
+const MODULE_JS: &str = include_str!("js/module.js");
+const TIMERS_JS: &str = include_str!("js/timers.js");
+const DETERMINISTIC_JS: &str = include_str!("js/deterministic.js");
+#[cfg(feature = "web")]
+const WEB_JS: &str = include_str!("js/web.js");
+const NODE_JS: &str = include_str!("js/node.js");
+const FS_JS: &str = include_str!("js/fs.js");
+const FETCH_JS: &str = include_str!("js/fetch.js");
+const KV_JS: &str = include_str!("js/kv.js");
+const SQL_JS: &str = include_str!("js/sql.js");
+
+// console.log() is not available by default -- add the most basic version with single argument (and no warn/info/... variants)
+const CONSOLE_JS: &str =
+	"const console = { log: function(expr) { Deno.core.print(expr + '\\n', false); } };";
+
 pub trait JsApi<'a> {
 	/// Generate an API from a script
 	fn from_script(script: &'a mut Script) -> Self
@@ -34,12 +70,27 @@ pub trait JsApi<'a> {
 /// A typical usage pattern is to load a file with one or more JS function definitions, and then call those functions from Rust.
 pub struct Script {
 	runtime: JsRuntime,
-	last_rid: u32,
+	last_call: u32,
 	timeout: Option<Duration>,
+	module: Option<v8::Global<v8::Object>>,
+	file_name: String,
 }
 
 impl Script {
 	const DEFAULT_FILENAME: &'static str = "sandboxed.js";
+	const CALL_FILENAME: &'static str = "js_sandbox:call.js";
+	const CONSOLE_FILENAME: &'static str = "js_sandbox:console.js";
+	const HOST_FILENAME: &'static str = "js_sandbox:host.js";
+	const MODULE_FILENAME: &'static str = "js_sandbox:module.js";
+	const TIMERS_FILENAME: &'static str = "js_sandbox:timers.js";
+	const DETERMINISTIC_FILENAME: &'static str = "js_sandbox:deterministic.js";
+	#[cfg(feature = "web")]
+	const WEB_FILENAME: &'static str = "js_sandbox:web.js";
+	const NODE_FILENAME: &'static str = "js_sandbox:node.js";
+	const FS_FILENAME: &'static str = "js_sandbox:fs.js";
+	const FETCH_FILENAME: &'static str = "js_sandbox:fetch.js";
+	const KV_FILENAME: &'static str = "js_sandbox:kv.js";
+	const SQL_FILENAME: &'static str = "js_sandbox:sql.js";
 
 	// ----------------------------------------------------------------------------------------------------------------------------------------------
 	// Constructors and builders
@@ -48,16 +99,28 @@ impl Script {
 	///
 	/// Returns a new object on success, and an error in case of syntax or initialization error with the code.
 	pub fn from_string(js_code: &str) -> Result<Self, JsError> {
-		// console.log() is not available by default -- add the most basic version with single argument (and no warn/info/... variants)
-		let all_code =
-			"const console = { log: function(expr) { Deno.core.print(expr + '\\n', false); } };"
-				.to_string() + js_code;
+		Self::builder().build_from_string(js_code)
+	}
 
-		Self::create_script(all_code)
+	/// Returns a builder to configure a script before creating it, e.g. with a custom module loader.
+	pub fn builder() -> ScriptBuilder {
+		ScriptBuilder::new()
 	}
 
 	pub fn rd_get_run_time() -> Result<Self, JsError> {
-		Self::rd_create_run_time()
+		Self::rd_create_run_time(ScriptBuilder::new())
+	}
+
+	/// Like [`rd_get_run_time()`](Self::rd_get_run_time), with `permissions` granted to the script, e.g. to allow the
+	/// groups of functions added with [`add_exposed_func()`](Self::add_exposed_func).
+	pub fn rd_get_run_time_with_permissions(permissions: Permissions) -> Result<Self, JsError> {
+		Self::rd_get_run_time_with_builder(ScriptBuilder::new().permissions(permissions))
+	}
+
+	/// Like [`rd_get_run_time()`](Self::rd_get_run_time), configured by `builder`: its module loader, import map,
+	/// permissions and host features apply to the runtime.
+	pub fn rd_get_run_time_with_builder(builder: ScriptBuilder) -> Result<Self, JsError> {
+		Self::rd_create_run_time(builder)
 	}
 
 	// pub fn rd_get_run_time2(file_path: &str) -> Result<Self, AnyError> {
@@ -65,20 +128,19 @@ impl Script {
 	// }
 
 	pub fn rd_run_string(&mut self, js_code: &str) -> Result<v8::Global<v8::Value>, JsError> {
-		// console.log() is not available by default -- add the most basic version with single argument (and no warn/info/... variants)
-		let all_code =
-			"const console = { log: function(expr) { Deno.core.print(expr + '\\n', false); } };"
-				.to_string() + js_code;
-
-		self.rd_run_script(all_code)
+		let file_name = self.file_name.clone();
+		Self::execute_named(&mut self.runtime, &file_name, js_code)
 	}
 
 	pub fn rd_run_file(
 		&mut self,
 		file: impl AsRef<Path>,
 	) -> Result<v8::Global<v8::Value>, JsError> {
+		let file = file.as_ref();
 		match std::fs::read_to_string(file) {
-			Ok(js_code) => self.rd_run_script(js_code),
+			Ok(js_code) => {
+				Self::execute_named(&mut self.runtime, &file.display().to_string(), &js_code)
+			}
 			Err(e) => Err(JsError::Runtime(AnyError::from(e))),
 		}
 	}
@@ -90,17 +152,100 @@ impl Script {
 	///
 	/// Returns a new object on success. Fails if the file cannot be opened or in case of syntax or initialization error with the code.
 	pub fn from_file(file: impl AsRef<Path>) -> Result<Self, JsError> {
-		// let filename = file
-		// 	.as_ref()
-		// 	.file_name()
-		// 	.and_then(|s| s.to_str())
-		// 	.unwrap_or(Self::DEFAULT_FILENAME)
-		// 	.to_owned();
+		Self::builder().build_from_file(file)
+	}
 
-		match std::fs::read_to_string(file) {
-			Ok(js_code) => Self::create_script(js_code),
-			Err(e) => Err(JsError::Runtime(AnyError::from(e))),
+	/// Initialize a script by loading a .js file as ES module.
+	///
+	/// Imports are resolved relative to the module file. Unlike with [`Self::from_file()`], the module's exports remain accessible
+	/// after loading: [`Self::call()`] and [`JsApi`] bindings invoke exported functions. Names that are not exported directly are
+	/// looked up on the `export default` object; a default-exported function itself is called by the name `"default"`.
+	///
+	/// Top-level declarations of a module are private to it, so [`Self::get_global()`] and related methods only see `globalThis`.
+	///
+	/// Modules are read from the file system; use [`Self::builder()`] to load them from elsewhere.
+	///
+	/// Returns a new object on success. Fails if the module or one of its imports cannot be loaded, or if evaluating it throws.
+	pub fn from_module(file: impl AsRef<Path>) -> Result<Self, JsError> {
+		Self::builder().build_from_module(&file.as_ref().to_string_lossy())
+	}
+
+	/// Async version of [`Self::from_module()`], to be awaited on the caller's tokio runtime.
+	pub async fn from_module_async(file: impl AsRef<Path>) -> Result<Self, JsError> {
+		Self::builder()
+			.build_from_module_async(&file.as_ref().to_string_lossy())
+			.await
+	}
+
+	pub(crate) async fn create_module(
+		specifier: &str,
+		builder: ScriptBuilder,
+	) -> Result<Self, JsError> {
+		let source_maps = SourceMapStore::default();
+		let module_loader = builder.create_module_loader(&source_maps)?;
+		// Custom loaders and `vfs:` URLs resolve the specifier themselves; otherwise it is a path
+		let is_virtual = builder.virtual_fs.is_some() && specifier.starts_with("vfs:");
+		let main_module = if builder.module_loader.is_some() || is_virtual {
+			module_loader.resolve(specifier, ".", ResolutionKind::MainModule)?
+		} else {
+			deno_core::resolve_path(
+				specifier,
+				&std::env::current_dir().context("Unable to get CWD")?,
+			)
+			.map_err(AnyError::from)?
+		};
+
+		let mut runtime = Self::new_runtime(module_loader, &source_maps, None, &builder)?;
+		let namespace = Self::load_module(&mut runtime, &main_module).await?;
+
+		{
+			let scope = &mut runtime.handle_scope();
+			let context = scope.get_current_context();
+			let global = context.global(scope);
+
+			let key = v8::String::new(scope, "__rust_module").unwrap();
+			let value = v8::Local::new(scope, &namespace);
+			global.set(scope, key.into(), value.into());
 		}
+		runtime.execute_script(Self::MODULE_FILENAME, MODULE_JS.into())?;
+
+		let mut script = Self::from_runtime(runtime, builder);
+		script.module = Some(namespace);
+		script.file_name = main_module.to_string();
+		Ok(script)
+	}
+
+	/// Runs `js_code` and captures the resulting state as a V8 startup snapshot.
+	///
+	/// Everything the code sets up -- functions, loaded libraries, precomputed tables -- is part of the snapshot. Restoring it
+	/// with [`Self::from_snapshot()`] is much faster than running the code again, so a snapshot can be created once and used
+	/// for many scripts. Pending promises are settled and timers cancelled before the snapshot is taken; code that still waits
+	/// for other async operations, such as `fetch()`, cannot be captured and fails.
+	///
+	/// The snapshot can only be restored by the same version of this crate (and thus of V8). Host functions are not part of
+	/// the snapshot; they are reattached when restoring, through V8's external references.
+	///
+	/// Fails in case of syntax or initialization error with the code, like [`Self::from_string()`].
+	pub fn create_snapshot(js_code: &str) -> Result<Vec<u8>, JsError> {
+		Self::builder().create_snapshot(js_code)
+	}
+
+	/// Initialize a script from a snapshot created with [`Self::create_snapshot()`].
+	///
+	/// Returns an error if `snapshot` was not created by this version of the crate.
+	pub fn from_snapshot(snapshot: &[u8]) -> Result<Self, JsError> {
+		Self::builder().build_from_snapshot(snapshot)
+	}
+
+	/// Moves a script onto a dedicated thread, returning a handle that can be shared across threads.
+	///
+	/// `builder` creates the script on the new thread, e.g. `|| Script::from_file("plugin.js")`; a script itself cannot be
+	/// moved between threads. Returns once the script is created, or with the error `builder` returned.
+	pub fn spawn<F>(builder: F) -> Result<ScriptHandle, JsError>
+	where
+		F: FnOnce() -> Result<Script, JsError> + Send + 'static,
+	{
+		ScriptHandle::spawn(builder)
 	}
 
 	/// Equips this script with a timeout, meaning that any function call is aborted after the specified duration.
@@ -118,6 +263,14 @@ impl Script {
 		self
 	}
 
+	/// The name under which this script's code appears in errors and stack traces.
+	///
+	/// This is the path for scripts loaded from a file, the main module's URL for modules, and `"sandboxed.js"` for scripts
+	/// created from a string, unless set through [`ScriptBuilder::file_name()`].
+	pub fn file_name(&self) -> &str {
+		&self.file_name
+	}
+
 	// ----------------------------------------------------------------------------------------------------------------------------------------------
 	// Call API
 
@@ -128,18 +281,82 @@ impl Script {
 	/// `args_tuple` needs to be a tuple.
 	///
 	/// Each tuple element is converted to JSON (using serde_json) and passed as a distinct argument to the JS function.
+	///
+	/// In async code, prefer [`Self::call_async()`]; see there for how this method behaves inside a tokio runtime.
 	pub fn call<A, R>(&mut self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		block_on(self.call_async(fn_name, args_tuple))
+	}
+
+	/// Invokes a JavaScript function, like [`Self::call()`], but awaits its completion instead of blocking.
+	///
+	/// The event loop of the script is driven by the caller's executor, typically a tokio runtime. Asynchronous JS functions
+	/// thus do not block the thread while they wait, e.g. for timers.
+	///
+	/// The blocking methods such as [`Self::call()`] run the same code on a runtime of their own. Called from within a
+	/// multi-threaded tokio runtime, they block the current worker thread with `tokio::task::block_in_place()`. Within a
+	/// current-thread runtime, nothing that depends on the runtime (such as timers) could make progress while they block,
+	/// so they fail instead; use the async methods there.
+	///
+	/// The call completes once the function has returned, or its promise has settled. Timers that are still pending by then
+	/// keep running during later calls.
+	pub async fn call_async<A, R>(&mut self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
 	where
 		A: CallArgs,
 		R: DeserializeOwned,
 	{
 		let json_args = args_tuple.into_arg_string()?;
-		let json_result = self.call_impl(fn_name, json_args)?;
+		let json_result = self.call_impl_async(fn_name, json_args).await?;
 		let result: R = serde_json::from_value(json_result)?;
 
 		Ok(result)
 	}
 
+	/// Dispatches an event to all handlers that the script registered with `host.on(event, handler)`.
+	///
+	/// Handlers run in registration order; async handlers are awaited before the next one starts.
+	/// The returned vector holds one entry per handler: its return value (`null` for `undefined`), or the error it threw.
+	/// An event without handlers yields an empty vector.
+	///
+	/// The outer `Result` fails only if the dispatch itself fails, e.g. `payload` cannot be serialized or the call times out.
+	pub fn emit<P>(
+		&mut self,
+		event: &str,
+		payload: P,
+	) -> Result<Vec<Result<JsValue, JsError>>, JsError>
+	where
+		P: Serialize,
+	{
+		block_on(self.emit_async(event, payload))
+	}
+
+	/// Dispatches an event like [`Self::emit()`], but awaits the handlers instead of blocking.
+	pub async fn emit_async<P>(
+		&mut self,
+		event: &str,
+		payload: P,
+	) -> Result<Vec<Result<JsValue, JsError>>, JsError>
+	where
+		P: Serialize,
+	{
+		let json_args = (event, payload).into_arg_string()?;
+		let json_result = self.call_expr_impl("__rust_emit", json_args).await?;
+		let outcomes: Vec<EmitOutcome> = serde_json::from_value(json_result)?;
+
+		let results = outcomes
+			.into_iter()
+			.map(|outcome| match outcome {
+				EmitOutcome::Ok(value) => Ok(value),
+				EmitOutcome::Err(message) => Err(JsError::Runtime(anyhow!(message))),
+			})
+			.collect();
+
+		Ok(results)
+	}
+
 	pub fn bind_api<'a, A>(&'a mut self) -> A
 	where
 		A: JsApi<'a>,
@@ -147,128 +364,901 @@ impl Script {
 		A::from_script(self)
 	}
 
+	/// Checks whether `fn_name` refers to a function that [`Self::call()`] can invoke.
+	///
+	/// For module scripts, the module's exports are considered, including members of the default export.
+	pub fn has_function(&mut self, fn_name: &str) -> Result<bool, JsError> {
+		let js_expr = if self.module.is_some() {
+			format!("__rust_module.has({})", serde_json::to_string(fn_name)?)
+		} else {
+			format!(
+				"(() => {{
+					try {{
+						return typeof {fn_name} === 'function';
+					}} catch (e) {{
+						if (e instanceof ReferenceError) return false;
+						throw e;
+					}}
+				}})()"
+			)
+		};
+
+		Ok(serde_json::from_value(self.eval_json(&js_expr)?)?)
+	}
+
+	/// Lists the names exported by a module script, including `"default"` for a default export.
+	///
+	/// Scripts not created by [`Self::from_module()`] have no exports, and return an empty list.
+	pub fn exports(&mut self) -> Result<Vec<String>, JsError> {
+		if self.module.is_none() {
+			return Ok(Vec::new());
+		}
+
+		Ok(serde_json::from_value(
+			self.eval_json("__rust_module.names()")?,
+		)?)
+	}
+
 	pub(crate) fn call_json(&mut self, fn_name: &str, args: &JsValue) -> Result<JsValue, JsError> {
 		self.call_impl(fn_name, args.to_string())
 	}
 
-	fn call_impl(&mut self, fn_name: &str, json_args: String) -> Result<JsValue, JsError> {
+	pub(crate) fn call_impl(
+		&mut self,
+		fn_name: &str,
+		json_args: String,
+	) -> Result<JsValue, JsError> {
+		block_on(self.call_impl_async(fn_name, json_args))
+	}
+
+	async fn call_impl_async(
+		&mut self,
+		fn_name: &str,
+		json_args: String,
+	) -> Result<JsValue, JsError> {
+		// Module scripts call their exports, classic scripts anything reachable from global scope
+		let fn_expr = if self.module.is_some() {
+			format!("__rust_module.resolve({})", serde_json::to_string(fn_name)?)
+		} else {
+			fn_name.to_string()
+		};
+
+		self.call_expr_impl(&fn_expr, json_args).await
+	}
+
+	async fn call_expr_impl(
+		&mut self,
+		fn_expr: &str,
+		json_args: String,
+	) -> Result<JsValue, JsError> {
 		// Note: ops() is required to initialize internal state
 		// Wrap everything in scoped block
 
+		// Each call passes its own token to op_return(), so that an async function left pending by an earlier call (e.g.
+		// one that timed out) cannot complete a later one
+		self.last_call = self.last_call.wrapping_add(1);
+		let token = self.last_call;
+		self.runtime
+			.op_state()
+			.borrow_mut()
+			.put(CallResult { token, value: None });
+
 		// 'undefined' will cause JSON serialization error, so it needs to be treated as null
 		let js_code = format!(
 			"(async () => {{
-				let __rust_result = {fn_name}.constructor.name === 'AsyncFunction'
-					? await {fn_name}({json_args})
-					: {fn_name}({json_args});
+				let __rust_result = {fn_expr}.constructor.name === 'AsyncFunction'
+					? await {fn_expr}({json_args})
+					: {fn_expr}({json_args});
 
 				if (typeof __rust_result === 'undefined')
 					__rust_result = null;
 
-				Deno.core.ops.op_return(__rust_result);
+				Deno.core.ops.op_return({token}, __rust_result);
 			}})()"
 		);
 
-		if let Some(timeout) = self.timeout {
-			let handle = self.runtime.v8_isolate().thread_safe_handle();
-
-			thread::spawn(move || {
-				thread::sleep(timeout);
-				handle.terminate_execution();
-			});
-		}
+		let timeout_thread = self.timeout.map(|timeout| {
+			TimeoutThread::start(self.runtime.v8_isolate().thread_safe_handle(), timeout)
+		});
+		let deadline = self
+			.timeout
+			.map(|timeout| tokio::time::Instant::now() + timeout);
 
 		// syncing ops is required cause they sometimes change while preparing the engine
 		// self.runtime.sync_ops_cache();
 
 		// TODO use strongly typed JsError here (downcast)
-		self.runtime
-			.execute_script(Self::DEFAULT_FILENAME, js_code.into())?;
-		deno_core::futures::executor::block_on(self.runtime.run_event_loop(false))?;
+		let result = match self
+			.runtime
+			.execute_script(Self::CALL_FILENAME, js_code.into())
+		{
+			Ok(_) => {
+				self.run_event_loop_until(deadline, true, |state| {
+					state.borrow::<CallResult>().value.is_some()
+				})
+				.await
+			}
+			Err(e) => Err(e.into()),
+		};
+
+		// Execution may have been terminated just as the call completed; either way, the script must be usable again
+		let terminated = timeout_thread.map_or(false, TimeoutThread::stop);
+		if terminated {
+			self.runtime.v8_isolate().cancel_terminate_execution();
+		}
 
+		if let Err(e) = result {
+			// Timers and requests of a script that timed out are cancelled, like the call itself
+			if terminated || deadline.map_or(false, |d| d <= tokio::time::Instant::now()) {
+				self.cancel_timers();
+				self.cancel_requests();
+			}
+			return Err(e);
+		}
+
+		// The event loop also stops once there is nothing left to do, e.g. for an async function awaiting a promise that
+		// nothing will ever resolve
 		let state_rc = self.runtime.op_state();
 		let mut state = state_rc.borrow_mut();
-		let table = &mut state.resource_table;
+		state
+			.borrow_mut::<CallResult>()
+			.value
+			.take()
+			.ok_or_else(|| JsError::Runtime(anyhow!("call failed: promise never settled")))
+	}
 
-		// Get resource, and free slot (no longer needed)
-		let entry: Rc<ResultResource> = table
-			.take(self.last_rid)
-			.expect("Resource entry must be present");
-		let extracted =
-			Rc::try_unwrap(entry).expect("Rc must hold single strong ref to resource entry");
-		self.last_rid += 1;
+	/// Drives the event loop until `done` holds, or until there is no work left.
+	///
+	/// Fails once `deadline` has passed, as the timeout thread cannot interrupt a script that is waiting rather than running.
+	/// With a virtual clock and `jump_clock`, time jumps to the next timer whenever the loop would otherwise wait.
+	async fn run_event_loop_until(
+		&mut self,
+		deadline: Option<tokio::time::Instant>,
+		jump_clock: bool,
+		done: impl Fn(&OpState) -> bool,
+	) -> Result<(), JsError> {
+		let mut timeout = deadline.map(|deadline| Box::pin(tokio::time::sleep_until(deadline)));
+
+		futures::future::poll_fn(|cx| {
+			if let Poll::Ready(result) = self.runtime.poll_event_loop(cx, false) {
+				return Poll::Ready(result.map_err(JsError::from));
+			}
+
+			let state_rc = self.runtime.op_state();
+			let mut state = state_rc.borrow_mut();
+			if done(&state) {
+				return Poll::Ready(Ok(()));
+			}
+
+			if let Some(timeout) = &mut timeout {
+				if timeout.as_mut().poll(cx).is_ready() {
+					return Poll::Ready(Err(JsError::Runtime(anyhow!("execution terminated"))));
+				}
+			}
+
+			let timers = state.borrow_mut::<Timers>();
+			if timers.has_due() || (jump_clock && timers.jump_to_next()) {
+				cx.waker().wake_by_ref();
+			}
+			Poll::Pending
+		})
+		.await
+	}
+
+	// ----------------------------------------------------------------------------------------------------------------------------------------------
+	// Timers
 
-		Ok(extracted.json_value)
+	/// Moves the virtual clock forward by `duration`, running the timers that become due on the way.
+	///
+	/// Timers fire in order, each with the clock set to its deadline; intervals fire as often as they would in real time.
+	/// Timers started by the callbacks run as well if they are due before the end of `duration`.
+	///
+	/// During calls, a virtual clock also jumps ahead by itself whenever the script waits for a timer, so e.g. a retry with
+	/// backoff completes instantly. This method is for timers left pending after a call.
+	///
+	/// Panics if the script was not built with [`ScriptBuilder::virtual_clock()`].
+	pub fn advance_time(&mut self, duration: Duration) -> Result<(), JsError> {
+		block_on(self.advance_time_async(duration))
 	}
 
-	fn rd_create_run_time() -> Result<Self, JsError> {
-		let ext = Extension::builder("script")
-			.ops(vec![(op_return::decl())])
-			.build();
+	/// Async version of [`Self::advance_time()`].
+	pub async fn advance_time_async(&mut self, duration: Duration) -> Result<(), JsError> {
+		let end = {
+			let state = self.runtime.op_state();
+			let state = state.borrow();
+			let timers = state.borrow::<Timers>();
+			assert!(
+				timers.is_virtual(),
+				"advance_time() requires a script with virtual clock"
+			);
+
+			timers.now() + duration
+		};
 
-		let js_runtime = JsRuntime::new(deno_core::RuntimeOptions {
-			module_loader: Some(Rc::new(deno_core::FsModuleLoader)),
-			extensions: vec![ext],
-			..Default::default()
-		});
+		loop {
+			let next = {
+				let state = self.runtime.op_state();
+				let mut state = state.borrow_mut();
+				let timers = state.borrow_mut::<Timers>();
 
-		Ok(Script {
-			runtime: js_runtime,
-			last_rid: 0,
-			timeout: None,
-		})
+				let next = timers.next_deadline().filter(|&deadline| deadline <= end);
+				timers.set_virtual_now(next.unwrap_or(end).max(timers.now()));
+				next
+			};
+
+			self.run_event_loop_until(None, false, |state| !state.borrow::<Timers>().has_due())
+				.await?;
+
+			if next.is_none() {
+				return Ok(());
+			}
+		}
+	}
+
+	fn cancel_timers(&mut self) {
+		let state = self.runtime.op_state();
+		let mut state = state.borrow_mut();
+		state.borrow_mut::<Timers>().cancel_all();
+	}
+
+	fn cancel_requests(&mut self) {
+		let state = self.runtime.op_state();
+		let mut state = state.borrow_mut();
+		if let Some(fetch) = state.try_borrow_mut::<FetchState>() {
+			fetch.cancel_all();
+		}
+	}
+
+	// ----------------------------------------------------------------------------------------------------------------------------------------------
+	// Permissions
+
+	/// Replaces the script's permissions, e.g. when a pooled script is handed to another tenant.
+	pub fn set_permissions(&mut self, permissions: Permissions) {
+		let state = self.runtime.op_state();
+		let mut state = state.borrow_mut();
+		state
+			.borrow_mut::<PermissionState>()
+			.set_permissions(permissions);
+	}
+
+	/// Returns the accesses denied since the last call, oldest first, and clears the log.
+	///
+	/// Denials are recorded even if the script catches the `PermissionDenied` error. At most 1000 are kept between calls.
+	pub fn take_denials(&mut self) -> Vec<PermissionDenied> {
+		let state = self.runtime.op_state();
+		let mut state = state.borrow_mut();
+		state.borrow_mut::<PermissionState>().take_denials()
+	}
+
+	// ----------------------------------------------------------------------------------------------------------------------------------------------
+	// Global variables
+
+	/// Assigns a value to the global variable `name`, serialized via JSON.
+	///
+	/// If the script declared `name` at top level with `let`, that binding is updated. Otherwise, the value ends up as a property of
+	/// `globalThis`. Assigning to a `const` binding fails with a JS `TypeError`.
+	pub fn set_global<T>(&mut self, name: &str, value: T) -> Result<(), JsError>
+	where
+		T: Serialize,
+	{
+		check_identifier(name)?;
+		let json_value = serde_json::to_string(&value)?;
+
+		// Sloppy-mode assignment resolves lexical bindings first and falls back to creating a global property
+		self.eval_json(&format!("{name} = ({json_value}), undefined"))?;
+		Ok(())
+	}
+
+	/// Reads the global variable `name` and deserializes it from JSON.
+	///
+	/// Top-level `let`/`const` bindings are found as well as properties of `globalThis`. A variable that does not exist reads as `null`,
+	/// so use `Option<T>` if it may be absent.
+	pub fn get_global<T>(&mut self, name: &str) -> Result<T, JsError>
+	where
+		T: DeserializeOwned,
+	{
+		check_identifier(name)?;
+		let json_value = self.eval_json(&format!(
+			"(() => {{
+				try {{
+					return {name};
+				}} catch (e) {{
+					if (e instanceof ReferenceError) return undefined;
+					throw e;
+				}}
+			}})()"
+		))?;
+
+		Ok(serde_json::from_value(json_value)?)
+	}
+
+	/// Checks whether a global variable `name` exists, either as top-level `let`/`const` binding or as property of `globalThis`.
+	///
+	/// A variable that exists but holds `undefined` counts as existing.
+	pub fn has_global(&mut self, name: &str) -> Result<bool, JsError> {
+		check_identifier(name)?;
+		let json_value = self.eval_json(&format!(
+			"(() => {{
+				try {{
+					{name};
+					return true;
+				}} catch (e) {{
+					if (e instanceof ReferenceError) return false;
+					throw e;
+				}}
+			}})()"
+		))?;
+
+		Ok(serde_json::from_value(json_value)?)
+	}
+
+	/// Removes the global variable `name` from `globalThis`.
+	///
+	/// Returns `false` if there is no such variable, or if it cannot be deleted (e.g. a top-level `var` declaration).
+	/// Top-level `let`/`const` bindings cannot be removed in JS at all; attempting to do so results in an error.
+	pub fn delete_global(&mut self, name: &str) -> Result<bool, JsError> {
+		check_identifier(name)?;
+		let json_value = self.eval_json(&format!(
+			"(() => {{
+				if (Object.prototype.hasOwnProperty.call(globalThis, '{name}'))
+					return delete globalThis['{name}'];
+				if ('{name}' in globalThis)
+					return false;
+
+				try {{
+					{name};
+				}} catch (e) {{
+					if (e instanceof ReferenceError) return false;
+					throw e;
+				}}
+				throw new TypeError(\"Cannot delete lexical binding '{name}'; assign undefined instead\");
+			}})()"
+		))?;
+
+		Ok(serde_json::from_value(json_value)?)
 	}
 
-	fn rd_run_script(&mut self, js_code: String) -> Result<v8::Global<v8::Value>, JsError> {
-		let value: v8::Global<v8::Value> = self
+	/// Evaluates a JS expression in the global scope and returns its value converted to JSON.
+	///
+	/// `undefined` and other values without JSON representation are returned as `null`.
+	fn eval_json(&mut self, js_expr: &str) -> Result<JsValue, JsError> {
+		let js_code = format!(
+			"(() => {{
+				const __rust_value = ({js_expr});
+				return JSON.stringify(__rust_value) ?? 'null';
+			}})()"
+		);
+
+		let global = self
 			.runtime
-			.execute_script(Self::DEFAULT_FILENAME, js_code.into())?;
+			.execute_script(Self::CALL_FILENAME, js_code.into())?;
+
+		let scope = &mut self.runtime.handle_scope();
+		let local = v8::Local::new(scope, global);
+		let json_string = local.to_rust_string_lossy(scope);
 
-		Ok(value)
+		Ok(serde_json::from_str(&json_string)?)
+	}
+
+	fn rd_create_run_time(builder: ScriptBuilder) -> Result<Self, JsError> {
+		let source_maps = SourceMapStore::default();
+		let module_loader = builder.create_module_loader(&source_maps)?;
+		let mut js_runtime = Self::new_runtime(module_loader, &source_maps, None, &builder)?;
+
+		// console.log() is not available by default -- install it once, as its own script, for all code run later
+		js_runtime.execute_script(Self::CONSOLE_FILENAME, CONSOLE_JS.into())?;
+
+		let file_name = builder.file_name.clone();
+		let mut script = Self::from_runtime(js_runtime, builder);
+		if let Some(file_name) = file_name {
+			script.file_name = file_name;
+		}
+		Ok(script)
 	}
 
 	pub fn rd_load_module(&mut self, main_url: &str) -> Result<(), Error> {
 		println!("Run {main_url}");
 
-		let runtime = tokio::runtime::Builder::new_current_thread()
-			.enable_all()
-			.build()?;
+		block_on(self.load_module_async(main_url)).map_err(Error::from)
+	}
 
+	/// Loads and evaluates an ES module in this script's runtime, for its side effects. Awaits the module's evaluation,
+	/// including top-level `await`, on the caller's executor.
+	///
+	/// `main_url` is a file path relative to the working directory, or a `file://` URL. To call a module's exports, create
+	/// the script with [`Self::from_module()`] instead.
+	pub async fn load_module_async(&mut self, main_url: &str) -> Result<(), JsError> {
 		let main_module = deno_core::resolve_path(
 			main_url,
 			&std::env::current_dir().context("Unable to get CWD")?,
-		)?;
+		)
+		.map_err(AnyError::from)?;
+
+		let mod_id = self
+			.runtime
+			.load_main_module(&main_module, None)
+			.await
+			.map_err(|e| Self::explain_module_error(&mut self.runtime, e))?;
+		let result = self.runtime.mod_evaluate(mod_id);
+		self.runtime.run_event_loop(false).await?;
+		result.await.map_err(AnyError::from)??;
 
-		let future = async move {
-			let mod_id = self.runtime.load_main_module(&main_module, None).await?;
-			let result = self.runtime.mod_evaluate(mod_id);
-			self.runtime.run_event_loop(false).await?;
-			result.await?
+		Ok(())
+	}
+
+	/// Creates a classic script from source code; `with_console` installs the basic `console.log()` first.
+	pub(crate) fn create_script(
+		js_code: String,
+		with_console: bool,
+		builder: ScriptBuilder,
+	) -> Result<Self, JsError> {
+		let file_name = builder
+			.file_name
+			.clone()
+			.unwrap_or_else(|| Self::DEFAULT_FILENAME.to_string());
+
+		let source_maps = SourceMapStore::default();
+		source_maps.insert_from_comment(&file_name, &js_code, None);
+
+		Self::create_script_with_source_maps(js_code, file_name, with_console, builder, source_maps)
+	}
+
+	/// Loads a classic script from `file`, transpiling it first if it is written in TypeScript.
+	pub(crate) fn create_script_from_file(
+		file: &Path,
+		builder: ScriptBuilder,
+	) -> Result<Self, JsError> {
+		let js_code = match std::fs::read_to_string(file) {
+			Ok(js_code) => js_code,
+			Err(e) => return Err(JsError::Runtime(AnyError::from(e))),
 		};
-		runtime.block_on(future)
+
+		let file_name = builder
+			.file_name
+			.clone()
+			.unwrap_or_else(|| file.display().to_string());
+
+		let source_maps = SourceMapStore::default();
+		// A bare file name has an empty parent, which cannot be canonicalized
+		let dir = file.parent().map(|dir| {
+			if dir.as_os_str().is_empty() {
+				Path::new(".")
+			} else {
+				dir
+			}
+		});
+		source_maps.insert_from_comment(&file_name, &js_code, dir);
+		let js_code = Self::transpile_script(file, &file_name, js_code, &source_maps)?;
+
+		Self::create_script_with_source_maps(js_code, file_name, false, builder, source_maps)
 	}
 
-	fn create_script(js_code: String) -> Result<Self, JsError> {
-		let ext = Extension::builder("script")
-			.ops(vec![(op_return::decl())])
-			.build();
+	#[cfg(feature = "typescript")]
+	fn transpile_script(
+		file: &Path,
+		file_name: &str,
+		js_code: String,
+		source_maps: &SourceMapStore,
+	) -> Result<String, JsError> {
+		let cwd = std::env::current_dir().context("Unable to get CWD")?;
+		let url = deno_core::resolve_path(&file.to_string_lossy(), &cwd).map_err(AnyError::from)?;
+		if !crate::typescript::needs_transpile(&url) {
+			return Ok(js_code);
+		}
 
-		let mut isolate = JsRuntime::new(deno_core::RuntimeOptions {
-			module_loader: Some(Rc::new(deno_core::FsModuleLoader)),
-			extensions: vec![ext],
-			..Default::default()
+		let (code, source_map) = crate::typescript::transpile(&url, &js_code, false)?;
+		if let Some(source_map) = source_map {
+			source_maps.insert_source_map(file_name, source_map.into_bytes());
+		}
+		source_maps.insert_source(url.as_str(), js_code);
+
+		Ok(code)
+	}
+
+	#[cfg(not(feature = "typescript"))]
+	fn transpile_script(
+		file: &Path,
+		_file_name: &str,
+		js_code: String,
+		_source_maps: &SourceMapStore,
+	) -> Result<String, JsError> {
+		let is_typescript = file.extension().map_or(false, |ext| {
+			["ts", "tsx", "mts", "cts"]
+				.iter()
+				.any(|ts| ext.eq_ignore_ascii_case(ts))
 		});
 
-		// We cannot provide a dynamic filename because execute_script() requires a &'static str
-		isolate.execute_script(Self::DEFAULT_FILENAME, js_code.into())?;
+		if is_typescript {
+			Err(JsError::Runtime(anyhow!(
+				"{} is a TypeScript file; enable the \"typescript\" feature of js-sandbox to load it",
+				file.display()
+			)))
+		} else {
+			Ok(js_code)
+		}
+	}
+
+	fn create_script_with_source_maps(
+		js_code: String,
+		file_name: String,
+		with_console: bool,
+		builder: ScriptBuilder,
+		source_maps: SourceMapStore,
+	) -> Result<Self, JsError> {
+		let module_loader = builder.create_module_loader(&source_maps)?;
+		let mut isolate = Self::new_runtime(module_loader, &source_maps, None, &builder)?;
+
+		// Run as separate script, so that it does not shift line numbers of the user code.
+		// Top-level declarations are shared between classic scripts, so `console` remains visible.
+		if with_console {
+			isolate.execute_script(Self::CONSOLE_FILENAME, CONSOLE_JS.into())?;
+		}
+		source_maps.insert_source(&file_name, js_code.clone());
+		Self::execute_named(&mut isolate, &file_name, &js_code)?;
+
+		let mut script = Self::from_runtime(isolate, builder);
+		script.file_name = file_name;
+		Ok(script)
+	}
+
+	/// Runs a classic script like `JsRuntime::execute_script()`, but under a file name determined at runtime.
+	///
+	/// The name appears in error messages and stack traces, and is used to look up source maps. Returns the script's
+	/// completion value.
+	fn execute_named(
+		runtime: &mut JsRuntime,
+		file_name: &str,
+		js_code: &str,
+	) -> Result<v8::Global<v8::Value>, JsError> {
+		let scope = &mut runtime.handle_scope();
+		let scope = &mut v8::TryCatch::new(scope);
+
+		let source = v8::String::new(scope, js_code)
+			.ok_or_else(|| anyhow!("Code of {file_name} is too large"))?;
+		let name = v8::String::new(scope, file_name)
+			.ok_or_else(|| anyhow!("File name {file_name} is too large"))?;
+		let source_map_url = v8::undefined(scope).into();
+		let origin = v8::ScriptOrigin::new(
+			scope,
+			name.into(),
+			0,
+			0,
+			false,
+			0,
+			source_map_url,
+			false,
+			false,
+			false,
+		);
+
+		let completed =
+			v8::Script::compile(scope, source, Some(&origin)).and_then(|script| script.run(scope));
+		if let Some(value) = completed {
+			return Ok(v8::Global::new(scope, value));
+		}
+
+		let exception = match scope.exception() {
+			Some(exception) => exception,
+			None => return Err(JsError::Runtime(anyhow!("execution terminated"))),
+		};
+		let mut error = deno_core::error::JsError::from_v8_exception(scope, exception);
+
+		// Syntax errors have no stack; take their location from the message, so it can be shown and rendered
+		if error.frames.is_empty() {
+			if let Some(message) = scope.message() {
+				let line = message.get_line_number(scope).map(|line| line as i64);
+				let column = Some(message.get_start_column() as i64 + 1);
+
+				error.frames.push(JsStackFrame::from_location(
+					Some(file_name.to_string()),
+					line,
+					column,
+				));
+				error.source_line = message
+					.get_source_line(scope)
+					.map(|source_line| source_line.to_rust_string_lossy(scope));
+				error.source_line_frame_index = Some(0);
+			}
+		}
+
+		Err(JsError::Runtime(error.into()))
+	}
+
+	fn from_runtime(runtime: JsRuntime, builder: ScriptBuilder) -> Self {
+		Script {
+			runtime,
+			last_call: 0,
+			timeout: builder.timeout,
+			module: None,
+			file_name: Self::DEFAULT_FILENAME.to_string(),
+		}
+	}
+
+	/// Loads and evaluates `main_module`, returning its namespace object.
+	async fn load_module(
+		runtime: &mut JsRuntime,
+		main_module: &ModuleSpecifier,
+	) -> Result<v8::Global<v8::Object>, AnyError> {
+		let mod_id = runtime
+			.load_main_module(main_module, None)
+			.await
+			.map_err(|e| Self::explain_module_error(runtime, e))?;
+		let result = runtime.mod_evaluate(mod_id);
+		runtime.run_event_loop(false).await?;
+		result.await??;
+
+		runtime.get_module_namespace(mod_id)
+	}
 
-		Ok(Script {
-			runtime: isolate,
-			last_rid: 0,
-			timeout: None,
+	/// Points out why loading a module may have failed if V8 does not accept import attributes.
+	fn explain_module_error(runtime: &mut JsRuntime, error: AnyError) -> AnyError {
+		if Self::import_attributes_enabled(runtime) {
+			return error;
+		}
+
+		error.context(
+			"import attributes such as `with { type: \"json\" }` are unavailable: V8 was started before js_sandbox could \
+			enable them with --harmony-import-attributes. Create the first JsRuntime of the process with js_sandbox, or set \
+			the flag before V8 starts",
+		)
+	}
+
+	/// Whether V8 accepts `import ... with { ... }`, probed once per process.
+	///
+	/// V8 flags only take effect before V8 starts, which may have happened before [`Self::init_v8()`], e.g. when another
+	/// crate created a `JsRuntime` first.
+	fn import_attributes_enabled(runtime: &mut JsRuntime) -> bool {
+		static ENABLED: OnceLock<bool> = OnceLock::new();
+
+		*ENABLED.get_or_init(|| {
+			let scope = &mut runtime.handle_scope();
+			let scope = &mut v8::TryCatch::new(scope);
+
+			let code = v8::String::new(
+				scope,
+				"import data from './probe.json' with { type: 'json' };",
+			)
+			.unwrap();
+			let name = v8::String::new(scope, "js_sandbox:probe.js").unwrap();
+			let source_map_url = v8::undefined(scope).into();
+			let origin = v8::ScriptOrigin::new(
+				scope,
+				name.into(),
+				0,
+				0,
+				false,
+				0,
+				source_map_url,
+				false,
+				false,
+				true,
+			);
+
+			let source = v8::script_compiler::Source::new(code, Some(&origin));
+			v8::script_compiler::compile_module(scope, source).is_some()
 		})
 	}
 
+	/// Creates a runtime with the crate's ops registered and the host API (`host.on()` etc.) and timers installed.
+	///
+	/// Errors and stack traces are mapped to original sources (e.g. TypeScript) through `source_maps`.
+	/// A runtime restored from `snapshot` already contains the host API.
+	fn new_runtime(
+		module_loader: Rc<dyn ModuleLoader>,
+		source_maps: &SourceMapStore,
+		snapshot: Option<Snapshot>,
+		builder: &ScriptBuilder,
+	) -> Result<JsRuntime, JsError> {
+		Self::init_v8();
+
+		let from_snapshot = snapshot.is_some();
+		let mut runtime = JsRuntime::new(deno_core::RuntimeOptions {
+			module_loader: Some(module_loader.clone()),
+			extensions: Self::extensions(),
+			source_map_getter: Some(Box::new(source_maps.clone())),
+			get_error_class_fn: Some(&permissions::get_error_class),
+			startup_snapshot: snapshot,
+			..Default::default()
+		});
+
+		Self::put_op_state(&mut runtime, module_loader, builder)?;
+
+		if !from_snapshot {
+			Self::install_globals(&mut runtime, builder)?;
+		}
+		Ok(runtime)
+	}
+
+	/// Stores the state of timers and other ops, as configured by `builder`. `module_loader` serves `require()`.
+	fn put_op_state(
+		runtime: &mut JsRuntime,
+		module_loader: Rc<dyn ModuleLoader>,
+		builder: &ScriptBuilder,
+	) -> Result<(), JsError> {
+		let state = runtime.op_state();
+		let mut state = state.borrow_mut();
+
+		state.put(Timers::new(builder.timers.clone()));
+		if let Some(config) = &builder.deterministic {
+			state.put(Deterministic::new(config));
+		}
+		state.put(PermissionState::new(builder.permissions.clone()));
+		if let Some(node) = &builder.node {
+			let custom_loader = builder.module_loader.is_some();
+			state.put(NodeState::new(node.clone(), module_loader, custom_loader)?);
+		}
+		if let Some(fs) = builder.host_fs_state() {
+			state.put(fs);
+		}
+		if let Some(fetch) = &builder.fetch {
+			state.put(FetchState::new(fetch.clone()));
+		}
+		if let Some(kv) = &builder.kv {
+			state.put(kv.clone());
+		}
+		if let Some(sql) = &builder.sql {
+			state.put(sql.clone());
+		}
+
+		Ok(())
+	}
+
+	/// Runs the JS parts of the host API, timers, deterministic mode, web APIs, Node compatibility, `host.fs`, `fetch()`,
+	/// `host.kv` and SQL access. These are part of snapshots.
+	fn install_globals(runtime: &mut JsRuntime, builder: &ScriptBuilder) -> Result<(), JsError> {
+		runtime.execute_script(Self::HOST_FILENAME, HOST_JS.into())?;
+		runtime.execute_script(Self::TIMERS_FILENAME, TIMERS_JS.into())?;
+		if builder.deterministic.is_some() {
+			runtime.execute_script(Self::DETERMINISTIC_FILENAME, DETERMINISTIC_JS.into())?;
+		}
+		#[cfg(feature = "web")]
+		if builder.web_apis {
+			runtime.execute_script(Self::WEB_FILENAME, WEB_JS.into())?;
+		}
+		if builder.node.is_some() {
+			runtime.execute_script(Self::NODE_FILENAME, NODE_JS.into())?;
+		}
+		if builder.host_fs_state().is_some() {
+			runtime.execute_script(Self::FS_FILENAME, FS_JS.into())?;
+		}
+		if builder.fetch.is_some() {
+			runtime.execute_script(Self::FETCH_FILENAME, FETCH_JS.into())?;
+		}
+		if builder.kv.is_some() {
+			runtime.execute_script(Self::KV_FILENAME, KV_JS.into())?;
+		}
+		if builder.sql.is_some() {
+			runtime.execute_script(Self::SQL_FILENAME, SQL_JS.into())?;
+		}
+
+		Ok(())
+	}
+
+	/// Extensions with the ops available to scripts.
+	///
+	/// Runtimes restored from a snapshot must be given the same ops in the same order, as V8 refers to them by index.
+	fn extensions() -> Vec<Extension> {
+		let mut ops = vec![
+			op_return::decl(),
+			op_timer_start::decl(),
+			op_timer_cancel::decl(),
+			op_timer_now::decl(),
+			op_timer_wait::decl(),
+			op_deterministic_random::decl(),
+			op_deterministic_date_now::decl(),
+		];
+
+		#[cfg(feature = "web")]
+		ops.extend(crate::web::ops());
+		ops.extend(crate::node::ops());
+		ops.extend(crate::host_fs::ops());
+		ops.extend(crate::fetch::ops());
+		ops.extend(crate::kv::ops());
+		ops.extend(crate::sql::ops());
+
+		let ext = Extension::builder("script").ops(ops).build();
+		vec![ext]
+	}
+
+	/// Sets the V8 flags the crate needs. They only take effect if V8 has not started yet; module loading errors point out
+	/// if they did not, see [`Self::import_attributes_enabled()`].
+	fn init_v8() {
+		// `import ... with { type: "json" }` is still behind a flag in this V8 version; flags must be set before V8 starts
+		static V8_FLAGS: Once = Once::new();
+		V8_FLAGS.call_once(|| {
+			deno_core::v8_set_flags(vec![
+				String::new(),
+				"--harmony-import-attributes".to_string(),
+			]);
+		});
+	}
+
+	pub(crate) fn create_snapshot_with(
+		js_code: &str,
+		builder: &ScriptBuilder,
+	) -> Result<Vec<u8>, JsError> {
+		Self::init_v8();
+
+		let file_name = builder
+			.file_name
+			.clone()
+			.unwrap_or_else(|| Self::DEFAULT_FILENAME.to_string());
+
+		let mut runtime = JsRuntimeForSnapshot::new(
+			deno_core::RuntimeOptions {
+				extensions: Self::extensions(),
+				get_error_class_fn: Some(&permissions::get_error_class),
+				..Default::default()
+			},
+			Default::default(),
+		);
+
+		let module_loader = builder.create_module_loader(&SourceMapStore::default())?;
+		Self::put_op_state(&mut runtime, module_loader, builder)?;
+		Self::install_globals(&mut runtime, builder)?;
+		runtime.execute_script(Self::CONSOLE_FILENAME, CONSOLE_JS.into())?;
+		Self::execute_named(&mut runtime, &file_name, js_code)?;
+
+		block_on(async { Self::settle_for_snapshot(&mut runtime) })?;
+
+		let snapshot = SnapshotData {
+			file_name,
+			js_code: js_code.to_string(),
+			blob: runtime.snapshot().to_vec(),
+		};
+		Ok(snapshot.encode())
+	}
+
+	/// Settles the promises of the initialization, as pending work cannot be captured in a snapshot.
+	///
+	/// Neither can timers: they are cancelled whenever the event loop is polled, which resolves the pending `op_timer_wait`
+	/// with no due timers, so that timers.js drops its callbacks as well. Fails if the event loop still waits for other
+	/// ops, such as `fetch()`, once it makes no more progress on its own.
+	fn settle_for_snapshot(runtime: &mut JsRuntime) -> Result<(), JsError> {
+		// Poll at least once
+		let woken = Arc::new(Woken(AtomicBool::new(true)));
+		let waker = futures::task::waker(woken.clone());
+		let mut cx = std::task::Context::from_waker(&waker);
+
+		while woken.0.swap(false, Ordering::SeqCst) {
+			runtime
+				.op_state()
+				.borrow_mut()
+				.borrow_mut::<Timers>()
+				.cancel_all();
+
+			if let Poll::Ready(result) = runtime.poll_event_loop(&mut cx, false) {
+				return result.map_err(JsError::from);
+			}
+		}
+
+		Err(JsError::Runtime(anyhow!(
+			"Cannot create snapshot: the code still waits for async operations such as fetch(); only settled state can be captured"
+		)))
+	}
+
+	pub(crate) fn create_from_snapshot(
+		snapshot: &[u8],
+		builder: ScriptBuilder,
+	) -> Result<Self, JsError> {
+		let snapshot = SnapshotData::decode(snapshot)?;
+
+		let source_maps = SourceMapStore::default();
+		source_maps.insert_source(&snapshot.file_name, snapshot.js_code);
+
+		let module_loader = builder.create_module_loader(&source_maps)?;
+		let runtime = Self::new_runtime(
+			module_loader,
+			&source_maps,
+			Some(Snapshot::Boxed(snapshot.blob.into_boxed_slice())),
+			&builder,
+		)?;
+
+		let mut script = Self::from_runtime(runtime, builder);
+		script.file_name = snapshot.file_name;
+		Ok(script)
+	}
+
 	// pub fn add_exposed_object(&mut self, obj:ExposedObject)
 	// {
 	// 	let mut scope = self.runtime.handle_scope();
@@ -335,6 +1325,11 @@ impl Script {
 	// 	runtime_clone
 	// }
 
+	/// Exposes the host function `A` as global function to JS.
+	///
+	/// Calls throw `PermissionDenied` unless the function's [group](ExposedFunction::group) is allowed by the script's
+	/// permissions, see [`ScriptBuilder::permissions()`] and
+	/// [`rd_get_run_time_with_permissions()`](Self::rd_get_run_time_with_permissions).
 	pub fn add_exposed_func<A>(&mut self)
 	where
 		A: ExposedFunction,
@@ -345,13 +1340,23 @@ impl Script {
 		let scope = &mut v8::ContextScope::new(&mut scope, context);
 
 		let my_func_key = v8::String::new(scope, A::name().as_str()).unwrap();
-		println!("my_func_key is ${:?}", my_func_key);
 
 		let my_func_templ = v8::FunctionTemplate::new(
 			scope,
 			|scope: &mut v8::HandleScope,
 			 args: v8::FunctionCallbackArguments,
-			 rv: v8::ReturnValue| { A::rust_func_for_js(scope, args, rv) },
+			 rv: v8::ReturnValue| {
+				let state = JsRuntime::op_state_from(scope);
+				let checked = state
+					.borrow_mut()
+					.borrow_mut::<PermissionState>()
+					.check_host_functions(&A::group());
+
+				match checked {
+					Ok(()) => A::rust_func_for_js(scope, args, rv),
+					Err(denied) => throw_permission_denied(scope, &denied),
+				}
+			},
 		);
 		let my_func_val = my_func_templ.get_function(scope).unwrap();
 		global.set(scope, my_func_key.into(), my_func_val.into());
@@ -374,8 +1379,8 @@ impl Script {
 	// 		|inner_scope: &mut v8::HandleScope,
 	// 		 inner_args: v8::FunctionCallbackArguments,
 	// 		 inner_rv: v8::ReturnValue| {             tokio::task::spawn_blocking(move || {
-    //             futures::executor::block_on(ExposedObject::call(inner_scope, inner_args, inner_rv))
-    //         }); },
+	//             futures::executor::block_on(ExposedObject::call(inner_scope, inner_args, inner_rv))
+	//         }); },
 	// 	);
 	// 	let my_func_val = my_func_templ.get_function(scope).unwrap();
 	// 	global.set(scope, my_func_key.into(), my_func_val.into());
@@ -403,30 +1408,149 @@ impl Script {
 	// 	let my_func_val = my_func_templ.get_function(scope).unwrap();
 	// 	global.set(scope, my_func_key.into(), my_func_val.into());
 	// }
+}
+
+thread_local! {
+	/// Runtime that drives the blocking API on this thread; shared by all scripts of the thread, created on first use
+	static BLOCKING_RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.expect("tokio runtime can be created");
+}
+
+/// Runs `future` to completion for the blocking API. See [`Script::call_async()`] for the behavior inside tokio.
+///
+/// Fails within a current-thread runtime: blocking its only thread would stall the timers, deadlines and requests of
+/// the script, which are driven by that runtime.
+pub(crate) fn block_on<F, T, E>(future: F) -> Result<T, E>
+where
+	F: Future<Output = Result<T, E>>,
+	E: From<AnyError>,
+{
+	match tokio::runtime::Handle::try_current() {
+		Err(_) => BLOCKING_RUNTIME.with(|runtime| runtime.block_on(future)),
+		Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+			tokio::task::block_in_place(|| {
+				BLOCKING_RUNTIME.with(|runtime| runtime.block_on(future))
+			})
+		}
+		Ok(_) => Err(anyhow!(
+			"the blocking API cannot be used within a current-thread tokio runtime; use the async methods, e.g. Script::call_async()"
+		)
+		.into()),
+	}
+}
+
+/// Throws a `PermissionDenied` error from a host function, like ops do for denied accesses.
+///
+/// The error is an instance of `host.PermissionDenied`, the class ops throw. Should the host API be missing, a plain
+/// `Error` named `PermissionDenied` is thrown instead.
+fn throw_permission_denied(scope: &mut v8::HandleScope, denied: &PermissionDenied) {
+	let message = v8::String::new(scope, &denied.to_string()).unwrap();
+
+	if let Some(class) = permission_denied_class(scope) {
+		// Should the constructor fail, its exception is already pending
+		if let Some(exception) = class.new_instance(scope, &[message.into()]) {
+			scope.throw_exception(exception.into());
+		}
+		return;
+	}
+
+	let exception = v8::Exception::error(scope, message);
+	if let Some(error) = exception.to_object(scope) {
+		let key = v8::String::new(scope, "name").unwrap();
+		let value = v8::String::new(scope, "PermissionDenied").unwrap();
+		error.set(scope, key.into(), value.into());
+	}
+	scope.throw_exception(exception);
+}
+
+/// Looks up `globalThis.host.PermissionDenied`, installed by host.js.
+fn permission_denied_class<'s>(
+	scope: &mut v8::HandleScope<'s>,
+) -> Option<v8::Local<'s, v8::Function>> {
+	let global = scope.get_current_context().global(scope);
+	let host_key = v8::String::new(scope, "host")?;
+	let host = global.get(scope, host_key.into())?;
+	let host = v8::Local::<v8::Object>::try_from(host).ok()?;
+	let class_key = v8::String::new(scope, "PermissionDenied")?;
+	let class = host.get(scope, class_key.into())?;
+
+	v8::Local::<v8::Function>::try_from(class).ok()
+}
 
+/// Makes sure `name` can be spliced into JS code as a plain identifier.
+///
+/// Only ASCII identifiers are accepted; this rules out anything that could change the meaning of the generated code.
+fn check_identifier(name: &str) -> Result<(), JsError> {
+	let mut chars = name.chars();
+	let valid_start =
+		matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$');
+	let valid_rest = chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+
+	if valid_start && valid_rest {
+		Ok(())
+	} else {
+		Err(JsError::Runtime(anyhow!(
+			"'{name}' is not a valid JS identifier"
+		)))
+	}
+}
 
+/// Thread terminating JS execution once a call exceeds the script's timeout
+struct TimeoutThread {
+	stop: mpsc::Sender<()>,
+	thread: thread::JoinHandle<bool>,
 }
 
-#[derive(Debug)]
-struct ResultResource {
-	json_value: JsValue,
+impl TimeoutThread {
+	fn start(isolate: v8::IsolateHandle, timeout: Duration) -> Self {
+		let (stop, stopped) = mpsc::channel();
+
+		let thread = thread::spawn(move || match stopped.recv_timeout(timeout) {
+			Err(RecvTimeoutError::Timeout) => isolate.terminate_execution(),
+			_ => false,
+		});
+
+		Self { stop, thread }
+	}
+
+	/// Stops the thread, so that it cannot interrupt a later call. Returns whether it terminated execution.
+	fn stop(self) -> bool {
+		drop(self.stop);
+		self.thread.join().unwrap_or(false)
+	}
 }
 
-// Type that is stored inside Deno's resource table
-impl deno_core::Resource for ResultResource {
-	fn name(&self) -> Cow<str> {
-		"__rust_Result".into()
+/// Waker recording whether the event loop asked to be polled again
+struct Woken(AtomicBool);
+
+impl futures::task::ArcWake for Woken {
+	fn wake_by_ref(arc_self: &Arc<Self>) {
+		arc_self.0.store(true, Ordering::SeqCst);
 	}
 }
 
+/// Result of a single event handler, as reported by `__rust_emit` in host.js
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum EmitOutcome {
+	Ok(JsValue),
+	Err(String),
+}
+
+/// Result of the current call, set by op_return()
+struct CallResult {
+	token: u32,
+	value: Option<JsValue>,
+}
+
 #[op]
-fn op_return(
-	state: &mut OpState,
-	args: JsValue,
-	_buf: Option<JsBuffer>,
-) -> Result<JsValue, deno_core::error::AnyError> {
-	let entry = ResultResource { json_value: args };
-	let resource_table = &mut state.resource_table;
-	let _rid = resource_table.add(entry);
-	Ok(serde_json::Value::Null)
+fn op_return(state: &mut OpState, token: u32, value: JsValue) {
+	// Results of earlier calls that finish late are dropped
+	if let Some(result) = state.try_borrow_mut::<CallResult>() {
+		if result.token == token {
+			result.value = Some(value);
+		}
+	}
 }
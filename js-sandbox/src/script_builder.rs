@@ -0,0 +1,325 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use deno_core::anyhow::Context;
+use deno_core::url::Url;
+use deno_core::{ModuleLoader, ResolutionKind};
+
+use crate::deterministic::DeterministicConfig;
+use crate::fetch::Fetch;
+use crate::host_fs::HostFs;
+use crate::kv::HostKv;
+use crate::module_loader::{ImportMapLoader, RawModuleLoader, VirtualFsModuleLoader};
+use crate::node::NodeCompat;
+use crate::permissions::Permissions;
+use crate::source_map::{SourceMapStore, SourceMappingLoader};
+use crate::sql::HostSql;
+use crate::timers::TimerConfig;
+use crate::{AnyError, ImportMap, JsError, Script, VirtualFs};
+
+/// Configures a [`Script`] before its JS runtime is created.
+///
+/// Some settings, such as the module loader, shape the runtime itself and cannot be changed once a script exists.
+/// The `Script::from_*()` constructors are shorthands for a builder with default settings.
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use js_sandbox::{MemoryModuleLoader, Script, AnyError};
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let modules = HashMap::from([
+/// 		("main.js".to_string(), "import { sub } from './math.js'; export { sub };".to_string()),
+/// 		("math.js".to_string(), "export function sub(a, b) { return a - b; }".to_string()),
+/// 	]);
+///
+/// 	let mut script = Script::builder()
+/// 		.module_loader(MemoryModuleLoader::new(modules))
+/// 		.build_from_module("main.js")?;
+///
+/// 	let result: i32 = script.call("sub", (7, 5))?;
+/// 	assert_eq!(result, 2);
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Default)]
+pub struct ScriptBuilder {
+	pub(crate) module_loader: Option<Rc<dyn ModuleLoader>>,
+	pub(crate) import_map: Option<ImportMap>,
+	pub(crate) text_imports: bool,
+	pub(crate) timeout: Option<Duration>,
+	pub(crate) file_name: Option<String>,
+	pub(crate) timers: TimerConfig,
+	pub(crate) deterministic: Option<DeterministicConfig>,
+	#[cfg(feature = "web")]
+	pub(crate) web_apis: bool,
+	pub(crate) node: Option<NodeCompat>,
+	pub(crate) permissions: Permissions,
+	pub(crate) host_fs: Option<HostFs>,
+	pub(crate) virtual_fs: Option<VirtualFs>,
+	pub(crate) fetch: Option<Fetch>,
+	pub(crate) kv: Option<HostKv>,
+	pub(crate) sql: Option<HostSql>,
+}
+
+impl ScriptBuilder {
+	/// Creates a builder with default settings; equivalent to [`Script::builder()`].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Resolves and loads ES modules through `loader`.
+	///
+	/// By default, modules are read from the file system with Deno's `FsModuleLoader`.
+	pub fn module_loader<L>(mut self, loader: L) -> Self
+	where
+		L: ModuleLoader + 'static,
+	{
+		self.module_loader = Some(Rc::new(loader));
+		self
+	}
+
+	/// Resolves bare specifiers like `"@inoerp/sdk"` through `import_map`, for whichever module loader is in use.
+	pub fn import_map(mut self, import_map: ImportMap) -> Self {
+		self.import_map = Some(import_map);
+		self
+	}
+
+	/// Lets modules import text files as strings: `import template from "./invoice.html" with { type: "text" };`, or
+	/// `import()` with `{ with: { type: "text" } }`.
+	///
+	/// Text files are loaded through the [`module_loader()`](Self::module_loader), which must be set: building fails with
+	/// the default `FsModuleLoader`, as it would let scripts read any file of the host. Imports with a `?raw` query are
+	/// rejected, as that query marks text imports internally.
+	pub fn text_imports(mut self) -> Self {
+		self.text_imports = true;
+		self
+	}
+
+	/// Aborts any function call after the specified duration. See [`Script::with_timeout()`] for details.
+	///
+	/// Panics with invalid timeouts.
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		assert!(timeout > Duration::ZERO);
+
+		self.timeout = Some(timeout);
+		self
+	}
+
+	/// Names the script's code in errors and stack traces, e.g. `"plugins/tenant-42/pricing.js"`.
+	///
+	/// Defaults to the path for [`build_from_file()`](Self::build_from_file) and `"sandboxed.js"` for
+	/// [`build_from_string()`](Self::build_from_string). Modules are always named by their URL.
+	pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+		self.file_name = Some(file_name.into());
+		self
+	}
+
+	/// Limits how many timers (`setTimeout()`, `setInterval()`) a script can have pending at once; 1000 by default.
+	///
+	/// Starting another timer throws an error in JS.
+	pub fn max_timers(mut self, max_timers: usize) -> Self {
+		self.timers.max_timers = max_timers;
+		self
+	}
+
+	/// Limits the delay of timers; one hour by default. Longer delays throw an error in JS.
+	pub fn max_timer_delay(mut self, max_delay: Duration) -> Self {
+		self.timers.max_delay = max_delay;
+		self
+	}
+
+	/// Runs timers on a virtual clock instead of real time, for tests.
+	///
+	/// The clock starts at zero and only moves forward when the script waits for a timer during a call, jumping right to
+	/// that timer's deadline, or through [`Script::advance_time()`]. Code waiting for timers thus runs without delay.
+	pub fn virtual_clock(mut self) -> Self {
+		self.timers.virtual_clock = true;
+		self
+	}
+
+	/// Makes execution reproducible: the same code with the same inputs computes the same results, on any machine.
+	///
+	/// * `Math.random()` returns a sequence determined by `seed`.
+	/// * Timers run on a virtual clock, see [`virtual_clock()`](Self::virtual_clock); timers due at the same time fire in the
+	///   order they were started.
+	/// * `Date.now()` and `new Date()` start at `start_time` and follow the virtual clock. They stand still unless the script
+	///   waits for timers or the host moves the clock with [`Script::advance_time()`]. `performance.now()` is the virtual
+	///   time since the script was created.
+	///
+	/// Local time methods such as `Date.prototype.getHours()` still depend on the host's time zone; use the UTC variants
+	/// for portable results. A snapshot keeps the mode it was created with, so build scripts from it with the same settings.
+	pub fn deterministic(mut self, seed: u64, start_time: SystemTime) -> Self {
+		self.deterministic = Some(DeterministicConfig { seed, start_time });
+		self.timers.virtual_clock = true;
+		self
+	}
+
+	/// Provides a subset of web APIs that libraries commonly rely on: `TextEncoder`, `TextDecoder`, `URL`, `URLSearchParams`,
+	/// `atob()`, `btoa()`, `structuredClone()` and `crypto.randomUUID()`.
+	///
+	/// Only UTF-8 is supported by `TextDecoder`. None of these APIs give access to the network or file system.
+	/// In [`deterministic()`](Self::deterministic) mode, `crypto.randomUUID()` uses the seeded generator as well.
+	#[cfg(feature = "web")]
+	pub fn web_apis(mut self) -> Self {
+		self.web_apis = true;
+		self
+	}
+
+	/// Grants the script access to host resources; by default, it has none. See [`Permissions`].
+	pub fn permissions(mut self, permissions: Permissions) -> Self {
+		self.permissions = permissions;
+		self
+	}
+
+	/// Provides Node.js globals for libraries written for Node: `process`, `Buffer` and a CommonJS `require()`.
+	///
+	/// `require()` loads modules through this builder's module loader and import map. See [`NodeCompat`] for details.
+	pub fn node_compat(mut self, node: NodeCompat) -> Self {
+		self.node = Some(node);
+		self
+	}
+
+	/// Gives the script read-only access to the directories mounted in `fs`, through `host.fs`. See [`HostFs`].
+	pub fn host_fs(mut self, fs: HostFs) -> Self {
+		self.host_fs = Some(fs);
+		self
+	}
+
+	/// Shares the in-memory files of `vfs` with the script. See [`VirtualFs`].
+	///
+	/// Scripts read and write them through `host.fs`, on top of directories mounted with [`host_fs()`](Self::host_fs), and
+	/// import them as modules under `vfs:` URLs. The host keeps a clone of `vfs` to access the files.
+	pub fn virtual_fs(mut self, vfs: VirtualFs) -> Self {
+		self.virtual_fs = Some(vfs);
+		self
+	}
+
+	/// Provides `fetch()`, sending requests through `fetch` to the hosts allowed by [`Permissions::allow_net()`]. See
+	/// [`Fetch`].
+	pub fn fetch(mut self, fetch: Fetch) -> Self {
+		self.fetch = Some(fetch);
+		self
+	}
+
+	/// Gives the script a namespace of persistent key-value storage, through `host.kv`. See [`HostKv`].
+	pub fn kv(mut self, kv: HostKv) -> Self {
+		self.kv = Some(kv);
+		self
+	}
+
+	/// Provides `sqlSelect()` and `sqlExecute()`, running parameterized statements with the executor of `sql`. See
+	/// [`HostSql`].
+	pub fn sql(mut self, sql: HostSql) -> Self {
+		self.sql = Some(sql);
+		self
+	}
+
+	/// Creates the script from JavaScript source code. See [`Script::from_string()`].
+	pub fn build_from_string(self, js_code: &str) -> Result<Script, JsError> {
+		Script::create_script(js_code.to_string(), true, self)
+	}
+
+	/// Creates the script by loading a .js file. See [`Script::from_file()`].
+	///
+	/// With the `typescript` feature, .ts and .tsx files are transpiled to JavaScript first.
+	pub fn build_from_file(self, file: impl AsRef<Path>) -> Result<Script, JsError> {
+		Script::create_script_from_file(file.as_ref(), self)
+	}
+
+	/// Creates the script by loading an ES module. See [`Script::from_module()`].
+	///
+	/// With the default loader, `specifier` is a file path relative to the working directory, or a `file://` URL.
+	/// A custom loader resolves `specifier` itself, as main module with the referrer `"."`. With a
+	/// [`virtual_fs()`](Self::virtual_fs), `vfs:` URLs such as `vfs:///main.js` load modules from it.
+	pub fn build_from_module(self, specifier: &str) -> Result<Script, JsError> {
+		crate::script::block_on(Script::create_module(specifier, self))
+	}
+
+	/// Async version of [`build_from_module()`](Self::build_from_module), to be awaited on the caller's tokio runtime.
+	pub async fn build_from_module_async(self, specifier: &str) -> Result<Script, JsError> {
+		Script::create_module(specifier, self).await
+	}
+
+	/// Runs `js_code` and captures the resulting state as snapshot. See [`Script::create_snapshot()`].
+	///
+	/// Only the file name, timer settings, [`deterministic()`](Self::deterministic) mode and the installed globals are taken
+	/// from this builder; other settings apply when building a script from the snapshot.
+	pub fn create_snapshot(&self, js_code: &str) -> Result<Vec<u8>, JsError> {
+		Script::create_snapshot_with(js_code, self)
+	}
+
+	/// Creates the script from a snapshot. See [`Script::from_snapshot()`].
+	///
+	/// The file name is the one the snapshot was created with.
+	pub fn build_from_snapshot(self, snapshot: &[u8]) -> Result<Script, JsError> {
+		Script::create_from_snapshot(snapshot, self)
+	}
+
+	/// State behind `host.fs`: the mounts of [`host_fs()`](Self::host_fs) overlaid with the [`virtual_fs()`](Self::virtual_fs),
+	/// or `None` if neither is set.
+	pub(crate) fn host_fs_state(&self) -> Option<HostFs> {
+		if self.host_fs.is_none() && self.virtual_fs.is_none() {
+			return None;
+		}
+
+		let mut fs = self.host_fs.clone().unwrap_or_default();
+		fs.virtual_fs = self.virtual_fs.clone();
+		Some(fs)
+	}
+
+	/// Returns the module loader for the runtime: the custom one or Deno's `FsModuleLoader`, wrapped to serve `vfs:` and
+	/// text imports and to apply the import map if any.
+	///
+	/// Source maps referenced by modules are registered in `source_maps`, as are those of TypeScript modules transpiled
+	/// with the `typescript` feature.
+	pub(crate) fn create_module_loader(
+		&self,
+		source_maps: &SourceMapStore,
+	) -> Result<Rc<dyn ModuleLoader>, AnyError> {
+		let loader: Rc<dyn ModuleLoader> = match &self.module_loader {
+			Some(loader) => loader.clone(),
+			None => Rc::new(deno_core::FsModuleLoader),
+		};
+		let loader: Rc<dyn ModuleLoader> = match &self.virtual_fs {
+			Some(vfs) => Rc::new(VirtualFsModuleLoader::new(loader, vfs.clone())),
+			None => loader,
+		};
+		let loader: Rc<dyn ModuleLoader> = match (self.text_imports, &self.module_loader) {
+			(true, Some(_)) => Rc::new(RawModuleLoader::new(loader)),
+			(true, None) => {
+				return Err(AnyError::msg(
+					"text imports require a module loader restricting the files scripts can read; see ScriptBuilder::module_loader()",
+				))
+			}
+			(false, _) => loader,
+		};
+		let loader: Rc<dyn ModuleLoader> =
+			Rc::new(SourceMappingLoader::new(loader, source_maps.clone()));
+
+		#[cfg(feature = "typescript")]
+		let loader: Rc<dyn ModuleLoader> = Rc::new(crate::typescript::TranspilingModuleLoader::new(
+			loader,
+			source_maps.clone(),
+		));
+
+		let Some(import_map) = &self.import_map else {
+			return Ok(loader);
+		};
+
+		let base_url = match (import_map.base_url(), &self.module_loader) {
+			(Some(url), _) => url.clone(),
+			(None, Some(custom)) => custom.resolve("./", ".", ResolutionKind::MainModule)?,
+			(None, None) => {
+				let cwd = std::env::current_dir().context("Unable to get CWD")?;
+				Url::from_directory_path(&cwd).map_err(|_| {
+					AnyError::msg(format!("CWD {} is not a valid URL", cwd.display()))
+				})?
+			}
+		};
+
+		Ok(Rc::new(ImportMapLoader::new(loader, import_map, base_url)?))
+	}
+}
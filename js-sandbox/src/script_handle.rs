@@ -0,0 +1,149 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::thread;
+
+use deno_core::anyhow::anyhow;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{CallArgs, JsError, JsValue, Script};
+
+/// Work for the script thread
+type Command = Box<dyn FnOnce(&mut Script) + Send>;
+
+/// A [`Script`] running on its own thread, accessed through messages.
+///
+/// Created with [`Script::spawn()`]. Unlike `Script`, the handle is `Send + Sync` and cheap to clone, so it can be stored
+/// in the state of a server framework such as axum or tonic. All clones talk to the same script; requests are processed
+/// one at a time, in the order they arrive. The thread ends once the last handle is dropped.
+///
+/// For many concurrent requests, see [`ScriptPool`](crate::ScriptPool), which runs several scripts.
+#[derive(Clone)]
+pub struct ScriptHandle {
+	commands: mpsc::UnboundedSender<Command>,
+}
+
+impl ScriptHandle {
+	pub(crate) fn spawn<F>(builder: F) -> Result<Self, JsError>
+	where
+		F: FnOnce() -> Result<Script, JsError> + Send + 'static,
+	{
+		let (commands, mut receiver) = mpsc::unbounded_channel::<Command>();
+		let (ready_sender, ready) = std::sync::mpsc::channel();
+
+		thread::Builder::new()
+			.name("js-sandbox-script".to_string())
+			.spawn(move || {
+				let mut script = match builder() {
+					Ok(script) => {
+						let _ = ready_sender.send(Ok(()));
+						script
+					}
+					Err(e) => {
+						let _ = ready_sender.send(Err(e));
+						return;
+					}
+				};
+
+				while let Some(command) = receiver.blocking_recv() {
+					command(&mut script);
+				}
+			})
+			.map_err(|e| JsError::Runtime(e.into()))?;
+
+		match ready.recv() {
+			Ok(Ok(())) => Ok(Self { commands }),
+			Ok(Err(e)) => Err(e),
+			Err(_) => Err(JsError::Runtime(anyhow!("script thread panicked"))),
+		}
+	}
+
+	/// Invokes a JavaScript function. See [`Script::call()`].
+	pub async fn call<A, R>(&self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		let fn_name = fn_name.to_string();
+		let json_args = args_tuple.into_arg_string()?;
+
+		let json_result = self
+			.run(move |script| script.call_impl(&fn_name, json_args))
+			.await??;
+
+		Ok(serde_json::from_value(json_result)?)
+	}
+
+	/// Assigns a global variable. See [`Script::set_global()`].
+	pub async fn set_global<T>(&self, name: &str, value: T) -> Result<(), JsError>
+	where
+		T: Serialize,
+	{
+		let name = name.to_string();
+		let value: JsValue = serde_json::to_value(value)?;
+
+		self.run(move |script| script.set_global(&name, value))
+			.await?
+	}
+
+	/// Runs `f` on the script's thread, typically to use an API bound with [`Script::bind_api()`].
+	///
+	/// ```rust
+	/// use js_sandbox::{js_api, JsResult, Script, AnyError};
+	///
+	/// #[js_api]
+	/// trait MathApi {
+	/// 	fn triple(&mut self, a: i32) -> JsResult<i32>;
+	/// }
+	///
+	/// # #[tokio::main(flavor = "current_thread")]
+	/// # async fn main() -> Result<(), AnyError> {
+	/// let handle = Script::spawn(|| Script::from_string("function triple(a) { return 3 * a; }"))?;
+	///
+	/// let result = handle
+	/// 	.bind_api(|script| script.bind_api::<MathApi>().triple(5))
+	/// 	.await??;
+	///
+	/// assert_eq!(result, 15);
+	/// # Ok(())
+	/// # }
+	/// ```
+	///
+	/// Fails only if the script thread has stopped, e.g. because an earlier `f` panicked.
+	pub async fn bind_api<F, R>(&self, f: F) -> Result<R, JsError>
+	where
+		F: FnOnce(&mut Script) -> R + Send + 'static,
+		R: Send + 'static,
+	{
+		self.run(f).await
+	}
+
+	/// Same as [`bind_api()`](Self::bind_api), named for closures that use the script directly rather than a bound API.
+	pub async fn with_script<F, R>(&self, f: F) -> Result<R, JsError>
+	where
+		F: FnOnce(&mut Script) -> R + Send + 'static,
+		R: Send + 'static,
+	{
+		self.bind_api(f).await
+	}
+
+	async fn run<F, R>(&self, f: F) -> Result<R, JsError>
+	where
+		F: FnOnce(&mut Script) -> R + Send + 'static,
+		R: Send + 'static,
+	{
+		let (reply, response) = oneshot::channel();
+		let command: Command = Box::new(move |script| {
+			let _ = reply.send(f(script));
+		});
+
+		self.commands
+			.send(command)
+			.map_err(|_| JsError::Runtime(anyhow!("script thread has stopped")))?;
+
+		response
+			.await
+			.map_err(|_| JsError::Runtime(anyhow!("script thread stopped during the request")))
+	}
+}
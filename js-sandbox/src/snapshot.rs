@@ -0,0 +1,81 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use deno_core::anyhow::anyhow;
+
+use crate::AnyError;
+
+/// Marks the start of a snapshot created by this crate
+const MAGIC: &[u8] = b"js-sandbox snapshot\0";
+
+/// A V8 startup snapshot, together with what the script needs besides the V8 heap.
+///
+/// Serialized as: magic, crate version, file name, source code, V8 blob. Strings are prefixed with their length as
+/// little-endian `u32`. The version guards against blobs from another build, which V8 does not reject gracefully.
+pub(crate) struct SnapshotData {
+	pub file_name: String,
+	pub js_code: String,
+	pub blob: Vec<u8>,
+}
+
+impl SnapshotData {
+	pub fn encode(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(
+			MAGIC.len() + self.file_name.len() + self.js_code.len() + self.blob.len() + 32,
+		);
+
+		bytes.extend_from_slice(MAGIC);
+		write_str(&mut bytes, env!("CARGO_PKG_VERSION"));
+		write_str(&mut bytes, &self.file_name);
+		write_str(&mut bytes, &self.js_code);
+		bytes.extend_from_slice(&self.blob);
+		bytes
+	}
+
+	pub fn decode(bytes: &[u8]) -> Result<Self, AnyError> {
+		let rest = bytes
+			.strip_prefix(MAGIC)
+			.ok_or_else(|| anyhow!("Invalid snapshot: not created by Script::create_snapshot()"))?;
+
+		let (version, rest) = read_str(rest)?;
+		if version != env!("CARGO_PKG_VERSION") {
+			return Err(anyhow!(
+				"Invalid snapshot: created by js-sandbox {version}, but this is {}",
+				env!("CARGO_PKG_VERSION")
+			));
+		}
+
+		let (file_name, rest) = read_str(rest)?;
+		let (js_code, blob) = read_str(rest)?;
+
+		Ok(Self {
+			file_name,
+			js_code,
+			blob: blob.to_vec(),
+		})
+	}
+}
+
+fn write_str(bytes: &mut Vec<u8>, s: &str) {
+	let len = u32::try_from(s.len()).expect("string in snapshot exceeds 4 GiB");
+	bytes.extend_from_slice(&len.to_le_bytes());
+	bytes.extend_from_slice(s.as_bytes());
+}
+
+fn read_str(bytes: &[u8]) -> Result<(String, &[u8]), AnyError> {
+	let truncated = || anyhow!("Invalid snapshot: truncated header");
+
+	if bytes.len() < 4 {
+		return Err(truncated());
+	}
+	let (len, rest) = bytes.split_at(4);
+	let len = u32::from_le_bytes(len.try_into().expect("4 bytes")) as usize;
+
+	if rest.len() < len {
+		return Err(truncated());
+	}
+	let (s, rest) = rest.split_at(len);
+
+	let s =
+		String::from_utf8(s.to_vec()).map_err(|_| anyhow!("Invalid snapshot: malformed header"))?;
+	Ok((s, rest))
+}
@@ -0,0 +1,203 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use deno_core::futures::FutureExt;
+use deno_core::{
+	ModuleLoader, ModuleSourceFuture, ModuleSpecifier, ModuleType, ResolutionKind, SourceMapGetter,
+};
+use serde::Deserialize;
+
+use crate::module_loader::{decode_data_url, is_raw_import, path_within};
+use crate::AnyError;
+
+/// Source maps and original sources of the code loaded into a script.
+///
+/// Handed to Deno as [`SourceMapGetter`], which uses it to rewrite error locations and stack traces from generated code
+/// to the original sources. Clones share the same storage, so loaders can register maps after the runtime is created.
+#[derive(Clone, Default)]
+pub(crate) struct SourceMapStore {
+	/// Source map (JSON) by file name of the generated code
+	maps: Rc<RefCell<HashMap<String, Vec<u8>>>>,
+
+	/// Source code by file name: loaded scripts and modules, and original sources referenced from within source maps
+	sources: Rc<RefCell<HashMap<String, String>>>,
+}
+
+/// The parts of a source map needed to show original source lines
+#[derive(Deserialize)]
+struct SourceMapJson {
+	#[serde(default)]
+	sources: Vec<String>,
+	#[serde(default, rename = "sourcesContent")]
+	sources_content: Vec<Option<String>>,
+}
+
+impl SourceMapStore {
+	/// Registers the source map for `file_name`, along with the original sources embedded in it (if any).
+	pub fn insert_source_map(&self, file_name: &str, source_map: Vec<u8>) {
+		if let Ok(json) = serde_json::from_slice::<SourceMapJson>(&source_map) {
+			for (source, content) in json.sources.into_iter().zip(json.sources_content) {
+				if let Some(content) = content {
+					self.insert_source(&source, content);
+				}
+			}
+		}
+
+		self.maps
+			.borrow_mut()
+			.insert(file_name.to_string(), source_map);
+	}
+
+	pub fn insert_source(&self, file_name: &str, code: String) {
+		self.sources
+			.borrow_mut()
+			.insert(file_name.to_string(), code);
+	}
+
+	/// Registers the source map referenced by a `//# sourceMappingURL=` comment in a classic script, if any.
+	///
+	/// Inline `data:` URLs are always honored. Other URLs are read as paths relative to `dir`, the script's directory, and
+	/// must stay within it (symlinks resolved); scripts that did not come from a file only support inline maps. A map that
+	/// cannot be read is ignored.
+	pub fn insert_from_comment(&self, file_name: &str, js_code: &str, dir: Option<&Path>) {
+		let Some(url) = source_mapping_url(js_code) else {
+			return;
+		};
+
+		let source_map = if url.starts_with("data:") {
+			decode_data_url(url).map(|(_, json)| json.into_bytes())
+		} else {
+			dir.and_then(|dir| read_within(dir, url))
+		};
+
+		if let Some(source_map) = source_map {
+			self.insert_source_map(file_name, source_map);
+		}
+	}
+}
+
+impl SourceMapGetter for SourceMapStore {
+	fn get_source_map(&self, file_name: &str) -> Option<Vec<u8>> {
+		self.maps.borrow().get(file_name).cloned()
+	}
+
+	fn get_source_line(&self, file_name: &str, line_number: usize) -> Option<String> {
+		let sources = self.sources.borrow();
+		let line = sources.get(file_name)?.lines().nth(line_number)?;
+		Some(line.to_string())
+	}
+}
+
+/// Reads the file at `url` relative to `dir`, unless it lies outside of `dir`.
+fn read_within(dir: &Path, url: &str) -> Option<Vec<u8>> {
+	let dir = dir.canonicalize().ok()?;
+	let path = path_within(&dir.join(url), &dir).ok()??;
+	std::fs::read(path).ok()
+}
+
+/// Returns the URL of the trailing `//# sourceMappingURL=` comment in `js_code`.
+///
+/// Only comments after the last line of code count, as in browsers.
+fn source_mapping_url(js_code: &str) -> Option<&str> {
+	for line in js_code.lines().rev() {
+		let line = line.trim();
+		let url = line
+			.strip_prefix("//# sourceMappingURL=")
+			.or_else(|| line.strip_prefix("//@ sourceMappingURL="));
+
+		match url {
+			Some(url) => return Some(url.trim()).filter(|url| !url.is_empty()),
+			None if line.is_empty() || line.starts_with("//") => continue,
+			None => return None,
+		}
+	}
+
+	None
+}
+
+/// Module loader registering the source maps referenced by `//# sourceMappingURL=` comments in loaded modules.
+///
+/// The code of each module is registered as well, so that errors can show the offending source line.
+/// External maps are resolved relative to the module and loaded through the inner loader, so they are subject to the
+/// same rules as imports. A map that cannot be loaded is ignored; the module itself still loads.
+pub(crate) struct SourceMappingLoader {
+	inner: Rc<dyn ModuleLoader>,
+	source_maps: SourceMapStore,
+}
+
+impl SourceMappingLoader {
+	pub fn new(inner: Rc<dyn ModuleLoader>, source_maps: SourceMapStore) -> Self {
+		Self { inner, source_maps }
+	}
+}
+
+impl ModuleLoader for SourceMappingLoader {
+	fn resolve(
+		&self,
+		specifier: &str,
+		referrer: &str,
+		kind: ResolutionKind,
+	) -> Result<ModuleSpecifier, AnyError> {
+		self.inner.resolve(specifier, referrer, kind)
+	}
+
+	fn load(
+		&self,
+		module_specifier: &ModuleSpecifier,
+		maybe_referrer: Option<&ModuleSpecifier>,
+		is_dyn_import: bool,
+	) -> Pin<Box<ModuleSourceFuture>> {
+		let future = self
+			.inner
+			.load(module_specifier, maybe_referrer, is_dyn_import);
+
+		if is_raw_import(module_specifier) {
+			return future;
+		}
+
+		let inner = self.inner.clone();
+		let source_maps = self.source_maps.clone();
+		let specifier = module_specifier.clone();
+
+		async move {
+			let source = future.await?;
+			if source.module_type != ModuleType::JavaScript {
+				return Ok(source);
+			}
+			source_maps.insert_source(specifier.as_str(), source.code.as_str().to_string());
+
+			let Some(url) = source_mapping_url(source.code.as_str()) else {
+				return Ok(source);
+			};
+
+			if url.starts_with("data:") {
+				if let Some((_, json)) = decode_data_url(url) {
+					source_maps.insert_source_map(specifier.as_str(), json.into_bytes());
+				}
+				return Ok(source);
+			}
+
+			let map_url = specifier
+				.join(url)
+				.map_err(AnyError::from)
+				.and_then(|map_url| {
+					inner.resolve(map_url.as_str(), specifier.as_str(), ResolutionKind::Import)
+				});
+
+			if let Ok(map_url) = map_url {
+				if let Ok(map) = inner.load(&map_url, Some(&specifier), false).await {
+					let json = map.code.as_str().as_bytes().to_vec();
+					source_maps.insert_source_map(specifier.as_str(), json);
+				}
+			}
+
+			Ok(source)
+		}
+		.boxed_local()
+	}
+}
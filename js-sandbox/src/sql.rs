@@ -0,0 +1,302 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::fmt;
+use std::sync::Arc;
+
+use deno_core::anyhow::anyhow;
+use deno_core::{op, OpDecl, OpState};
+
+use crate::permissions::PermissionState;
+use crate::{AnyError, JsValue};
+
+/// A result row, by column name
+pub type SqlRow = serde_json::Map<String, JsValue>;
+
+/// SQL access for scripts, through `sqlSelect()` and `sqlExecute()`. Enabled with
+/// [`ScriptBuilder::sql()`](crate::ScriptBuilder::sql).
+///
+/// Statements are run by the application's [`SqlExecutor`]. Values always travel as bound parameters, never as part of
+/// the SQL text:
+///
+/// ```js
+/// const rows = sqlSelect("SELECT id, total FROM invoices WHERE customer = ? AND total > ?", [customer, 100]);
+/// // Template literals are turned into placeholders and parameters as well
+/// const open = sqlSelect`SELECT id FROM invoices WHERE customer = ${customer} AND paid = 0`;
+/// const changed = sqlExecute("UPDATE invoices SET paid = 1 WHERE id = ?", [rows[0].id]);
+/// ```
+///
+/// `sqlSelect()` returns the rows as array of objects, keyed by column name; `sqlExecute()` returns the number of
+/// affected rows. Parameters must be `null`, booleans, numbers or strings. Constant literals such as `status = 'POSTED'`
+/// are fine in the SQL text; values from outside the script belong in parameters. In [`read_only()`](Self::read_only) mode,
+/// `sqlExecute()` throws.
+///
+/// Scripts additionally need the permission for the host functions `"sql"`, see
+/// [`Permissions::allow_host_functions()`](crate::Permissions::allow_host_functions).
+#[derive(Clone)]
+pub struct HostSql {
+	executor: Arc<dyn SqlExecutor>,
+	read_only: bool,
+	max_rows: usize,
+}
+
+impl HostSql {
+	/// Runs the statements of scripts with `executor`.
+	pub fn new(executor: Arc<dyn SqlExecutor>) -> Self {
+		Self {
+			executor,
+			read_only: false,
+			max_rows: 10_000,
+		}
+	}
+
+	/// Only allows `sqlSelect()`.
+	///
+	/// This is not a security boundary by itself: `HostSql` does not parse SQL, so whether `sqlSelect()` can be used to
+	/// write depends on the executor honouring [`SqlExecutor::select()`]. To make sure scripts cannot modify data, give
+	/// the executor a read-only connection or database user, e.g. [`SqliteExecutor::open_read_only()`].
+	pub fn read_only(mut self) -> Self {
+		self.read_only = true;
+		self
+	}
+
+	/// Fails queries returning more than `rows` rows; 10 000 by default.
+	pub fn max_rows(mut self, rows: usize) -> Self {
+		self.max_rows = rows;
+		self
+	}
+}
+
+impl fmt::Debug for HostSql {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("HostSql")
+			.field("read_only", &self.read_only)
+			.field("max_rows", &self.max_rows)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Runs the SQL statements of scripts against the application's database; implemented by [`SqliteExecutor`] with the
+/// cargo feature `sqlite`.
+///
+/// Parameters are positional and bound to the statement, never spliced into `sql`. Each is `null`, a boolean, a number
+/// or a string.
+pub trait SqlExecutor: Send + Sync {
+	/// Runs a query and returns its rows, with at most `max_rows + 1` rows read.
+	///
+	/// Must refuse statements that modify data; [`HostSql`] relies on this, also in read-only mode.
+	fn select(
+		&self,
+		sql: &str,
+		params: &[JsValue],
+		max_rows: usize,
+	) -> Result<Vec<SqlRow>, AnyError>;
+
+	/// Runs a statement that modifies data and returns the number of affected rows.
+	fn execute(&self, sql: &str, params: &[JsValue]) -> Result<u64, AnyError>;
+}
+
+/// Reference executor backed by SQLite, e.g. for tests of plugins against a small copy of the data.
+///
+/// [`select()`](SqlExecutor::select) refuses statements that could write to the database.
+#[cfg(feature = "sqlite")]
+pub struct SqliteExecutor {
+	connection: std::sync::Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteExecutor {
+	/// Opens or creates the database at `path`.
+	pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, AnyError> {
+		Ok(Self::from_connection(rusqlite::Connection::open(path)?))
+	}
+
+	/// Opens the existing database at `path` read-only, so that neither statement can modify it.
+	pub fn open_read_only(path: impl AsRef<std::path::Path>) -> Result<Self, AnyError> {
+		use rusqlite::OpenFlags;
+
+		let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+			| OpenFlags::SQLITE_OPEN_NO_MUTEX
+			| OpenFlags::SQLITE_OPEN_URI;
+		Ok(Self::from_connection(
+			rusqlite::Connection::open_with_flags(path, flags)?,
+		))
+	}
+
+	/// Creates an empty database in memory.
+	pub fn open_in_memory() -> Result<Self, AnyError> {
+		Ok(Self::from_connection(
+			rusqlite::Connection::open_in_memory()?
+		))
+	}
+
+	/// Uses an already opened connection, e.g. one with custom functions or pragmas.
+	pub fn from_connection(connection: rusqlite::Connection) -> Self {
+		Self {
+			connection: std::sync::Mutex::new(connection),
+		}
+	}
+
+	/// Runs `sql`, possibly several statements, without parameters; for setting up schemas and test data.
+	pub fn execute_batch(&self, sql: &str) -> Result<(), AnyError> {
+		Ok(self.connection().execute_batch(sql)?)
+	}
+
+	fn connection(&self) -> std::sync::MutexGuard<rusqlite::Connection> {
+		self.connection.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+#[cfg(feature = "sqlite")]
+impl fmt::Debug for SqliteExecutor {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SqliteExecutor").finish_non_exhaustive()
+	}
+}
+
+#[cfg(feature = "sqlite")]
+impl SqlExecutor for SqliteExecutor {
+	fn select(
+		&self,
+		sql: &str,
+		params: &[JsValue],
+		max_rows: usize,
+	) -> Result<Vec<SqlRow>, AnyError> {
+		use rusqlite::types::ValueRef;
+
+		let connection = self.connection();
+		let mut statement = connection.prepare(sql)?;
+		if !statement.readonly() {
+			return Err(anyhow!("sqlSelect(): statement would modify the database"));
+		}
+
+		let columns: Vec<String> = statement
+			.column_names()
+			.into_iter()
+			.map(String::from)
+			.collect();
+		let mut rows =
+			statement.query(rusqlite::params_from_iter(params.iter().map(sqlite_value)))?;
+
+		let mut result = Vec::new();
+		while let Some(row) = rows.next()? {
+			let mut object = SqlRow::new();
+			for (i, column) in columns.iter().enumerate() {
+				let value = match row.get_ref(i)? {
+					ValueRef::Null => JsValue::Null,
+					ValueRef::Integer(n) => n.into(),
+					ValueRef::Real(n) => n.into(),
+					ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+					ValueRef::Blob(bytes) => bytes.to_vec().into(),
+				};
+				object.insert(column.clone(), value);
+			}
+
+			result.push(object);
+			if result.len() > max_rows {
+				break;
+			}
+		}
+
+		Ok(result)
+	}
+
+	fn execute(&self, sql: &str, params: &[JsValue]) -> Result<u64, AnyError> {
+		let connection = self.connection();
+		let changed = connection.execute(
+			sql,
+			rusqlite::params_from_iter(params.iter().map(sqlite_value)),
+		)?;
+		Ok(changed as u64)
+	}
+}
+
+/// Parameters are checked by [`check_params()`] before reaching the executor
+#[cfg(feature = "sqlite")]
+fn sqlite_value(param: &JsValue) -> rusqlite::types::Value {
+	use rusqlite::types::Value;
+
+	match param {
+		JsValue::Bool(b) => Value::Integer(*b as i64),
+		JsValue::Number(n) => match n.as_i64() {
+			Some(n) => Value::Integer(n),
+			None => Value::Real(n.as_f64().unwrap_or(f64::NAN)),
+		},
+		JsValue::String(s) => Value::Text(s.clone()),
+		_ => Value::Null,
+	}
+}
+
+fn check_params(params: &[JsValue]) -> Result<(), AnyError> {
+	match params
+		.iter()
+		.position(|param| param.is_array() || param.is_object())
+	{
+		Some(i) => Err(anyhow!(
+			"SQL parameter {} must be null, a boolean, a number or a string",
+			i + 1
+		)),
+		None => Ok(()),
+	}
+}
+
+fn host_sql(state: &mut OpState) -> Result<HostSql, AnyError> {
+	let sql = state
+		.try_borrow::<HostSql>()
+		.ok_or_else(|| anyhow!("SQL is not enabled; build the script with ScriptBuilder::sql()"))?
+		.clone();
+
+	state
+		.borrow_mut::<PermissionState>()
+		.check_host_functions("sql")?;
+	Ok(sql)
+}
+
+/// Runs a query for `sqlSelect()`
+pub(crate) fn select(
+	state: &mut OpState,
+	sql: &str,
+	params: &[JsValue],
+) -> Result<Vec<SqlRow>, AnyError> {
+	let host_sql = host_sql(state)?;
+	check_params(params)?;
+
+	let rows = host_sql.executor.select(sql, params, host_sql.max_rows)?;
+	if rows.len() > host_sql.max_rows {
+		return Err(anyhow!(
+			"sqlSelect(): query returned more than {} rows",
+			host_sql.max_rows
+		));
+	}
+
+	Ok(rows)
+}
+
+/// Runs a statement for `sqlExecute()`
+pub(crate) fn execute(state: &mut OpState, sql: &str, params: &[JsValue]) -> Result<u64, AnyError> {
+	let host_sql = host_sql(state)?;
+	if host_sql.read_only {
+		return Err(anyhow!("sqlExecute(): SQL access is read-only"));
+	}
+	check_params(params)?;
+
+	host_sql.executor.execute(sql, params)
+}
+
+/// Ops for sql.js, registered with the crate's other ops
+pub(crate) fn ops() -> Vec<OpDecl> {
+	vec![op_sql_select::decl(), op_sql_execute::decl()]
+}
+
+#[op]
+fn op_sql_select(
+	state: &mut OpState,
+	sql: String,
+	params: Vec<JsValue>,
+) -> Result<Vec<SqlRow>, AnyError> {
+	select(state, &sql, &params)
+}
+
+#[op]
+fn op_sql_execute(state: &mut OpState, sql: String, params: Vec<JsValue>) -> Result<u64, AnyError> {
+	execute(state, &sql, &params)
+}
@@ -0,0 +1,257 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use deno_core::anyhow::anyhow;
+use deno_core::futures::future::poll_fn;
+use deno_core::{op, OpState};
+
+use crate::AnyError;
+
+/// Limits and clock for the timers of a script, set through [`ScriptBuilder`](crate::ScriptBuilder).
+#[derive(Clone, Debug)]
+pub(crate) struct TimerConfig {
+	pub max_timers: usize,
+	pub max_delay: Duration,
+	pub virtual_clock: bool,
+}
+
+impl Default for TimerConfig {
+	fn default() -> Self {
+		Self {
+			max_timers: 1000,
+			max_delay: Duration::from_secs(60 * 60),
+			virtual_clock: false,
+		}
+	}
+}
+
+/// Pending timers of a script, stored in its `OpState`.
+///
+/// Times are measured from the creation of the runtime. The JS side (timers.js) keeps the callbacks and waits for due
+/// timers with `op_timer_wait`; this side decides when they are due. Timers due at the same time fire in the order they
+/// were started.
+pub(crate) struct Timers {
+	config: TimerConfig,
+	origin: Instant,
+
+	/// Current time of a virtual clock; `None` for real time
+	virtual_now: Option<Duration>,
+
+	next_id: u32,
+
+	/// Interval period (if repeating) by deadline and ID
+	queue: BTreeMap<(Duration, u32), Option<Duration>>,
+
+	/// Deadline by ID, to find timers in `queue`
+	deadlines: HashMap<u32, Duration>,
+
+	/// Wakes the pending `op_timer_wait`, if any
+	waker: Option<Waker>,
+}
+
+impl Timers {
+	pub fn new(config: TimerConfig) -> Self {
+		let virtual_now = config.virtual_clock.then_some(Duration::ZERO);
+
+		Self {
+			config,
+			origin: Instant::now(),
+			virtual_now,
+			next_id: 1,
+			queue: BTreeMap::new(),
+			deadlines: HashMap::new(),
+			waker: None,
+		}
+	}
+
+	pub fn is_virtual(&self) -> bool {
+		self.virtual_now.is_some()
+	}
+
+	pub fn now(&self) -> Duration {
+		self.virtual_now.unwrap_or_else(|| self.origin.elapsed())
+	}
+
+	/// Deadline of the next timer, if any
+	pub fn next_deadline(&self) -> Option<Duration> {
+		self.queue.keys().next().map(|(deadline, _)| *deadline)
+	}
+
+	pub fn has_due(&self) -> bool {
+		self.next_deadline()
+			.map_or(false, |deadline| deadline <= self.now())
+	}
+
+	/// Sets the virtual clock to `now`, which must not be in the past.
+	pub fn set_virtual_now(&mut self, now: Duration) {
+		debug_assert!(self.is_virtual() && now >= self.now());
+
+		self.virtual_now = Some(now);
+		self.wake();
+	}
+
+	/// Moves a virtual clock forward to the next timer, unless one is already due. Returns whether a timer is due now.
+	pub fn jump_to_next(&mut self) -> bool {
+		match (self.virtual_now, self.next_deadline()) {
+			(Some(now), Some(deadline)) => {
+				if deadline > now {
+					self.set_virtual_now(deadline);
+				}
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// Cancels all timers, e.g. after a script timed out.
+	pub fn cancel_all(&mut self) {
+		self.queue.clear();
+		self.deadlines.clear();
+		self.wake();
+	}
+
+	fn start(&mut self, delay_ms: f64, repeat: bool) -> Result<u32, AnyError> {
+		let max_delay = self.config.max_delay;
+		if delay_ms > max_delay.as_millis() as f64 {
+			return Err(anyhow!(
+				"Timer delay of {delay_ms} ms exceeds the limit of {} ms",
+				max_delay.as_millis()
+			));
+		}
+
+		if self.queue.len() >= self.config.max_timers {
+			return Err(anyhow!(
+				"Too many pending timers (limit {})",
+				self.config.max_timers
+			));
+		}
+
+		// Like browsers, treat negative and NaN delays as 0; intervals need some delay to let other work run
+		let delay = Duration::from_secs_f64(delay_ms.max(0.0) / 1000.0);
+		let period = repeat.then(|| delay.max(Duration::from_millis(1)));
+
+		let id = self.next_id;
+		self.next_id = self.next_id.wrapping_add(1).max(1);
+
+		let deadline = self.now() + delay;
+		self.queue.insert((deadline, id), period);
+		self.deadlines.insert(id, deadline);
+		self.wake();
+
+		Ok(id)
+	}
+
+	fn cancel(&mut self, id: u32) {
+		if let Some(deadline) = self.deadlines.remove(&id) {
+			self.queue.remove(&(deadline, id));
+			self.wake();
+		}
+	}
+
+	/// Removes due timers and returns their IDs in firing order. Intervals are scheduled again.
+	fn take_due(&mut self) -> Vec<u32> {
+		let now = self.now();
+		let mut due = Vec::new();
+
+		while let Some(entry) = self.queue.first_entry() {
+			let (deadline, id) = *entry.key();
+			if deadline > now {
+				break;
+			}
+
+			let period = entry.remove();
+			due.push(id);
+
+			match period {
+				Some(period) => {
+					// An interval that fell behind skips the missed runs
+					let mut next = deadline + period;
+					if next <= now {
+						next = now + period;
+					}
+					self.queue.insert((next, id), Some(period));
+					self.deadlines.insert(id, next);
+				}
+				None => {
+					self.deadlines.remove(&id);
+				}
+			}
+		}
+
+		due
+	}
+
+	/// Wall-clock time of the next deadline, for waiting in real time
+	fn next_instant(&self) -> Option<Instant> {
+		match self.virtual_now {
+			Some(_) => None,
+			None => self.next_deadline().map(|deadline| self.origin + deadline),
+		}
+	}
+
+	fn wake(&mut self) {
+		if let Some(waker) = self.waker.take() {
+			waker.wake();
+		}
+	}
+}
+
+/// Starts a timer firing after `delay` milliseconds; repeatedly if `repeat` is set. Returns its ID.
+#[op]
+fn op_timer_start(state: &mut OpState, delay: f64, repeat: bool) -> Result<u32, AnyError> {
+	state.borrow_mut::<Timers>().start(delay, repeat)
+}
+
+#[op]
+fn op_timer_cancel(state: &mut OpState, id: u32) {
+	state.borrow_mut::<Timers>().cancel(id);
+}
+
+/// Time since the runtime was created, in milliseconds; on the virtual clock if the script has one
+#[op]
+fn op_timer_now(state: &mut OpState) -> f64 {
+	state.borrow::<Timers>().now().as_secs_f64() * 1000.0
+}
+
+/// Waits until timers are due and returns their IDs. Returns an empty list if there are no timers left.
+#[op]
+async fn op_timer_wait(state: Rc<RefCell<OpState>>) -> Result<Vec<u32>, AnyError> {
+	let mut sleep: Option<Pin<Box<tokio::time::Sleep>>> = None;
+
+	poll_fn(|cx| {
+		let mut state = state.borrow_mut();
+		let timers = state.borrow_mut::<Timers>();
+
+		let due = timers.take_due();
+		if !due.is_empty() || timers.queue.is_empty() {
+			return Poll::Ready(Ok(due));
+		}
+		timers.waker = Some(cx.waker().clone());
+
+		// A virtual clock is moved by the script; real time needs a wake-up at the next deadline
+		if let Some(deadline) = timers.next_instant() {
+			let deadline = tokio::time::Instant::from_std(deadline);
+			if sleep
+				.as_ref()
+				.map_or(true, |sleep| sleep.deadline() != deadline)
+			{
+				sleep = Some(Box::pin(tokio::time::sleep_until(deadline)));
+			}
+
+			let sleep = sleep.as_mut().expect("sleep is set");
+			if sleep.as_mut().poll(cx).is_ready() {
+				cx.waker().wake_by_ref();
+			}
+		}
+
+		Poll::Pending
+	})
+	.await
+}
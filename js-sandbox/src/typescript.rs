@@ -0,0 +1,119 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::pin::Pin;
+use std::rc::Rc;
+
+use deno_ast::{EmitOptions, MediaType, ParseParams, SourceTextInfo};
+use deno_core::futures::FutureExt;
+use deno_core::{
+	ModuleLoader, ModuleSource, ModuleSourceFuture, ModuleSpecifier, ModuleType, ResolutionKind,
+};
+
+use crate::module_loader::is_raw_import;
+use crate::source_map::SourceMapStore;
+use crate::AnyError;
+
+/// Whether the code at `specifier` must be transpiled before V8 can run it, judging by its extension.
+pub(crate) fn needs_transpile(specifier: &ModuleSpecifier) -> bool {
+	matches!(
+		MediaType::from_specifier(specifier),
+		MediaType::TypeScript | MediaType::Mts | MediaType::Cts | MediaType::Tsx | MediaType::Jsx
+	)
+}
+
+/// Strips types from TypeScript `code`, returning the JavaScript code and its source map (JSON).
+///
+/// No type checking takes place; the code only fails to transpile if it cannot be parsed.
+pub(crate) fn transpile(
+	specifier: &ModuleSpecifier,
+	code: &str,
+	is_module: bool,
+) -> Result<(String, Option<String>), AnyError> {
+	let params = ParseParams {
+		specifier: specifier.to_string(),
+		text_info: SourceTextInfo::from_string(code.to_string()),
+		media_type: MediaType::from_specifier(specifier),
+		capture_tokens: false,
+		scope_analysis: false,
+		maybe_syntax: None,
+	};
+
+	let parsed = if is_module {
+		deno_ast::parse_module(params)?
+	} else {
+		deno_ast::parse_script(params)?
+	};
+
+	let transpiled = parsed.transpile(&EmitOptions {
+		source_map: true,
+		inline_source_map: false,
+		inline_sources: true,
+		..Default::default()
+	})?;
+
+	Ok((transpiled.text, transpiled.source_map))
+}
+
+/// Module loader transpiling TypeScript modules loaded by another loader.
+///
+/// Source maps are registered in the script's [`SourceMapStore`], so that errors point to the TypeScript source.
+pub(crate) struct TranspilingModuleLoader {
+	inner: Rc<dyn ModuleLoader>,
+	source_maps: SourceMapStore,
+}
+
+impl TranspilingModuleLoader {
+	pub fn new(inner: Rc<dyn ModuleLoader>, source_maps: SourceMapStore) -> Self {
+		Self { inner, source_maps }
+	}
+}
+
+impl ModuleLoader for TranspilingModuleLoader {
+	fn resolve(
+		&self,
+		specifier: &str,
+		referrer: &str,
+		kind: ResolutionKind,
+	) -> Result<ModuleSpecifier, AnyError> {
+		self.inner.resolve(specifier, referrer, kind)
+	}
+
+	fn load(
+		&self,
+		module_specifier: &ModuleSpecifier,
+		maybe_referrer: Option<&ModuleSpecifier>,
+		is_dyn_import: bool,
+	) -> Pin<Box<ModuleSourceFuture>> {
+		let future = self
+			.inner
+			.load(module_specifier, maybe_referrer, is_dyn_import);
+
+		let specifier = module_specifier.clone();
+		let source_maps = self.source_maps.clone();
+
+		async move {
+			let source = future.await?;
+			if !needs_transpile(&specifier)
+				|| is_raw_import(&specifier)
+				|| source.module_type != ModuleType::JavaScript
+			{
+				return Ok(source);
+			}
+
+			let original = source.code.as_str().to_string();
+			let (code, source_map) = transpile(&specifier, &original, true)?;
+
+			if let Some(source_map) = source_map {
+				source_maps.insert_source_map(specifier.as_str(), source_map.into_bytes());
+			}
+			source_maps.insert_source(specifier.as_str(), original);
+
+			Ok(ModuleSource::new(
+				ModuleType::JavaScript,
+				code.into(),
+				&specifier,
+			))
+		}
+		.boxed_local()
+	}
+}
@@ -0,0 +1,228 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use deno_core::anyhow::anyhow;
+
+use crate::host_fs::virtual_segments;
+use crate::AnyError;
+
+/// In-memory files shared between the host and scripts, to pass inputs and collect outputs without touching disk.
+///
+/// The host fills it with [`insert()`](Self::insert) and reads results back with [`get()`](Self::get). Scripts built with
+/// [`ScriptBuilder::virtual_fs()`](crate::ScriptBuilder::virtual_fs) see the files through `host.fs`, on top of any
+/// mounted directories, and can import them as modules under `vfs:` URLs, e.g. `import { format } from "vfs:///lib/format.js"`.
+///
+/// ```rust
+/// use js_sandbox::{Script, VirtualFs, AnyError};
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let vfs = VirtualFs::new();
+/// 	vfs.insert("/in/data.csv", "item,qty\napple,3\n");
+///
+/// 	let mut script = Script::builder()
+/// 		.virtual_fs(vfs.clone())
+/// 		.build_from_string(r#"
+/// 			function report() {
+/// 				const lines = host.fs.readText("/in/data.csv").trim().split("\n");
+/// 				host.fs.writeText("/out/report.txt", `${lines.length - 1} items`);
+/// 			}"#)?;
+///
+/// 	let _: () = script.call("report", ())?;
+/// 	assert_eq!(vfs.get("/out/report.txt"), Some(b"1 items".to_vec()));
+/// 	Ok(())
+/// }
+/// ```
+///
+/// Clones share the same files. Paths are absolute, with `.` and `..` resolved; directories exist implicitly through the
+/// files in them.
+#[derive(Clone)]
+pub struct VirtualFs {
+	inner: Arc<Mutex<Files>>,
+}
+
+struct Files {
+	files: BTreeMap<String, Vec<u8>>,
+
+	/// Bytes of all paths and contents, kept up to date so that writes need not recount the file system
+	size: usize,
+
+	/// Limit for writes by scripts, in bytes of paths and contents
+	max_size: usize,
+}
+
+impl Files {
+	fn insert(&mut self, path: String, bytes: Vec<u8>) {
+		self.remove(&path);
+		self.size += path.len() + bytes.len();
+		self.files.insert(path, bytes);
+	}
+
+	fn remove(&mut self, path: &str) -> Option<Vec<u8>> {
+		let old = self.files.remove(path)?;
+		self.size -= path.len() + old.len();
+		Some(old)
+	}
+
+	fn clear(&mut self) {
+		self.files.clear();
+		self.size = 0;
+	}
+}
+
+/// An entry of a virtual directory
+pub(crate) struct VirtualEntry {
+	pub name: String,
+	pub is_file: bool,
+}
+
+impl Default for VirtualFs {
+	fn default() -> Self {
+		Self {
+			inner: Arc::new(Mutex::new(Files {
+				files: BTreeMap::new(),
+				size: 0,
+				max_size: 64 * 1024 * 1024,
+			})),
+		}
+	}
+}
+
+impl VirtualFs {
+	/// Creates an empty file system.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Limits how large scripts can make the file system by writing to it; 64 MiB by default. Paths count as well.
+	///
+	/// Files inserted by the host are not limited, but count towards the size. Like the files, the limit is shared by all
+	/// clones.
+	pub fn set_max_size(&self, bytes: usize) {
+		self.files().max_size = bytes;
+	}
+
+	/// Stores `bytes` at `path`, replacing any previous file there.
+	///
+	/// Panics if `path` is not absolute or leads outside the root through `..`.
+	pub fn insert(&self, path: &str, bytes: impl Into<Vec<u8>>) {
+		let path = normalize(path).unwrap_or_else(|e| panic!("{e}"));
+		self.files().insert(path, bytes.into());
+	}
+
+	/// Returns the contents of the file at `path`, if there is one.
+	pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+		let path = normalize(path).ok()?;
+		self.files().files.get(&path).cloned()
+	}
+
+	/// Removes the file at `path`, returning its contents.
+	pub fn remove(&self, path: &str) -> Option<Vec<u8>> {
+		let path = normalize(path).ok()?;
+		self.files().remove(&path)
+	}
+
+	/// Paths of all files, sorted.
+	pub fn paths(&self) -> Vec<String> {
+		self.files().files.keys().cloned().collect()
+	}
+
+	/// Removes all files.
+	pub fn clear(&self) {
+		self.files().clear();
+	}
+
+	/// Writes a file on behalf of a script, within the size limit.
+	pub(crate) fn write(&self, path: &str, bytes: Vec<u8>) -> Result<(), AnyError> {
+		let path = normalize(path)?;
+		let mut files = self.files();
+
+		let previous = files
+			.files
+			.get(&path)
+			.map_or(0, |old| path.len() + old.len());
+		let size = files.size - previous + path.len() + bytes.len();
+		if size > files.max_size {
+			return Err(anyhow!(
+				"host.fs: cannot write '{path}': virtual file system would exceed its limit of {} bytes",
+				files.max_size
+			));
+		}
+
+		files.insert(path, bytes);
+		Ok(())
+	}
+
+	/// Entries of the directory at `path`, or `None` if no file lies below it.
+	pub(crate) fn list(&self, path: &str) -> Option<Vec<VirtualEntry>> {
+		let prefix = dir_prefix(path)?;
+		let files = self.files();
+
+		let mut entries: Vec<VirtualEntry> = Vec::new();
+		for path in files.files.keys() {
+			let Some(rest) = path.strip_prefix(&prefix) else {
+				continue;
+			};
+
+			let (name, is_file) = match rest.split_once('/') {
+				Some((dir, _)) => (dir, false),
+				None => (rest, true),
+			};
+			if entries.last().map_or(true, |last| last.name != name) {
+				entries.push(VirtualEntry {
+					name: name.to_string(),
+					is_file,
+				});
+			}
+		}
+
+		(!entries.is_empty()).then_some(entries)
+	}
+
+	/// Whether `path` is a file (`Some(true)`), a directory with files below it (`Some(false)`) or neither (`None`).
+	pub(crate) fn kind(&self, path: &str) -> Option<bool> {
+		let normalized = normalize(path).ok()?;
+		let files = self.files();
+		if files.files.contains_key(&normalized) {
+			return Some(true);
+		}
+
+		let prefix = dir_prefix(path)?;
+		let is_dir = files
+			.files
+			.range(prefix.clone()..)
+			.next()
+			.map_or(false, |(path, _)| path.starts_with(&prefix));
+		is_dir.then_some(false)
+	}
+
+	fn files(&self) -> MutexGuard<Files> {
+		// Files stay consistent even if a thread panicked while holding the lock
+		self.inner.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+impl fmt::Debug for VirtualFs {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("VirtualFs")
+			.field("paths", &self.paths())
+			.finish()
+	}
+}
+
+/// Normalizes an absolute path to the form used as key, e.g. `/out/report.txt`.
+fn normalize(path: &str) -> Result<String, AnyError> {
+	Ok(format!("/{}", virtual_segments(path)?.join("/")))
+}
+
+/// The prefix shared by all files in the directory at `path`, e.g. `/out/`; `/` for the root.
+fn dir_prefix(path: &str) -> Option<String> {
+	let normalized = normalize(path).ok()?;
+	if normalized == "/" {
+		Some(normalized)
+	} else {
+		Some(normalized + "/")
+	}
+}
@@ -0,0 +1,203 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use deno_core::anyhow::anyhow;
+use deno_core::url::{form_urlencoded, quirks, Url};
+use deno_core::{op, JsBuffer, OpDecl, OpState, ToJsBuffer};
+use serde::Serialize;
+
+use crate::base64;
+use crate::deterministic::Deterministic;
+use crate::AnyError;
+
+/// Ops for web.js, registered with the crate's other ops
+pub(crate) fn ops() -> Vec<OpDecl> {
+	vec![
+		op_web_url_parse::decl(),
+		op_web_url_set::decl(),
+		op_web_url_search_params_parse::decl(),
+		op_web_url_search_params_stringify::decl(),
+		op_web_encode_utf8::decl(),
+		op_web_decode_utf8::decl(),
+		op_web_btoa::decl(),
+		op_web_atob::decl(),
+		op_web_random_uuid::decl(),
+	]
+}
+
+/// Components of a URL, as exposed by the JS `URL` class
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UrlParts {
+	href: String,
+	origin: String,
+	protocol: String,
+	username: String,
+	password: String,
+	host: String,
+	hostname: String,
+	port: String,
+	pathname: String,
+	search: String,
+	hash: String,
+}
+
+impl UrlParts {
+	fn new(url: &Url) -> Self {
+		Self {
+			href: quirks::href(url).to_string(),
+			origin: quirks::origin(url),
+			protocol: quirks::protocol(url).to_string(),
+			username: quirks::username(url).to_string(),
+			password: quirks::password(url).to_string(),
+			host: quirks::host(url).to_string(),
+			hostname: quirks::hostname(url).to_string(),
+			port: quirks::port(url).to_string(),
+			pathname: quirks::pathname(url).to_string(),
+			search: quirks::search(url).to_string(),
+			hash: quirks::hash(url).to_string(),
+		}
+	}
+}
+
+fn parse_url(href: &str, base: Option<&str>) -> Result<Url, AnyError> {
+	let url = match base {
+		Some(base) => Url::parse(base).and_then(|base| base.join(href)),
+		None => Url::parse(href),
+	};
+
+	url.map_err(|e| anyhow!("Invalid URL '{href}': {e}"))
+}
+
+#[op]
+fn op_web_url_parse(href: String, base: Option<String>) -> Result<UrlParts, AnyError> {
+	let url = parse_url(&href, base.as_deref())?;
+	Ok(UrlParts::new(&url))
+}
+
+/// Sets a component of `href`, following the rules of the `URL` setters: invalid values are ignored, except for `href`.
+#[op]
+fn op_web_url_set(href: String, setter: String, value: String) -> Result<UrlParts, AnyError> {
+	let mut url = parse_url(&href, None)?;
+
+	match setter.as_str() {
+		"href" => url = parse_url(&value, None)?,
+		"protocol" => {
+			let _ = quirks::set_protocol(&mut url, &value);
+		}
+		"username" => {
+			let _ = quirks::set_username(&mut url, &value);
+		}
+		"password" => {
+			let _ = quirks::set_password(&mut url, &value);
+		}
+		"host" => {
+			let _ = quirks::set_host(&mut url, &value);
+		}
+		"hostname" => {
+			let _ = quirks::set_hostname(&mut url, &value);
+		}
+		"port" => {
+			let _ = quirks::set_port(&mut url, &value);
+		}
+		"pathname" => quirks::set_pathname(&mut url, &value),
+		"search" => quirks::set_search(&mut url, &value),
+		"hash" => quirks::set_hash(&mut url, &value),
+		_ => return Err(anyhow!("Unknown URL component '{setter}'")),
+	}
+
+	Ok(UrlParts::new(&url))
+}
+
+#[op]
+fn op_web_url_search_params_parse(query: String) -> Vec<(String, String)> {
+	form_urlencoded::parse(query.as_bytes())
+		.into_owned()
+		.collect()
+}
+
+#[op]
+fn op_web_url_search_params_stringify(pairs: Vec<(String, String)>) -> String {
+	form_urlencoded::Serializer::new(String::new())
+		.extend_pairs(pairs)
+		.finish()
+}
+
+#[op]
+fn op_web_encode_utf8(text: String) -> ToJsBuffer {
+	text.into_bytes().into()
+}
+
+/// Decodes UTF-8 like `TextDecoder`: invalid sequences are replaced with U+FFFD, unless `fatal` is set.
+#[op]
+fn op_web_decode_utf8(bytes: JsBuffer, fatal: bool, ignore_bom: bool) -> Result<String, AnyError> {
+	let mut bytes: &[u8] = &bytes;
+	if !ignore_bom {
+		bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+	}
+
+	if fatal {
+		let text = std::str::from_utf8(bytes)
+			.map_err(|e| anyhow!("The encoded data is not valid UTF-8: {e}"))?;
+		Ok(text.to_string())
+	} else {
+		Ok(String::from_utf8_lossy(bytes).into_owned())
+	}
+}
+
+/// Base64-encodes a "binary string", in which each character represents a byte.
+#[op]
+fn op_web_btoa(data: String) -> Result<String, AnyError> {
+	let bytes = data
+		.chars()
+		.map(u8::try_from)
+		.collect::<Result<Vec<u8>, _>>()
+		.map_err(|_| anyhow!("btoa(): string contains characters outside of Latin1"))?;
+
+	Ok(base64::encode(&bytes))
+}
+
+/// Decodes base64 into a "binary string", in which each character represents a byte.
+#[op]
+fn op_web_atob(data: String) -> Result<String, AnyError> {
+	let invalid = || anyhow!("atob(): string is not correctly base64-encoded");
+
+	let valid_chars = data.bytes().all(|c| {
+		c.is_ascii_alphanumeric() || matches!(c, b'+' | b'/' | b'=') || c.is_ascii_whitespace()
+	});
+	if !valid_chars {
+		return Err(invalid());
+	}
+
+	let bytes = base64::decode(data.as_bytes()).ok_or_else(invalid)?;
+	Ok(bytes.into_iter().map(char::from).collect())
+}
+
+/// Random UUID (version 4). Uses the seeded generator in deterministic mode.
+#[op]
+fn op_web_random_uuid(state: &mut OpState) -> String {
+	let mut bytes: [u8; 16] = match state.try_borrow_mut::<Deterministic>() {
+		Some(deterministic) => {
+			let high = deterministic.next_u64().to_be_bytes();
+			let low = deterministic.next_u64().to_be_bytes();
+
+			let mut bytes = [0; 16];
+			bytes[..8].copy_from_slice(&high);
+			bytes[8..].copy_from_slice(&low);
+			bytes
+		}
+		None => rand::random(),
+	};
+
+	bytes[6] = (bytes[6] & 0x0f) | 0x40;
+	bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+	let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+	format!(
+		"{}-{}-{}-{}-{}",
+		&hex[0..8],
+		&hex[8..12],
+		&hex[12..16],
+		&hex[16..20],
+		&hex[20..32]
+	)
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use js_sandbox::{JsValue, Script};
+
+const CODE: &str = r#"
+	function add(a, b) { return a + b; }
+	async function addLater(a, b) { await null; return a + b; }
+	host.on("ping", (n) => n + 1);
+"#;
+
+#[tokio::test]
+async fn call_async() {
+	let mut script = Script::from_string(CODE).expect("Initialization succeeds");
+
+	let sum: i32 = script.call_async("add", (2, 3)).await.unwrap();
+	assert_eq!(sum, 5);
+
+	let sum: i32 = script.call_async("addLater", (4, 5)).await.unwrap();
+	assert_eq!(sum, 9);
+
+	let results: Vec<JsValue> = script
+		.emit_async("ping", 1)
+		.await
+		.expect("Dispatch succeeds")
+		.into_iter()
+		.map(|r| r.expect("Handler succeeds"))
+		.collect();
+	assert_eq!(results, vec![JsValue::from(2)]);
+}
+
+#[tokio::test]
+async fn module_async() {
+	let mut script = Script::from_module_async("assets/test/plugin.js")
+		.await
+		.expect("Module can be loaded");
+
+	let total: f64 = script.call_async("lineTotal", (3, 1.5)).await.unwrap();
+	assert_eq!(total, 4.5);
+}
+
+#[tokio::test]
+async fn load_module_async() {
+	let mut script = Script::from_string(CODE).expect("Initialization succeeds");
+
+	script
+		.load_module_async("assets/test/plugin_util.js")
+		.await
+		.expect("Module can be evaluated");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_api_within_tokio() {
+	let mut script = Script::from_string(CODE).expect("Initialization succeeds");
+
+	let sum: i32 = script.call("addLater", (1, 2)).unwrap();
+	assert_eq!(sum, 3);
+
+	let mut module = Script::from_module("assets/test/plugin.js").expect("Module can be loaded");
+	let total: f64 = module.call("lineTotal", (2, 2.0)).unwrap();
+	assert_eq!(total, 4.0);
+}
+
+#[tokio::test]
+async fn sync_api_within_current_thread_runtime() {
+	let mut script = Script::from_string(CODE).expect("Initialization succeeds");
+
+	// Blocking the runtime's only thread would stall the script's timers and deadline
+	let result: Result<i32, _> = script.call("addLater", (1, 2));
+	let error = result.unwrap_err().to_string();
+	assert!(error.contains("use the async methods"), "{error}");
+
+	let sum: i32 = script.call_async("addLater", (1, 2)).await.unwrap();
+	assert_eq!(sum, 3);
+}
@@ -0,0 +1,104 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use js_sandbox::Script;
+
+const CODE: &str = r#"
+	const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+
+	function randoms(n) {
+		return Array.from({ length: n }, () => Math.random());
+	}
+
+	function now() {
+		return {
+			dateNow: Date.now(),
+			iso: new Date().toISOString(),
+			string: typeof Date(),
+			parsed: new Date("2020-01-02T03:04:05Z").getTime(),
+			isDate: new Date() instanceof Date,
+			performance: performance.now(),
+		};
+	}
+
+	async function nowAfter(ms) {
+		await sleep(ms);
+		return Date.now();
+	}
+"#;
+
+// 2023-01-01T00:00:00Z
+const START_MS: u64 = 1_672_531_200_000;
+
+fn deterministic_script(seed: u64) -> Script {
+	let start_time = UNIX_EPOCH + Duration::from_millis(START_MS);
+
+	Script::builder()
+		.deterministic(seed, start_time)
+		.build_from_string(CODE)
+		.expect("Initialization succeeds")
+}
+
+#[test]
+fn seeded_random() {
+	let first: Vec<f64> = deterministic_script(42).call("randoms", (5,)).unwrap();
+	let second: Vec<f64> = deterministic_script(42).call("randoms", (5,)).unwrap();
+	let other: Vec<f64> = deterministic_script(7).call("randoms", (5,)).unwrap();
+
+	assert_eq!(first, second);
+	assert_ne!(first, other);
+	assert!(first.iter().all(|x| (0.0..1.0).contains(x)));
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Now {
+	date_now: u64,
+	iso: String,
+	string: String,
+	parsed: u64,
+	is_date: bool,
+	performance: f64,
+}
+
+#[test]
+fn host_controlled_date() {
+	let mut script = deterministic_script(1);
+
+	let now: Now = script.call("now", ()).unwrap();
+	assert_eq!(now.date_now, START_MS);
+	assert_eq!(now.iso, "2023-01-01T00:00:00.000Z");
+	assert_eq!(now.string, "string");
+	assert_eq!(now.parsed, 1_577_934_245_000);
+	assert!(now.is_date);
+	assert_eq!(now.performance, 0.0);
+
+	// Time stands still between calls...
+	let now: Now = script.call("now", ()).unwrap();
+	assert_eq!(now.date_now, START_MS);
+
+	// ...unless the host moves it, or the script waits for a timer
+	script.advance_time(Duration::from_secs(60)).unwrap();
+	let now: Now = script.call("now", ()).unwrap();
+	assert_eq!(now.date_now, START_MS + 60_000);
+	assert_eq!(now.performance, 60_000.0);
+
+	let after: u64 = script.call("nowAfter", (1500,)).unwrap();
+	assert_eq!(after, START_MS + 61_500);
+}
+
+#[test]
+fn real_time_by_default() {
+	let mut script = Script::from_string(CODE).expect("Initialization succeeds");
+
+	let now: Now = script.call("now", ()).unwrap();
+	let system_ms = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap()
+		.as_millis() as u64;
+
+	assert!(now.date_now.abs_diff(system_ms) < 60_000);
+}
@@ -0,0 +1,228 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use js_sandbox::{JsError, MemoryModuleLoader, Script};
+
+fn error_text<T>(result: Result<T, JsError>) -> String {
+	match result {
+		Ok(_) => panic!("Expected error"),
+		Err(e) => e.to_string(),
+	}
+}
+
+#[test]
+fn default_file_name() {
+	let src = "function fail() {\n\tthrow new Error('boom');\n}";
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+	assert_eq!(script.file_name(), "sandboxed.js");
+
+	// console prelude must not shift the position
+	let err = error_text(script.call::<_, ()>("fail", ()));
+	assert!(err.contains("sandboxed.js:2:8"), "{err}");
+}
+
+#[test]
+fn custom_file_name() {
+	let src = "\n\nfunction fail() { throw new Error('boom'); }";
+	let mut script = Script::builder()
+		.file_name("tenant-42/pricing.js")
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+	assert_eq!(script.file_name(), "tenant-42/pricing.js");
+
+	let err = error_text(script.call::<_, ()>("fail", ()));
+	assert!(err.contains("tenant-42/pricing.js:3:25"), "{err}");
+}
+
+#[test]
+fn syntax_error_location() {
+	let src = "let a = 1;\nlet b = a +* 2;";
+	let result = Script::builder()
+		.file_name("broken.js")
+		.build_from_string(src);
+
+	let err = error_text(result);
+	assert!(err.contains("SyntaxError"), "{err}");
+	assert!(err.contains("broken.js:2:"), "{err}");
+}
+
+#[test]
+fn file_name_from_path() {
+	let file = std::env::temp_dir().join(format!("js-sandbox-errors-{}.js", std::process::id()));
+	std::fs::write(&file, "// helpers\nfunction fail() { return missing + 1; }").unwrap();
+
+	let mut script = Script::from_file(&file).expect("File can be loaded");
+	assert_eq!(script.file_name(), file.display().to_string());
+
+	let err = error_text(script.call::<_, ()>("fail", ()));
+	assert!(err.contains("ReferenceError"), "{err}");
+	assert!(err.contains(&format!("{}:2:", file.display())), "{err}");
+}
+
+#[test]
+fn file_name_of_module() {
+	let script = Script::from_module("assets/test/plugin.js").expect("Module can be loaded");
+
+	assert!(script.file_name().starts_with("file:///"));
+	assert!(script.file_name().ends_with("assets/test/plugin.js"));
+}
+
+/// Minified `function fail()`, mapped to `fail.src.js` where `new Error` is on line 3, column 8
+const MINIFIED: &str = r#"function fail(){throw new Error("boom")}"#;
+const SOURCE_MAP: &str = r#"{"version": 3, "sources": ["fail.src.js"], "sourcesContent": ["// original\nfunction fail() {\n\tthrow new Error(\"boom\");\n}\n"], "names": [], "mappings": "AACA,sBACO"}"#;
+const SOURCE_MAP_BASE64: &str = "eyJ2ZXJzaW9uIjogMywgInNvdXJjZXMiOiBbImZhaWwuc3JjLmpzIl0sICJzb3VyY2VzQ29udGVudCI6IFsiLy8gb3JpZ2luYWxcbmZ1bmN0aW9uIGZhaWwoKSB7XG5cdHRocm93IG5ldyBFcnJvcihcImJvb21cIik7XG59XG4iXSwgIm5hbWVzIjogW10sICJtYXBwaW5ncyI6ICJBQUNBLHNCQUNPIn0=";
+
+#[test]
+fn inline_source_map() {
+	let src = format!(
+		"{MINIFIED}\n//# sourceMappingURL=data:application/json;base64,{SOURCE_MAP_BASE64}\n"
+	);
+	let mut script = Script::builder()
+		.file_name("fail.min.js")
+		.build_from_string(&src)
+		.expect("Initialization succeeds");
+
+	let err = error_text(script.call::<_, ()>("fail", ()));
+	assert!(err.contains("fail.src.js:3:8"), "{err}");
+	assert!(!err.contains("fail.min.js:1:23"), "{err}");
+}
+
+#[test]
+fn external_source_map() {
+	let dir = std::env::temp_dir().join(format!("js-sandbox-source-map-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(
+		dir.join("fail.min.js"),
+		format!("{MINIFIED}\n//# sourceMappingURL=fail.min.js.map"),
+	)
+	.unwrap();
+	std::fs::write(dir.join("fail.min.js.map"), SOURCE_MAP).unwrap();
+
+	let mut script = Script::from_file(dir.join("fail.min.js")).expect("File can be loaded");
+
+	let err = error_text(script.call::<_, ()>("fail", ()));
+	assert!(err.contains("fail.src.js:3:8"), "{err}");
+}
+
+#[test]
+fn external_source_map_next_to_relative_file() {
+	// Relative to the working directory, so the file has no directory component
+	let name = format!("js-sandbox-relative-{}.min.js", std::process::id());
+	let map = format!("{name}.map");
+	std::fs::write(&name, format!("{MINIFIED}\n//# sourceMappingURL={map}")).unwrap();
+	std::fs::write(&map, SOURCE_MAP).unwrap();
+
+	let result = Script::from_file(&name);
+	std::fs::remove_file(&name).unwrap();
+	std::fs::remove_file(&map).unwrap();
+
+	let mut script = result.expect("File can be loaded");
+	let err = error_text(script.call::<_, ()>("fail", ()));
+	assert!(err.contains("fail.src.js:3:8"), "{err}");
+}
+
+#[test]
+fn external_source_map_outside_of_directory() {
+	let dir = std::env::temp_dir().join(format!(
+		"js-sandbox-source-map-outside-{}",
+		std::process::id()
+	));
+	std::fs::create_dir_all(dir.join("plugin")).unwrap();
+	std::fs::write(dir.join("secret.map"), SOURCE_MAP).unwrap();
+
+	let absolute = dir.join("secret.map").display().to_string();
+	for url in ["../secret.map", absolute.as_str()] {
+		let file = dir.join("plugin").join("fail.min.js");
+		std::fs::write(&file, format!("{MINIFIED}\n//# sourceMappingURL={url}")).unwrap();
+
+		let mut script = Script::from_file(&file).expect("File can be loaded");
+
+		let err = error_text(script.call::<_, ()>("fail", ()));
+		assert!(!err.contains("fail.src.js"), "{url}: {err}");
+	}
+}
+
+#[test]
+fn module_source_map() {
+	let loader: MemoryModuleLoader = [
+		(
+			"dist/main.js",
+			format!("export {MINIFIED}\n//# sourceMappingURL=main.js.map"),
+		),
+		(
+			"dist/main.js.map",
+			SOURCE_MAP.replace("AACA,sBACO", "AACA,6BACO"),
+		),
+	]
+	.into_iter()
+	.collect();
+
+	let mut script = Script::builder()
+		.module_loader(loader)
+		.build_from_module("dist/main.js")
+		.expect("Module can be loaded");
+
+	let err = error_text(script.call::<_, ()>("fail", ()));
+	assert!(err.contains("fail.src.js:3:8"), "{err}");
+}
+
+#[test]
+fn render_code_frame() {
+	let src = "function rate(country) {\n\tif (country !== 'DE') {\n\t\tthrow new Error(`no tax rate for ${country}`);\n\t}\n\treturn 0.19;\n}";
+	let mut script = Script::builder()
+		.file_name("plugins/tax.js")
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let err = script.call::<_, f64>("rate", ("XX",)).unwrap_err();
+	let rendered = err.render(false);
+
+	let expected_frame = "\
+		Uncaught Error: no tax rate for XX\n \
+		--> plugins/tax.js:3:9\n  \
+		|\n\
+		3 | \t\tthrow new Error(`no tax rate for ${country}`);\n  \
+		| \t\t      ^\n    \
+		at rate (plugins/tax.js:3:9)";
+
+	assert!(rendered.starts_with(expected_frame), "{rendered}");
+	assert!(!rendered.contains("js_sandbox:"), "{rendered}");
+	assert_eq!(format!("{err:#}"), rendered);
+
+	let colored = err.render(true);
+	assert!(colored.contains("\x1b[31m^\x1b[0m"), "{colored}");
+}
+
+#[test]
+fn render_syntax_error() {
+	let result = Script::builder()
+		.file_name("broken.js")
+		.build_from_string("let a = 1;\nlet b = a +* 2;");
+
+	let rendered = match result {
+		Ok(_) => panic!("Expected syntax error"),
+		Err(e) => e.render(false),
+	};
+	assert!(rendered.contains("SyntaxError"), "{rendered}");
+	assert!(rendered.contains("--> broken.js:2:"), "{rendered}");
+	assert!(rendered.contains("2 | let b = a +* 2;"), "{rendered}");
+}
+
+#[test]
+fn render_without_location() {
+	let err = JsError::Json(serde_json::from_str::<i32>("x").unwrap_err());
+	assert_eq!(err.render(true), err.to_string());
+}
+
+#[test]
+fn rd_run_string_location() {
+	let mut script =
+		Script::rd_get_run_time_with_builder(Script::builder().file_name("tenant-42/rules.js"))
+			.expect("Initialization succeeds");
+
+	// console is installed once, as a separate script; repeated runs neither redeclare it nor shift positions
+	script
+		.rd_run_string("console.log('ready');")
+		.expect("console is available");
+	let err = error_text(script.rd_run_string("\nthrow new Error('boom');"));
+	assert!(err.contains("tenant-42/rules.js:2:7"), "{err}");
+}
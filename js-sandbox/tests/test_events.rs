@@ -0,0 +1,93 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use serde::Serialize;
+
+use js_sandbox::{JsValue, Script};
+
+#[derive(Serialize)]
+struct Record {
+	id: u32,
+	status: String,
+}
+
+#[test]
+fn emit_collects_handler_results() {
+	let src = r#"
+	host.on("record.created", (record) => record.id * 2);
+	host.on("record.created", async (record) => {
+		const status = await Promise.resolve(record.status);
+		return status.toUpperCase();
+	});
+	host.on("record.created", () => {});
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let record = Record {
+		id: 21,
+		status: "draft".to_string(),
+	};
+	let results: Vec<JsValue> = script
+		.emit("record.created", &record)
+		.expect("Dispatch succeeds")
+		.into_iter()
+		.map(|r| r.expect("Handler succeeds"))
+		.collect();
+
+	assert_eq!(
+		results,
+		vec![JsValue::from(42), JsValue::from("DRAFT"), JsValue::Null]
+	);
+}
+
+#[test]
+fn emit_without_handlers() {
+	let mut script = Script::from_string("").expect("Initialization succeeds");
+
+	let results = script.emit("record.posted", ()).expect("Dispatch succeeds");
+	assert!(results.is_empty());
+}
+
+#[test]
+fn emit_failing_handler() {
+	let src = r#"
+	host.on("record.approved", () => { throw new Error("not allowed"); });
+	host.on("record.approved", () => "still runs");
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let results = script
+		.emit("record.approved", ())
+		.expect("Dispatch succeeds");
+	assert_eq!(results.len(), 2);
+
+	let err = results[0].as_ref().unwrap_err();
+	assert!(
+		err.to_string().contains("not allowed"),
+		"Error message: {err}"
+	);
+	assert_eq!(results[1].as_ref().unwrap(), &JsValue::from("still runs"));
+}
+
+#[test]
+fn emit_after_off() {
+	let src = r#"
+	let calls = 0;
+	function onPosted() { return ++calls; }
+	host.on("record.posted", onPosted);
+
+	function unsubscribe() { return host.off("record.posted", onPosted); }
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let results = script.emit("record.posted", ()).expect("Dispatch succeeds");
+	assert_eq!(results.len(), 1);
+
+	let removed: bool = script.call("unsubscribe", ()).unwrap();
+	assert!(removed);
+
+	let results = script.emit("record.posted", ()).expect("Dispatch succeeds");
+	assert!(results.is_empty());
+}
@@ -0,0 +1,335 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use js_sandbox::{
+	Access, CallArgs, Fetch, FetchFuture, FetchRequest, FetchResponse, FetchTransport, JsValue,
+	Permissions, Script,
+};
+use serde_json::json;
+
+const CODE: &str = r#"
+	async function getJson(url) {
+		const response = await fetch(url, { headers: { "Accept": "application/json" } });
+		return {
+			status: response.status,
+			ok: response.ok,
+			type: response.headers.get("content-type"),
+			body: await response.json(),
+		};
+	}
+
+	async function post(url, body) {
+		const response = await fetch(url, { method: "post", body });
+		return response.status;
+	}
+
+	async function postBytes(url, bytes) {
+		const response = await fetch(url, { method: "POST", body: new Uint8Array(bytes) });
+		return response.status;
+	}
+
+	async function text(url) {
+		return (await fetch(url)).text();
+	}
+
+	async function withHeader(url, name, value) {
+		const response = await fetch(url, { headers: { [name]: value } });
+		return response.status;
+	}
+"#;
+
+/// Answers with a fixed response and records the requests
+#[derive(Clone)]
+struct Mock {
+	response: FetchResponse,
+	requests: Arc<Mutex<Vec<FetchRequest>>>,
+}
+
+impl Mock {
+	fn new(response: FetchResponse) -> Self {
+		Self {
+			response,
+			requests: Arc::default(),
+		}
+	}
+
+	fn requests(&self) -> Vec<FetchRequest> {
+		self.requests.lock().unwrap().clone()
+	}
+}
+
+impl FetchTransport for Mock {
+	fn send(&self, request: FetchRequest) -> FetchFuture {
+		self.requests.lock().unwrap().push(request);
+		let response = self.response.clone();
+		Box::pin(async move { Ok(response) })
+	}
+}
+
+/// Never answers; records whether the request was dropped
+struct Hanging {
+	dropped: Arc<AtomicBool>,
+}
+
+struct DropFlag(Arc<AtomicBool>);
+
+impl Drop for DropFlag {
+	fn drop(&mut self) {
+		self.0.store(true, Ordering::SeqCst);
+	}
+}
+
+impl FetchTransport for Hanging {
+	fn send(&self, _request: FetchRequest) -> FetchFuture {
+		let flag = DropFlag(self.dropped.clone());
+		Box::pin(async move {
+			let _flag = flag;
+			std::future::pending().await
+		})
+	}
+}
+
+fn fetch_script(fetch: Fetch) -> Script {
+	Script::builder()
+		.fetch(fetch)
+		.permissions(Permissions::new().allow_net("prices.internal"))
+		.build_from_string(CODE)
+		.expect("Initialization succeeds")
+}
+
+fn call_error(script: &mut Script, fn_name: &str, args: impl CallArgs) -> String {
+	let result: Result<JsValue, _> = script.call(fn_name, args);
+	result.unwrap_err().to_string()
+}
+
+#[test]
+fn get_json() {
+	let mock = Mock::new(
+		FetchResponse::new(200, r#"{ "EUR": 1.08 }"#).header("Content-Type", "application/json"),
+	);
+	let mut script = fetch_script(Fetch::new(mock.clone()));
+
+	let result: JsValue = script
+		.call("getJson", ("https://prices.internal/latest?base=USD",))
+		.unwrap();
+	assert_eq!(
+		result,
+		json!({ "status": 200, "ok": true, "type": "application/json", "body": { "EUR": 1.08 } })
+	);
+
+	let requests = mock.requests();
+	assert_eq!(requests.len(), 1);
+	assert_eq!(requests[0].method, "GET");
+	assert_eq!(requests[0].url, "https://prices.internal/latest?base=USD");
+	assert_eq!(
+		requests[0].headers,
+		vec![("accept".to_string(), "application/json".to_string())]
+	);
+}
+
+#[test]
+fn post_bodies() {
+	let mock = Mock::new(FetchResponse::new(204, ""));
+	let mut script = fetch_script(Fetch::new(mock.clone()).methods(["GET", "POST"]));
+
+	let status: u16 = script
+		.call("post", ("http://prices.internal:8080/hook", "payload"))
+		.unwrap();
+	assert_eq!(status, 204);
+
+	let status: u16 = script
+		.call("postBytes", ("http://prices.internal/hook", [1, 2, 3]))
+		.unwrap();
+	assert_eq!(status, 204);
+
+	let requests = mock.requests();
+	assert_eq!(requests[0].method, "POST");
+	assert_eq!(requests[0].body, b"payload");
+	assert_eq!(requests[1].body, vec![1, 2, 3]);
+}
+
+#[test]
+fn requires_net_permission() {
+	let mock = Mock::new(FetchResponse::new(200, ""));
+	let mut script = fetch_script(Fetch::new(mock.clone()));
+
+	let error = call_error(&mut script, "text", ("https://evil.example/steal",));
+	assert!(error.contains("PermissionDenied"), "{error}");
+	assert!(mock.requests().is_empty());
+
+	let denials = script.take_denials();
+	assert_eq!(denials.len(), 1);
+	assert_eq!(denials[0].access, Access::Net);
+	assert_eq!(denials[0].resource, "evil.example:443");
+
+	// Ports can be restricted as well
+	let mut script = Script::builder()
+		.fetch(Fetch::new(mock.clone()))
+		.permissions(Permissions::new().allow_net("prices.internal:8443"))
+		.build_from_string(CODE)
+		.unwrap();
+
+	let result: Result<String, _> = script.call("text", ("https://prices.internal:8443/",));
+	assert!(result.is_ok());
+	let result: Result<String, _> = script.call("text", ("https://prices.internal/",));
+	assert!(result.is_err());
+}
+
+#[test]
+fn forbidden_headers() {
+	let mock = Mock::new(FetchResponse::new(200, ""));
+	let mut script = fetch_script(Fetch::new(mock.clone()));
+
+	for name in [
+		"Host",
+		"connection",
+		"Content-Length",
+		"Transfer-Encoding",
+		"TE",
+		"Upgrade",
+		"Proxy-Authorization",
+	] {
+		let error = call_error(
+			&mut script,
+			"withHeader",
+			("https://prices.internal/", name, "admin.internal"),
+		);
+		assert!(
+			error.contains("cannot be set by scripts"),
+			"{name}: {error}"
+		);
+	}
+	assert!(mock.requests().is_empty());
+
+	let status: u16 = script
+		.call(
+			"withHeader",
+			("https://prices.internal/", "X-Request-Id", "42"),
+		)
+		.unwrap();
+	assert_eq!(status, 200);
+	assert_eq!(
+		mock.requests()[0].headers,
+		vec![("x-request-id".to_string(), "42".to_string())]
+	);
+}
+
+#[test]
+fn limits() {
+	let mock = Mock::new(FetchResponse::new(200, "0123456789"));
+	let mut script = fetch_script(
+		Fetch::new(mock.clone())
+			.methods(["GET", "POST"])
+			.max_request_size(4)
+			.max_response_size(8),
+	);
+
+	let error = call_error(
+		&mut script,
+		"post",
+		("https://prices.internal/", "too long"),
+	);
+	assert!(
+		error.contains("request body exceeds the size limit of 4 bytes"),
+		"{error}"
+	);
+
+	let error = call_error(&mut script, "text", ("https://prices.internal/",));
+	assert!(
+		error.contains("exceeds the size limit of 8 bytes"),
+		"{error}"
+	);
+
+	let error = call_error(&mut script, "text", ("file:///etc/passwd",));
+	assert!(error.contains("scheme 'file' is not supported"), "{error}");
+
+	let mut script = fetch_script(Fetch::new(mock));
+	let error = call_error(&mut script, "post", ("https://prices.internal/", ""));
+	assert!(error.contains("method POST is not allowed"), "{error}");
+}
+
+#[test]
+fn request_timeout() {
+	let dropped = Arc::new(AtomicBool::new(false));
+	let transport = Hanging {
+		dropped: dropped.clone(),
+	};
+	let mut script = fetch_script(Fetch::new(transport).timeout(Duration::from_millis(50)));
+
+	let error = call_error(&mut script, "text", ("https://prices.internal/",));
+	assert!(error.contains("timed out after 50 ms"), "{error}");
+	assert!(dropped.load(Ordering::SeqCst));
+}
+
+#[test]
+fn script_timeout_cancels_requests() {
+	let dropped = Arc::new(AtomicBool::new(false));
+	let transport = Hanging {
+		dropped: dropped.clone(),
+	};
+	let mut script = Script::builder()
+		.fetch(Fetch::new(transport))
+		.permissions(Permissions::new().allow_net("prices.internal"))
+		.timeout(Duration::from_millis(100))
+		.build_from_string(CODE)
+		.unwrap();
+
+	let result: Result<String, _> = script.call("text", ("https://prices.internal/",));
+	assert!(result.is_err());
+
+	// The pending op observes the cancellation once the event loop runs again
+	let _: Result<String, _> = script.call("text", ("https://prices.internal/",));
+	assert!(dropped.load(Ordering::SeqCst));
+}
+
+#[test]
+fn not_installed_by_default() {
+	let mut script = Script::from_string("function check() { return typeof fetch; }")
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("check", ()).unwrap();
+	assert_eq!(result, "undefined");
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn local_server() {
+	use std::io::{Read, Write};
+	use std::net::TcpListener;
+
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let port = listener.local_addr().unwrap().port();
+
+	let server = std::thread::spawn(move || {
+		let (mut stream, _) = listener.accept().unwrap();
+		let mut request = [0; 1024];
+		let len = stream.read(&mut request).unwrap();
+		let request = String::from_utf8_lossy(&request[..len]).into_owned();
+
+		let body = r#"{ "EUR": 1.08 }"#;
+		write!(
+			stream,
+			"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+			body.len()
+		)
+		.unwrap();
+		request
+	});
+
+	let mut script = Script::builder()
+		.fetch(Fetch::http())
+		.permissions(Permissions::new().allow_net(format!("127.0.0.1:{port}")))
+		.build_from_string(CODE)
+		.unwrap();
+
+	let result: JsValue = script
+		.call("getJson", (format!("http://127.0.0.1:{port}/latest"),))
+		.unwrap();
+	assert_eq!(result["body"], json!({ "EUR": 1.08 }));
+
+	let request = server.join().unwrap();
+	assert!(request.starts_with("GET /latest HTTP/1.1"), "{request}");
+}
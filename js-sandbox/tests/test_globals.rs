@@ -0,0 +1,85 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::HashMap;
+
+use js_sandbox::{JsError, Script};
+
+#[test]
+fn set_and_get_global_property() {
+	let src = r#"
+	function greet() { return "Hello " + globalThis.input.name; }
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let input = HashMap::from([("name", "Roger")]);
+	script.set_global("input", &input).unwrap();
+
+	let result: String = script.call("greet", ()).unwrap();
+	assert_eq!(result, "Hello Roger");
+
+	let read_back: HashMap<String, String> = script.get_global("input").unwrap();
+	assert_eq!(read_back["name"], "Roger");
+}
+
+#[test]
+fn read_result_written_by_script() {
+	let src = r#"
+	async function run() { globalThis.stmt = await Promise.resolve("hello world"); }
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let _: () = script.call("run", ()).unwrap();
+	let stmt: String = script.get_global("stmt").unwrap();
+
+	assert_eq!(stmt, "hello world");
+}
+
+#[test]
+fn lexical_bindings() {
+	let src = r#"
+	let counter = 1;
+	const limit = 10;
+	function next() { return ++counter; }
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	assert_eq!(script.get_global::<i32>("counter").unwrap(), 1);
+	assert_eq!(script.get_global::<i32>("limit").unwrap(), 10);
+
+	script.set_global("counter", 41).unwrap();
+	let result: i32 = script.call("next", ()).unwrap();
+	assert_eq!(result, 42);
+
+	assert!(script.set_global("limit", 20).is_err());
+	assert!(script.delete_global("counter").is_err());
+	assert!(script.has_global("counter").unwrap());
+}
+
+#[test]
+fn has_and_delete_global() {
+	let mut script = Script::from_string("var declared = 1;").expect("Initialization succeeds");
+
+	assert!(!script.has_global("context").unwrap());
+	assert_eq!(script.get_global::<Option<i32>>("context").unwrap(), None);
+
+	script.set_global("context", 7).unwrap();
+	assert!(script.has_global("context").unwrap());
+
+	assert!(script.delete_global("context").unwrap());
+	assert!(!script.has_global("context").unwrap());
+	assert!(!script.delete_global("context").unwrap());
+
+	// var declarations are not configurable
+	assert!(!script.delete_global("declared").unwrap());
+}
+
+#[test]
+fn invalid_global_name() {
+	let mut script = Script::from_string("").expect("Initialization succeeds");
+
+	let result = script.set_global("a; throw 1", 0);
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+}
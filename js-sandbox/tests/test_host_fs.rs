@@ -0,0 +1,162 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use js_sandbox::{Access, HostFs, JsValue, Permissions, Script};
+use serde_json::json;
+
+const CODE: &str = r#"
+	function readText(path) {
+		return host.fs.readText(path);
+	}
+
+	function readBytes(path) {
+		return Array.from(host.fs.readBytes(path).subarray(0, 4));
+	}
+
+	function list(path) {
+		return host.fs.list(path).map((entry) => [entry.name, entry.isFile]);
+	}
+
+	function stat(path) {
+		const { isFile, isDirectory, size } = host.fs.stat(path);
+		return { isFile, isDirectory, size };
+	}
+"#;
+
+fn fs_script(fs: HostFs, permissions: Permissions) -> Script {
+	Script::builder()
+		.host_fs(fs)
+		.permissions(permissions)
+		.build_from_string(CODE)
+		.expect("Initialization succeeds")
+}
+
+fn mounts() -> HostFs {
+	HostFs::new()
+		.mount("/templates", "assets/test/fs/templates")
+		.unwrap()
+		.mount("/data/tax", "assets/test/fs/tax")
+		.unwrap()
+}
+
+#[test]
+fn read_list_stat() {
+	let mut script = fs_script(mounts(), Permissions::new().allow_read("assets/test/fs"));
+
+	let text: String = script
+		.call("readText", ("/templates/invoice.txt",))
+		.unwrap();
+	assert_eq!(text, "Invoice {{number}}\n");
+
+	let bytes: Vec<u8> = script.call("readBytes", ("/data/tax/rates.csv",)).unwrap();
+	assert_eq!(bytes, b"coun");
+
+	let entries: JsValue = script.call("list", ("/templates",)).unwrap();
+	assert_eq!(
+		entries,
+		json!([["invoice.txt", true], ["letter.txt", true]])
+	);
+
+	let stat: JsValue = script.call("stat", ("/data/tax/./rates.csv",)).unwrap();
+	assert_eq!(
+		stat,
+		json!({ "isFile": true, "isDirectory": false, "size": 29 })
+	);
+
+	let stat: JsValue = script.call("stat", ("/data/tax",)).unwrap();
+	assert_eq!(stat["isDirectory"], json!(true));
+}
+
+#[test]
+fn no_access_outside_mounts() {
+	let mut script = fs_script(mounts(), Permissions::new().allow_read("/"));
+	let mut error = |path: &str| -> String {
+		let result: Result<String, _> = script.call("readText", (path,));
+		result.unwrap_err().to_string()
+	};
+
+	assert!(error("/etc/passwd").contains("not within a mounted directory"));
+	assert!(error("/data").contains("not within a mounted directory"));
+	assert!(error("/templates/../../etc/passwd").contains("leads outside of the root"));
+	assert!(error("templates/invoice.txt").contains("not absolute"));
+	assert!(error("/templates/..\\..\\Cargo.toml").contains("invalid characters"));
+
+	// `..` within the virtual tree is fine, even across mounts
+	let text: String = script
+		.call("readText", ("/templates/../data/tax/rates.csv",))
+		.unwrap();
+	assert!(text.starts_with("country,rate"));
+}
+
+#[cfg(unix)]
+#[test]
+fn no_symlinks_out_of_mounts() {
+	let dir = std::env::temp_dir().join(format!("js_sandbox_fs_{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let link = dir.join("escape");
+	let _ = std::fs::remove_file(&link);
+	std::os::unix::fs::symlink(std::fs::canonicalize("assets/test/fs/tax").unwrap(), &link)
+		.unwrap();
+
+	let fs = HostFs::new().mount("/jail", &dir).unwrap();
+	let mut script = fs_script(fs, Permissions::new().allow_read("/"));
+
+	let result: Result<String, _> = script.call("readText", ("/jail/escape/rates.csv",));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("leads outside of its mount"));
+
+	std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn size_limit() {
+	let fs = mounts().max_file_size(20);
+	let mut script = fs_script(fs, Permissions::new().allow_read("assets/test/fs"));
+
+	let result: Result<String, _> = script.call("readText", ("/templates/invoice.txt",));
+	assert!(result.is_ok());
+
+	let result: Result<String, _> = script.call("readText", ("/data/tax/rates.csv",));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("exceeds the size limit of 20 bytes"));
+}
+
+#[test]
+fn list_limit() {
+	let fs = mounts().max_list_entries(1);
+	let mut script = fs_script(fs, Permissions::new().allow_read("assets/test/fs"));
+
+	let result: Result<JsValue, _> = script.call("list", ("/templates",));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("'/templates' has more than 1 entries"));
+}
+
+#[test]
+fn requires_read_permission() {
+	let mut script = fs_script(
+		mounts(),
+		Permissions::new().allow_read("assets/test/fs/templates"),
+	);
+
+	let result: Result<String, _> = script.call("readText", ("/data/tax/rates.csv",));
+	assert!(result.unwrap_err().to_string().contains("PermissionDenied"));
+
+	let denials = script.take_denials();
+	assert_eq!(denials.len(), 1);
+	assert_eq!(denials[0].access, Access::Read);
+	assert!(denials[0].resource.ends_with("rates.csv"));
+}
+
+#[test]
+fn not_installed_by_default() {
+	let mut script = Script::from_string("function check() { return typeof host.fs; }")
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("check", ()).unwrap();
+	assert_eq!(result, "undefined");
+}
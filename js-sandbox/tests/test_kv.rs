@@ -0,0 +1,209 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::sync::Arc;
+
+use js_sandbox::{
+	Access, FileKvStore, HostKv, JsValue, KvStore, MemoryKvStore, Permissions, Script,
+};
+use serde_json::json;
+
+const CODE: &str = r#"
+	function get(key) {
+		const value = host.kv.get(key);
+		return value === undefined ? "missing" : value;
+	}
+
+	function set(key, value) {
+		host.kv.set(key, value);
+	}
+
+	function remove(key) {
+		return host.kv.delete(key);
+	}
+
+	function list(prefix) {
+		return host.kv.list(prefix);
+	}
+
+	function increment(key) {
+		return host.kv.update(key, (count) => (count ?? 0) + 1);
+	}
+
+	// Simulates another script changing the value while `fn` runs
+	function contended(key) {
+		let calls = 0;
+		const result = host.kv.update(key, (value) => {
+			calls++;
+			if (calls === 1) {
+				host.kv.set(key, "changed");
+			}
+			return `${value} updated`;
+		});
+		return [result, calls];
+	}
+"#;
+
+fn kv_script(kv: HostKv) -> Script {
+	Script::builder()
+		.kv(kv)
+		.permissions(Permissions::new().allow_host_functions("kv"))
+		.build_from_string(CODE)
+		.expect("Initialization succeeds")
+}
+
+#[test]
+fn get_set_delete_list() {
+	let store = Arc::new(MemoryKvStore::new());
+	let mut script = kv_script(HostKv::new(store.clone(), "plugin"));
+
+	let _: () = script.call("set", ("last-run", "2023-09-30")).unwrap();
+	let _: () = script
+		.call("set", ("orders/2", json!({ "total": 10.5 })))
+		.unwrap();
+	let _: () = script.call("set", ("orders/1", JsValue::Null)).unwrap();
+
+	let value: JsValue = script.call("get", ("last-run",)).unwrap();
+	assert_eq!(value, json!("2023-09-30"));
+	let value: JsValue = script.call("get", ("orders/1",)).unwrap();
+	assert_eq!(value, JsValue::Null);
+	let value: JsValue = script.call("get", ("unknown",)).unwrap();
+	assert_eq!(value, json!("missing"));
+
+	let entries: JsValue = script.call("list", ("orders/",)).unwrap();
+	assert_eq!(
+		entries,
+		json!([
+			{ "key": "orders/1", "value": null },
+			{ "key": "orders/2", "value": { "total": 10.5 } },
+		])
+	);
+
+	let removed: bool = script.call("remove", ("orders/1",)).unwrap();
+	assert!(removed);
+	let removed: bool = script.call("remove", ("orders/1",)).unwrap();
+	assert!(!removed);
+
+	assert_eq!(
+		store.get("plugin", "last-run").unwrap(),
+		Some(json!("2023-09-30"))
+	);
+}
+
+#[test]
+fn atomic_update() {
+	let store = Arc::new(MemoryKvStore::new());
+	let mut script = kv_script(HostKv::new(store, "plugin"));
+
+	for expected in 1..=3 {
+		let count: u32 = script.call("increment", ("runs",)).unwrap();
+		assert_eq!(count, expected);
+	}
+
+	let _: () = script.call("set", ("status", "initial")).unwrap();
+	let result: JsValue = script.call("contended", ("status",)).unwrap();
+	assert_eq!(result, json!(["changed updated", 2]));
+}
+
+#[test]
+fn namespaces_are_isolated() {
+	let store: Arc<dyn KvStore> = Arc::new(MemoryKvStore::new());
+	let mut first = kv_script(HostKv::new(store.clone(), "first"));
+	let mut second = kv_script(HostKv::new(store.clone(), "second"));
+
+	let _: () = first.call("set", ("secret", 42)).unwrap();
+
+	let value: JsValue = second.call("get", ("secret",)).unwrap();
+	assert_eq!(value, json!("missing"));
+	let entries: JsValue = second.call("list", ("",)).unwrap();
+	assert_eq!(entries, json!([]));
+
+	let value: JsValue = first.call("get", ("secret",)).unwrap();
+	assert_eq!(value, json!(42));
+}
+
+#[test]
+fn quotas() {
+	let store = Arc::new(MemoryKvStore::new());
+	let mut script = kv_script(HostKv::new(store, "plugin").max_keys(2).max_bytes(30));
+
+	let _: () = script.call("set", ("a", 1)).unwrap();
+	let _: () = script.call("set", ("b", 2)).unwrap();
+
+	let result: Result<(), _> = script.call("set", ("c", 3));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("would exceed its limit of 2 keys"));
+
+	// Existing keys can still be changed
+	let _: () = script.call("set", ("a", "0123456789")).unwrap();
+
+	let result: Result<(), _> = script.call("set", ("b", "0123456789012345678"));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("would exceed its limit of 30 bytes"));
+
+	let value: JsValue = script.call("get", ("b",)).unwrap();
+	assert_eq!(value, json!(2));
+
+	// Removed entries no longer count
+	let _: bool = script.call("remove", ("a",)).unwrap();
+	let _: () = script.call("set", ("b", "0123456789012345678")).unwrap();
+}
+
+#[test]
+fn persists_in_file() {
+	let path = std::env::temp_dir().join(format!("js_sandbox_kv_{}.json", std::process::id()));
+	let _ = std::fs::remove_file(&path);
+
+	{
+		let store = Arc::new(FileKvStore::open(&path).unwrap());
+		let mut script = kv_script(HostKv::new(store, "plugin"));
+		let _: u32 = script.call("increment", ("runs",)).unwrap();
+		let _: () = script.call("set", ("last-run", "2023-09-30")).unwrap();
+	}
+
+	// As after a restart of the host; the stored entries count against the quota
+	let store = Arc::new(FileKvStore::open(&path).unwrap());
+	let mut script = kv_script(HostKv::new(store, "plugin").max_bytes(30));
+
+	let count: u32 = script.call("increment", ("runs",)).unwrap();
+	assert_eq!(count, 2);
+	let value: JsValue = script.call("get", ("last-run",)).unwrap();
+	assert_eq!(value, json!("2023-09-30"));
+
+	let result: Result<(), _> = script.call("set", ("note", "x"));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("would exceed its limit of 30 bytes"));
+
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn requires_permission() {
+	let store = Arc::new(MemoryKvStore::new());
+	let mut script = Script::builder()
+		.kv(HostKv::new(store, "plugin"))
+		.build_from_string(CODE)
+		.expect("Initialization succeeds");
+
+	let result: Result<JsValue, _> = script.call("get", ("runs",));
+	assert!(result.unwrap_err().to_string().contains("PermissionDenied"));
+
+	let denials = script.take_denials();
+	assert_eq!(denials.len(), 1);
+	assert_eq!(denials[0].access, Access::HostFunction);
+	assert_eq!(denials[0].resource, "kv");
+}
+
+#[test]
+fn not_installed_by_default() {
+	let mut script = Script::from_string("function check() { return typeof host.kv; }")
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("check", ()).unwrap();
+	assert_eq!(result, "undefined");
+}
@@ -0,0 +1,81 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use serde::Serialize;
+
+use js_sandbox::{js_api, JsError, JsResult, Script};
+
+#[derive(Serialize)]
+struct Line {
+	qty: u32,
+	price: f64,
+}
+
+#[js_api]
+trait InvoiceApi {
+	fn invoice(&mut self, lines: Vec<Line>) -> JsResult<f64>;
+	#[allow(non_snake_case)]
+	fn invoiceCount(&mut self) -> i32;
+}
+
+#[test]
+fn call_module_exports() {
+	let mut script = Script::from_module("assets/test/plugin.js").expect("Module can be loaded");
+
+	let total: f64 = script.call("lineTotal", (3, 1.5)).unwrap();
+	assert_eq!(total, 4.5);
+
+	let mut api: InvoiceApi = script.bind_api();
+	let lines = vec![
+		Line {
+			qty: 2,
+			price: 0.25,
+		},
+		Line {
+			qty: 1,
+			price: 10.0,
+		},
+	];
+
+	assert_eq!(api.invoice(lines).unwrap(), 10.5);
+	assert_eq!(api.invoiceCount(), 1);
+}
+
+#[test]
+fn call_default_export() {
+	let mut script =
+		Script::from_module("assets/test/plugin_default.js").expect("Module can be loaded");
+
+	let result: String = script.call("default", ("Roger",)).unwrap();
+	assert_eq!(result, "Hello Roger");
+}
+
+#[test]
+fn module_introspection() {
+	let mut script = Script::from_module("assets/test/plugin.js").expect("Module can be loaded");
+
+	let mut exports = script.exports().unwrap();
+	exports.sort();
+	assert_eq!(exports, vec!["default", "invoice", "lineTotal"]);
+
+	assert!(script.has_function("invoice").unwrap());
+	assert!(script.has_function("invoiceCount").unwrap());
+	assert!(!script.has_function("round2").unwrap());
+}
+
+#[test]
+fn call_error_not_exported() {
+	let mut script = Script::from_module("assets/test/plugin.js").expect("Module can be loaded");
+
+	let result: Result<f64, JsError> = script.call("round2", (1.234,));
+	assert!(result.is_err());
+}
+
+#[test]
+fn classic_script_introspection() {
+	let mut script = Script::from_string("function triple(a) { return 3 * a; }")
+		.expect("Initialization succeeds");
+
+	assert!(script.has_function("triple").unwrap());
+	assert!(!script.has_function("square").unwrap());
+	assert!(script.exports().unwrap().is_empty());
+}
@@ -0,0 +1,474 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use js_sandbox::{ImportMap, JailedFsModuleLoader, JsError, MemoryModuleLoader, Script};
+
+fn plugin_bundle() -> MemoryModuleLoader {
+	[
+		(
+			"main.js",
+			r#"
+			import { money } from "./lib/money.js";
+			export function price(amount) { return money(amount, "EUR"); }
+			"#,
+		),
+		(
+			"lib/money.js",
+			r#"
+			import { round2 } from "../util/round.js";
+			export function money(amount, currency) { return round2(amount) + " " + currency; }
+			"#,
+		),
+		(
+			"util/round.js",
+			"export function round2(v) { return Math.round(v * 100) / 100; }",
+		),
+	]
+	.into_iter()
+	.collect()
+}
+
+#[test]
+fn memory_modules_relative_imports() {
+	let mut script = Script::builder()
+		.module_loader(plugin_bundle())
+		.build_from_module("main.js")
+		.expect("Module can be loaded");
+
+	let result: String = script.call("price", (12.345,)).unwrap();
+	assert_eq!(result, "12.35 EUR");
+}
+
+#[test]
+fn memory_module_specifiers() {
+	assert_eq!(
+		MemoryModuleLoader::specifier("lib/money.js").as_str(),
+		"memory:///lib/money.js"
+	);
+	assert_eq!(
+		MemoryModuleLoader::specifier("./a/../../b.js").as_str(),
+		"memory:///b.js"
+	);
+
+	let loader = plugin_bundle();
+	assert!(loader.contains("/lib/money.js"));
+	assert!(!loader.contains("money.js"));
+}
+
+#[test]
+fn memory_module_missing_import() {
+	let mut loader = MemoryModuleLoader::default();
+	loader.insert("main.js", "import { x } from './missing.js'; export { x };");
+
+	let result = Script::builder()
+		.module_loader(loader)
+		.build_from_module("main.js");
+
+	assert!(result.is_err());
+}
+
+#[test]
+fn memory_module_rejects_bare_import() {
+	let mut loader = MemoryModuleLoader::default();
+	loader.insert("main.js", "import { x } from 'lib.js'; export { x };");
+
+	let result = Script::builder()
+		.module_loader(loader)
+		.build_from_module("main.js");
+
+	assert!(result.is_err());
+}
+
+/// Creates a fresh directory `<tmp>/js-sandbox-<name>` with a `plugins` root and an `outside` sibling
+fn jail_fixture(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("js-sandbox-{name}-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+
+	fs::create_dir_all(dir.join("plugins/lib")).unwrap();
+	fs::create_dir_all(dir.join("outside")).unwrap();
+
+	fs::write(
+		dir.join("plugins/lib/util.js"),
+		"export function twice(v) { return 2 * v; }",
+	)
+	.unwrap();
+	fs::write(
+		dir.join("outside/secret.js"),
+		"export const secret = 'leaked';",
+	)
+	.unwrap();
+
+	dir
+}
+
+fn jailed_script(dir: &Path, main_code: &str) -> Result<Script, JsError> {
+	fs::write(dir.join("plugins/main.js"), main_code).unwrap();
+
+	let loader = JailedFsModuleLoader::new(dir.join("plugins")).expect("Root exists");
+	Script::builder()
+		.module_loader(loader)
+		.build_from_module("main.js")
+}
+
+fn jailed_text_script(dir: &Path, main_code: &str) -> Result<Script, JsError> {
+	fs::write(dir.join("plugins/main.js"), main_code).unwrap();
+
+	let loader = JailedFsModuleLoader::new(dir.join("plugins")).expect("Root exists");
+	Script::builder()
+		.module_loader(loader)
+		.text_imports()
+		.build_from_module("main.js")
+}
+
+#[test]
+fn jailed_relative_import() {
+	let dir = jail_fixture("jail-ok");
+
+	let mut script = jailed_script(
+		&dir,
+		"import { twice } from './lib/util.js'; export function run(v) { return twice(v); }",
+	)
+	.expect("Module can be loaded");
+
+	let result: i32 = script.call("run", (21,)).unwrap();
+	assert_eq!(result, 42);
+}
+
+#[test]
+fn jailed_rejects_escaping_paths() {
+	let dir = jail_fixture("jail-escape");
+	let outside = dir.join("outside/secret.js");
+	let outside_url = format!("file://{}", outside.display());
+
+	for import in [
+		"../outside/secret.js".to_string(),
+		outside.display().to_string(),
+		outside_url,
+	] {
+		let code = format!("import {{ secret }} from '{import}'; export {{ secret }};");
+		let result = jailed_script(&dir, &code);
+
+		assert!(
+			matches!(result, Err(JsError::ModuleResolution(_))),
+			"Import of '{import}' must be rejected"
+		);
+	}
+}
+
+#[cfg(unix)]
+#[test]
+fn jailed_rejects_symlink_escape() {
+	let dir = jail_fixture("jail-symlink");
+	std::os::unix::fs::symlink(dir.join("outside"), dir.join("plugins/linked")).unwrap();
+
+	let result = jailed_script(
+		&dir,
+		"import { secret } from './linked/secret.js'; export { secret };",
+	);
+
+	assert!(matches!(result, Err(JsError::ModuleResolution(_))));
+}
+
+#[test]
+fn jailed_data_urls() {
+	let dir = jail_fixture("jail-data");
+	let code = "import answer from 'data:text/javascript,export%20default%2042;'; export function get() { return answer; }";
+
+	let result = jailed_script(&dir, code);
+	assert!(matches!(result, Err(JsError::ModuleResolution(_))));
+
+	// same import, but allowed (base64 of "export default 42;")
+	let code = "import answer from 'data:text/javascript;base64,ZXhwb3J0IGRlZmF1bHQgNDI7'; export function get() { return answer; }";
+	fs::write(dir.join("plugins/main.js"), code).unwrap();
+
+	let loader = JailedFsModuleLoader::new(dir.join("plugins"))
+		.unwrap()
+		.allow_data_urls(true);
+	let mut script = Script::builder()
+		.module_loader(loader)
+		.build_from_module("main.js")
+		.expect("Module can be loaded");
+
+	let result: i32 = script.call("get", ()).unwrap();
+	assert_eq!(result, 42);
+}
+
+#[test]
+fn jailed_rejects_remote() {
+	let dir = jail_fixture("jail-remote");
+
+	let result = jailed_script(
+		&dir,
+		"import { x } from 'https://example.com/x.js'; export { x };",
+	);
+
+	let err = match result {
+		Err(JsError::ModuleResolution(e)) => e,
+		_ => panic!("Remote import must be rejected"),
+	};
+	assert_eq!(err.specifier(), "https://example.com/x.js");
+}
+
+fn sdk_bundle() -> MemoryModuleLoader {
+	[
+		(
+			"sdk/index.js",
+			"export function money(v) { return v.toFixed(2) + ' EUR'; }",
+		),
+		(
+			"sdk/format/date.js",
+			"export function isoDate(y, m, d) { return new Date(Date.UTC(y, m - 1, d)).toISOString().slice(0, 10); }",
+		),
+		(
+			"sdk-v1/index.js",
+			"export function money(v) { return 'EUR ' + v; }",
+		),
+		(
+			"main.js",
+			r#"
+			import { money } from "@inoerp/sdk";
+			import { isoDate } from "@inoerp/sdk/format/date.js";
+			import { legacyMoney } from "./legacy/plugin.js";
+			export function run() { return [money(5), isoDate(2023, 9, 1), legacyMoney(5)]; }
+			"#,
+		),
+		(
+			"legacy/plugin.js",
+			r#"
+			import { money } from "@inoerp/sdk";
+			export function legacyMoney(v) { return money(v); }
+			"#,
+		),
+	]
+	.into_iter()
+	.collect()
+}
+
+#[test]
+fn import_map_bare_and_scoped_specifiers() {
+	let import_map = ImportMap::from_json(
+		r#"{
+			"imports": {
+				"@inoerp/sdk": "./sdk/index.js",
+				"@inoerp/sdk/": "./sdk/"
+			},
+			"scopes": {
+				"./legacy/": { "@inoerp/sdk": "./sdk-v1/index.js" }
+			}
+		}"#,
+	)
+	.expect("Valid import map");
+
+	let mut script = Script::builder()
+		.module_loader(sdk_bundle())
+		.import_map(import_map)
+		.build_from_module("main.js")
+		.expect("Module can be loaded");
+
+	let result: Vec<String> = script.call("run", ()).unwrap();
+	assert_eq!(result, vec!["5.00 EUR", "2023-09-01", "EUR 5"]);
+}
+
+#[test]
+fn import_map_unmapped_bare_specifier() {
+	let mut import_map = ImportMap::default();
+	import_map.insert("@inoerp/sdk", "./sdk/index.js");
+
+	let mut loader = sdk_bundle();
+	loader.insert(
+		"main.js",
+		"import { x } from '@inoerp/other'; export { x };",
+	);
+
+	let result = Script::builder()
+		.module_loader(loader)
+		.import_map(import_map)
+		.build_from_module("main.js");
+
+	assert!(matches!(result, Err(JsError::ModuleResolution(_))));
+}
+
+#[test]
+fn import_map_respects_jail() {
+	let dir = jail_fixture("jail-import-map");
+	fs::write(
+		dir.join("plugins/main.js"),
+		"import { secret } from 'secrets'; export { secret };",
+	)
+	.unwrap();
+
+	let mut import_map = ImportMap::default();
+	import_map.insert("secrets", "../outside/secret.js");
+
+	let result = Script::builder()
+		.module_loader(JailedFsModuleLoader::new(dir.join("plugins")).unwrap())
+		.import_map(import_map)
+		.build_from_module("main.js");
+
+	assert!(matches!(result, Err(JsError::ModuleResolution(_))));
+}
+
+fn data_bundle() -> MemoryModuleLoader {
+	[
+		(
+			"config/tax.json",
+			r#"{ "rates": { "DE": 0.19, "FR": 0.2 } }"#,
+		),
+		(
+			"templates/line.html",
+			"<td>{{qty}}</td>\n<td>\"{{item}}\"</td>",
+		),
+	]
+	.into_iter()
+	.collect()
+}
+
+#[test]
+fn json_and_text_imports() {
+	let mut loader = data_bundle();
+	loader.insert(
+		"main.js",
+		r#"
+		import tax from "./config/tax.json" with { type: "json" };
+		import line from "./templates/line.html" with { type: "text" };
+		export function rate(country) { return tax.rates[country]; }
+		export function template() { return line; }
+		export async function loadTemplate() {
+			const module = await import("./templates/line.html", { with: { type: "text" } });
+			return module.default;
+		}
+		"#,
+	);
+
+	let mut script = Script::builder()
+		.module_loader(loader)
+		.text_imports()
+		.build_from_module("main.js")
+		.expect("Module can be loaded");
+
+	let rate: f64 = script.call("rate", ("DE",)).unwrap();
+	assert_eq!(rate, 0.19);
+
+	let template: String = script.call("template", ()).unwrap();
+	assert_eq!(template, "<td>{{qty}}</td>\n<td>\"{{item}}\"</td>");
+
+	let template: String = script.call("loadTemplate", ()).unwrap();
+	assert_eq!(template, "<td>{{qty}}</td>\n<td>\"{{item}}\"</td>");
+}
+
+#[test]
+fn text_imports_are_opt_in() {
+	let mut loader = data_bundle();
+	loader.insert(
+		"main.js",
+		"import line from './templates/line.html' with { type: 'text' }; export { line };",
+	);
+
+	let result = Script::builder()
+		.module_loader(loader)
+		.build_from_module("main.js");
+	assert!(result.is_err());
+
+	// The default loader could read any file of the host
+	let result = Script::builder()
+		.text_imports()
+		.build_from_string("async function read(path) { return (await import(path, { with: { type: 'text' } })).default; }");
+	let error = result.err().expect("Building fails").to_string();
+	assert!(
+		error.contains("text imports require a module loader"),
+		"{error}"
+	);
+}
+
+#[test]
+fn text_imports_only_from_attributes() {
+	let line_3 = r#"import line from "./templates/line.html" with { type: "text" }; export function fail() { throw new Error(line); }"#;
+	let mut loader = data_bundle();
+	loader.insert(
+		"main.js",
+		format!(
+			r#"// import line from "./templates/line.html" with {{ type: "text" }};
+			export function doc() {{ return 'import("./templates/line.html", {{ with: {{ type: "text" }} }})'; }}
+{line_3}"#
+		),
+	);
+
+	let mut script = Script::builder()
+		.module_loader(loader)
+		.text_imports()
+		.build_from_module("main.js")
+		.expect("Module can be loaded");
+
+	// Comments and other strings are left alone
+	let doc: String = script.call("doc", ()).unwrap();
+	assert_eq!(
+		doc,
+		r#"import("./templates/line.html", { with: { type: "text" } })"#
+	);
+
+	// Code after the rewritten import keeps its position
+	let column = line_3.find("new Error").unwrap() + 1;
+	let result: Result<(), _> = script.call("fail", ());
+	let error = result.unwrap_err().to_string();
+	assert!(error.contains(&format!("main.js:3:{column}")), "{error}");
+
+	// The query marking text imports cannot be written by scripts
+	let mut loader = data_bundle();
+	loader.insert(
+		"main.js",
+		"import line from './templates/line.html?raw'; export { line };",
+	);
+	let result = Script::builder()
+		.module_loader(loader)
+		.text_imports()
+		.build_from_module("main.js");
+	let error = result.err().expect("Building fails").to_string();
+	assert!(error.contains("reserved"), "{error}");
+}
+
+#[test]
+fn json_import_requires_type() {
+	let mut loader = data_bundle();
+	loader.insert(
+		"main.js",
+		"import tax from './config/tax.json'; export { tax };",
+	);
+
+	let result = Script::builder()
+		.module_loader(loader)
+		.build_from_module("main.js");
+
+	assert!(result.is_err());
+}
+
+#[test]
+fn jailed_data_imports() {
+	let dir = jail_fixture("jail-data-imports");
+	fs::write(dir.join("plugins/lib/limits.json"), r#"{ "max": 3 }"#).unwrap();
+	fs::write(dir.join("outside/secret.txt"), "leaked").unwrap();
+
+	let mut script = jailed_text_script(
+		&dir,
+		r#"
+		import limits from "./lib/limits.json" with { type: "json" };
+		import util from "./lib/util.js" with { type: "text" };
+		export function max() { return limits.max; }
+		export function source() { return util; }
+		"#,
+	)
+	.expect("Module can be loaded");
+
+	let max: i32 = script.call("max", ()).unwrap();
+	assert_eq!(max, 3);
+
+	let source: String = script.call("source", ()).unwrap();
+	assert_eq!(source, "export function twice(v) { return 2 * v; }");
+
+	let result = jailed_text_script(
+		&dir,
+		"import secret from '../outside/secret.txt' with { type: 'text' }; export { secret };",
+	);
+	assert!(matches!(result, Err(JsError::ModuleResolution(_))));
+}
@@ -0,0 +1,151 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use js_sandbox::{
+	Access, JsValue, MemoryModuleLoader, NodeCompat, PermissionDenied, Permissions, Script,
+};
+use serde_json::json;
+
+fn node_script(node: NodeCompat, js_code: &str) -> Script {
+	let loader: MemoryModuleLoader = [
+		(
+			"lib/index.js",
+			"const { rate } = require('./rates.json'); const fmt = require('./format'); \
+			 module.exports = { price: (n) => fmt(n * rate) };",
+		),
+		(
+			"lib/format.js",
+			"module.exports = (n) => n.toFixed(2) + ' EUR';",
+		),
+		("lib/rates.json", r#"{ "rate": 0.5 }"#),
+		("broken.js", "throw new Error('init failed');"),
+	]
+	.into_iter()
+	.collect();
+
+	Script::builder()
+		.module_loader(loader)
+		.node_compat(node)
+		.build_from_string(js_code)
+		.expect("Initialization succeeds")
+}
+
+#[test]
+fn require_commonjs() {
+	let mut script = node_script(
+		NodeCompat::new(),
+		r#"
+		function price(n) {
+			return require("./lib").price(n);
+		}
+
+		function cached() {
+			return require("./lib/index.js") === require("./lib");
+		}
+
+		function broken() {
+			return require("./broken.js");
+		}
+		"#,
+	);
+
+	let result: String = script.call("price", (7,)).unwrap();
+	assert_eq!(result, "3.50 EUR");
+
+	let result: bool = script.call("cached", ()).unwrap();
+	assert!(result);
+
+	let result: Result<JsValue, _> = script.call("broken", ());
+	assert!(result.unwrap_err().to_string().contains("init failed"));
+}
+
+#[test]
+fn process_env() {
+	std::env::set_var("JS_SANDBOX_TEST_ALLOWED", "yes");
+	std::env::set_var("JS_SANDBOX_TEST_SECRET", "hidden");
+
+	let mut script = node_script(
+		NodeCompat::new().allow_env("JS_SANDBOX_TEST_ALLOWED"),
+		r#"
+		function env() {
+			const env = process.env;
+			env.LOCAL = "1";
+			return [env.JS_SANDBOX_TEST_ALLOWED, env.JS_SANDBOX_TEST_SECRET ?? null, Object.keys(env)];
+		}
+
+		function exit() {
+			process.exit(1);
+		}
+		"#,
+	);
+
+	let result: JsValue = script.call("env", ()).unwrap();
+	assert_eq!(
+		result,
+		json!(["yes", null, ["JS_SANDBOX_TEST_ALLOWED", "LOCAL"]])
+	);
+	assert!(std::env::var("LOCAL").is_err());
+
+	// Reading a variable that is not allowed yields undefined, but is recorded
+	assert_eq!(
+		script.take_denials(),
+		vec![PermissionDenied {
+			access: Access::Env,
+			resource: "JS_SANDBOX_TEST_SECRET".to_string(),
+		}]
+	);
+
+	let result: Result<JsValue, _> = script.call("exit", ());
+	assert!(result.unwrap_err().to_string().contains("process.exit(1)"));
+}
+
+#[test]
+fn process_env_permissions() {
+	std::env::set_var("JS_SANDBOX_TEST_PERMITTED", "yes");
+
+	let mut script = Script::builder()
+		.node_compat(NodeCompat::new())
+		.permissions(Permissions::new().allow_env("JS_SANDBOX_TEST_PERMITTED"))
+		.build_from_string(
+			"function env() { return [process.env.JS_SANDBOX_TEST_PERMITTED, Object.keys(process.env)]; }",
+		)
+		.expect("Initialization succeeds");
+
+	let result: JsValue = script.call("env", ()).unwrap();
+	assert_eq!(result, json!(["yes", ["JS_SANDBOX_TEST_PERMITTED"]]));
+	assert!(script.take_denials().is_empty());
+}
+
+#[test]
+fn buffer() {
+	let mut script = node_script(
+		NodeCompat::new(),
+		r#"
+		function encode(text) {
+			const buf = Buffer.from(text);
+			return [buf.toString("hex"), buf.toString("base64"), buf.length, Buffer.isBuffer(buf)];
+		}
+
+		function decode() {
+			const fromHex = Buffer.from("68656c6c6f", "hex");
+			const fromBase64 = Buffer.from("aGVsbG8=", "base64");
+			return [fromHex.toString(), fromHex.equals(fromBase64), Buffer.concat([fromHex, Buffer.from("!")]).toString()];
+		}
+		"#,
+	);
+
+	let result: JsValue = script.call("encode", ("hé",)).unwrap();
+	assert_eq!(result, json!(["68c3a9", "aMOp", 3, true]));
+
+	let result: JsValue = script.call("decode", ()).unwrap();
+	assert_eq!(result, json!(["hello", true, "hello!"]));
+}
+
+#[test]
+fn not_installed_by_default() {
+	let mut script =
+		Script::from_string("function check() { return [typeof require, typeof Buffer]; }")
+			.expect("Initialization succeeds");
+
+	let result: Vec<String> = script.call("check", ()).unwrap();
+	assert_eq!(result, vec!["undefined", "undefined"]);
+}
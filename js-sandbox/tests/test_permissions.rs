@@ -0,0 +1,121 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use deno_core::v8::{FunctionCallbackArguments, HandleScope, ReturnValue};
+use js_sandbox::exposed_func::ExposedFunction;
+use js_sandbox::{Access, JsValue, NodeCompat, PermissionDenied, Permissions, Script};
+use serde_json::json;
+
+struct Answer;
+
+impl ExposedFunction for Answer {
+	fn rust_func_for_js(
+		_scope: &mut HandleScope,
+		_args: FunctionCallbackArguments,
+		mut rv: ReturnValue,
+	) {
+		rv.set_int32(42);
+	}
+
+	fn name() -> String {
+		"answer".to_string()
+	}
+
+	fn group() -> String {
+		"math".to_string()
+	}
+}
+
+const CODE: &str = r#"
+	function callAnswer() {
+		try {
+			return answer();
+		} catch (e) {
+			return [e instanceof host.PermissionDenied, e.name, e.message];
+		}
+	}
+
+	function readSettings() {
+		try {
+			return require("./assets/test/node/settings.json");
+		} catch (e) {
+			return [e instanceof host.PermissionDenied, e.message];
+		}
+	}
+"#;
+
+#[test]
+fn host_function_groups() {
+	let mut script = Script::from_string(CODE).expect("Initialization succeeds");
+	script.add_exposed_func::<Answer>();
+
+	let result: JsValue = script.call("callAnswer", ()).unwrap();
+	assert_eq!(
+		result,
+		json!([
+			true,
+			"PermissionDenied",
+			"Requires host function access to \"math\""
+		])
+	);
+
+	script.set_permissions(Permissions::new().allow_host_functions("math"));
+	let result: i32 = script.call("callAnswer", ()).unwrap();
+	assert_eq!(result, 42);
+
+	assert_eq!(
+		script.take_denials(),
+		vec![PermissionDenied {
+			access: Access::HostFunction,
+			resource: "math".to_string(),
+		}]
+	);
+	assert!(script.take_denials().is_empty());
+}
+
+#[test]
+fn read_paths() {
+	let build = |permissions: Permissions| {
+		Script::builder()
+			.node_compat(NodeCompat::new())
+			.permissions(permissions)
+			.build_from_string(CODE)
+			.expect("Initialization succeeds")
+	};
+
+	let mut script = build(Permissions::new().allow_read("assets/test/other"));
+	let result: (bool, String) = script.call("readSettings", ()).unwrap();
+	assert!(result.0);
+	assert!(result.1.starts_with("Requires read access to"));
+
+	let denials = script.take_denials();
+	assert_eq!(denials.len(), 1);
+	assert_eq!(denials[0].access, Access::Read);
+	assert!(denials[0].resource.ends_with("settings.json"));
+
+	// Parent directory references don't escape the allowed directory
+	let mut script = build(Permissions::new().allow_read("assets/test/node/../node"));
+	let result: JsValue = script.call("readSettings", ()).unwrap();
+	assert_eq!(result, json!({ "currency": "EUR" }));
+}
+
+#[test]
+fn permission_checks() {
+	let permissions = Permissions::new()
+		.allow_read("assets/test")
+		.allow_net("api.example.com")
+		.allow_net("[::1]:8080")
+		.allow_env("TZ");
+
+	assert!(permissions.allows_read("assets/test/node/settings.json"));
+	assert!(!permissions.allows_read("assets/test/../../Cargo.toml"));
+	assert!(!permissions.allows_write("assets/test/node/settings.json"));
+
+	assert!(permissions.allows_net("API.example.com", Some(443)));
+	assert!(permissions.allows_net("[::1]", Some(8080)));
+	assert!(!permissions.allows_net("[::1]", Some(8081)));
+	assert!(!permissions.allows_net("example.com", None));
+
+	assert!(permissions.allows_env("TZ"));
+	assert!(!permissions.allows_env("HOME"));
+	assert!(!permissions.allows_host_functions("kv"));
+}
@@ -0,0 +1,123 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use js_sandbox::{JsError, Script, ScriptPool};
+
+const COUNTER: &str = r#"
+	let calls = 0;
+	function count() { return ++calls; }
+	function fail() { throw new Error("failed"); }
+	function spin() { while (true) {} }
+	async function delayed(v) { await Promise.resolve(); return v * 2; }
+	async function hang() { await new Promise(() => {}); }
+"#;
+
+#[tokio::test]
+async fn pool_call() {
+	let pool = ScriptPool::builder()
+		.workers(2)
+		.build(|| Script::from_string(COUNTER))
+		.expect("Pool can be created");
+
+	let result: i32 = pool.call("delayed", (21,)).await.unwrap();
+	assert_eq!(result, 42);
+
+	let metrics = pool.metrics();
+	assert_eq!(metrics.workers, 2);
+	assert_eq!(metrics.calls, 1);
+	assert_eq!(metrics.errors, 0);
+	assert_eq!(metrics.busy, 0);
+	assert_eq!(metrics.queued, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pool_concurrent_calls() {
+	let pool = Arc::new(
+		ScriptPool::builder()
+			.workers(4)
+			.build(|| Script::from_string(COUNTER))
+			.unwrap(),
+	);
+
+	let tasks: Vec<_> = (0..40)
+		.map(|i| {
+			let pool = pool.clone();
+			tokio::spawn(async move { pool.call::<_, i32>("delayed", (i,)).await })
+		})
+		.collect();
+
+	for (i, task) in tasks.into_iter().enumerate() {
+		assert_eq!(task.await.unwrap().unwrap(), 2 * i as i32);
+	}
+	assert_eq!(pool.metrics().calls, 40);
+}
+
+#[tokio::test]
+async fn pool_recycles_after_max_calls() {
+	let pool = ScriptPool::builder()
+		.workers(1)
+		.max_calls(3)
+		.build(|| Script::from_string(COUNTER))
+		.unwrap();
+
+	let mut counts = Vec::new();
+	for _ in 0..5 {
+		counts.push(pool.call::<_, i32>("count", ()).await.unwrap());
+	}
+
+	assert_eq!(counts, vec![1, 2, 3, 1, 2]);
+	assert_eq!(pool.metrics().recycled, 1);
+}
+
+#[tokio::test]
+async fn pool_recycles_on_error() {
+	let pool = ScriptPool::builder()
+		.workers(1)
+		.build(|| Script::from_string(COUNTER).map(|s| s.with_timeout(Duration::from_millis(100))))
+		.unwrap();
+
+	assert_eq!(pool.call::<_, i32>("count", ()).await.unwrap(), 1);
+
+	let result = pool.call::<_, ()>("fail", ()).await;
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+	assert_eq!(pool.call::<_, i32>("count", ()).await.unwrap(), 1);
+
+	let result = pool.call::<_, ()>("spin", ()).await;
+	assert!(result.is_err());
+	assert_eq!(pool.call::<_, i32>("count", ()).await.unwrap(), 1);
+
+	let metrics = pool.metrics();
+	assert_eq!(metrics.calls, 5);
+	assert_eq!(metrics.errors, 2);
+	assert_eq!(metrics.recycled, 2);
+}
+
+#[tokio::test]
+async fn pool_survives_unsettled_promise() {
+	let pool = ScriptPool::builder()
+		.workers(1)
+		.build(|| Script::from_string(COUNTER))
+		.unwrap();
+
+	let result = pool.call::<_, ()>("hang", ()).await;
+	let error = result.unwrap_err().to_string();
+	assert!(error.contains("promise never settled"), "{error}");
+
+	assert_eq!(pool.call::<_, i32>("count", ()).await.unwrap(), 1);
+
+	let metrics = pool.metrics();
+	assert_eq!(metrics.workers, 1);
+	assert_eq!(metrics.busy, 0);
+	assert_eq!(metrics.errors, 1);
+}
+
+#[test]
+fn pool_factory_error() {
+	let result = ScriptPool::builder()
+		.workers(2)
+		.build(|| Script::from_string("function broken( {"));
+
+	assert!(result.is_err());
+}
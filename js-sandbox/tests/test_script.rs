@@ -9,7 +9,7 @@ use deno_core::{serde_v8, v8};
 use js_sandbox::exposed_func::{DefaultExposedFunction, ExposedFunction, ExposedObject};
 use serde::{Deserialize, Serialize};
 
-use js_sandbox::{AnyError, JsError, Script};
+use js_sandbox::{AnyError, JsError, Permissions, Script};
 use util::expect_error;
 
 mod util;
@@ -189,8 +189,18 @@ fn call() {
 		};
 	}"#;
 
+	// Host functions are denied unless their group is allowed
+	let mut denied = Script::rd_get_run_time().expect("Initialization succeeds");
+	denied.add_exposed_func::<StandardExposedFunction>();
+	let error = denied.rd_run_string(src).err().expect("standard_func is denied");
+	assert!(error.to_string().contains("PermissionDenied"), "{error}");
+
 	//let mut script = Script::from_string(src).expect("Initialization succeeds");
-	let mut script2 = Script::rd_get_run_time().expect("Initialization succeeds");
+	let permissions = Permissions::new()
+		.allow_host_functions("standard_func")
+		.allow_host_functions("db_func");
+	let mut script2 =
+		Script::rd_get_run_time_with_permissions(permissions).expect("Initialization succeeds");
 
 	let exp_obj = ExposedObject::new("standard_func".to_string(), rust_func_for_js);
 	// script2.add_exposed_object(get_exposed_object1());
@@ -198,7 +208,7 @@ fn call() {
 	//script2.add_exposed_func2::<StandardExposedFunction>(exp_obj);
 	 script2.add_exposed_func::<StandardExposedFunction>();
 	 script2.add_exposed_func::<DatabaseExposedFunction>();
-	script2.rd_run_string(src);
+	script2.rd_run_string(src).expect("host functions are allowed");
 	let x = script2.rd_run_file("./assets/test/test.js");
 	if let Err(err) = x {
 		eprintln!("Error is {:?}", err);
@@ -407,6 +417,36 @@ fn call_error_timeout() {
 	);
 }
 
+#[test]
+fn call_after_async_timeout() {
+	let js_code = r#"
+	let resume;
+	async function pending() {
+		// The timer keeps the call waiting until it times out
+		await new Promise((resolve) => { resume = resolve; setTimeout(() => {}, 10000); });
+		return "stale";
+	}
+	function next(value) {
+		resume?.();
+		resume = undefined;
+		return value;
+	}
+	"#;
+
+	let mut script = Script::from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_timeout(Duration::from_millis(100));
+
+	let result: Result<String, JsError> = script.call("pending", ());
+	assert!(result.is_err());
+
+	// The pending function completes during this call; its result must not be mixed up with later ones
+	let result: String = script.call("next", ("second",)).unwrap();
+	assert_eq!(result, "second");
+	let result: String = script.call("next", ("third",)).unwrap();
+	assert_eq!(result, "third");
+}
+
 #[test]
 fn call_async() {
 	let src = r#"
@@ -0,0 +1,78 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use js_sandbox::{js_api, JsResult, Script, ScriptHandle};
+
+#[js_api]
+trait GreetApi {
+	fn greet(&mut self, name: &str) -> JsResult<String>;
+}
+
+const CODE: &str = r#"
+	function greet(name) { return greeting + ", " + name; }
+	function add(a, b) { return a + b; }
+"#;
+
+fn assert_send_sync<T: Send + Sync + Clone>() {}
+
+#[test]
+fn handle_is_send_sync() {
+	assert_send_sync::<ScriptHandle>();
+}
+
+#[tokio::test]
+async fn handle_call_and_globals() {
+	let handle = Script::spawn(|| Script::from_string(CODE)).expect("Script can be spawned");
+
+	let sum: i32 = handle.call("add", (2, 3)).await.unwrap();
+	assert_eq!(sum, 5);
+
+	handle.set_global("greeting", "Hello").await.unwrap();
+	let greeting: String = handle.call("greet", ("Rust",)).await.unwrap();
+	assert_eq!(greeting, "Hello, Rust");
+
+	let greeting = handle
+		.bind_api(|script| script.bind_api::<GreetApi>().greet("API"))
+		.await
+		.unwrap()
+		.unwrap();
+	assert_eq!(greeting, "Hello, API");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn handle_shared_between_tasks() {
+	let handle = Script::spawn(|| Script::from_string("let n = 0; function inc() { return ++n; }"))
+		.expect("Script can be spawned");
+
+	let tasks: Vec<_> = (0..10)
+		.map(|_| {
+			let handle = handle.clone();
+			tokio::spawn(async move { handle.call::<_, i32>("inc", ()).await.unwrap() })
+		})
+		.collect();
+
+	let mut results = Vec::new();
+	for task in tasks {
+		results.push(task.await.unwrap());
+	}
+	results.sort();
+
+	// all clones share the same script
+	assert_eq!(results, (1..=10).collect::<Vec<_>>());
+}
+
+#[test]
+fn handle_builder_error() {
+	let result = Script::spawn(|| Script::from_string("function broken( {"));
+	assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn handle_after_panic() {
+	let handle = Script::spawn(|| Script::from_string(CODE)).unwrap();
+
+	let result = handle.with_script(|_| panic!("in closure")).await;
+	assert!(result.is_err());
+
+	let result = handle.call::<_, i32>("add", (1, 2)).await;
+	assert!(result.is_err());
+}
@@ -0,0 +1,128 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::future;
+
+use js_sandbox::{Fetch, FetchFuture, FetchRequest, FetchTransport, JsError, Permissions, Script};
+
+const PLUGIN: &str = r#"
+	// expensive initialization, done once when creating the snapshot
+	const rates = {};
+	for (let i = 0; i < 1000; ++i) {
+		rates["R" + i] = i / 1000;
+	}
+
+	let calls = 0;
+
+	function rate(code) {
+		calls += 1;
+		return rates[code];
+	}
+
+	function callCount() {
+		return calls;
+	}
+
+	function fail() {
+		throw new Error("from snapshot");
+	}
+"#;
+
+#[test]
+fn restore_snapshot() {
+	let snapshot = Script::create_snapshot(PLUGIN).expect("Snapshot can be created");
+
+	let mut script = Script::from_snapshot(&snapshot).expect("Snapshot can be restored");
+	let rate: f64 = script.call("rate", ("R250",)).unwrap();
+	assert_eq!(rate, 0.25);
+
+	let count: u32 = script.call("callCount", ()).unwrap();
+	assert_eq!(count, 1);
+
+	// each restored script starts from the captured state
+	let mut other = Script::from_snapshot(&snapshot).expect("Snapshot can be restored");
+	let count: u32 = other.call("callCount", ()).unwrap();
+	assert_eq!(count, 0);
+}
+
+#[test]
+fn snapshot_keeps_host_api_and_file_name() {
+	let snapshot = Script::builder()
+		.file_name("rates.js")
+		.create_snapshot(&format!(
+			"{PLUGIN}\nhost.on('ping', v => v + 1); console.log('initialized');"
+		))
+		.expect("Snapshot can be created");
+
+	let mut script = Script::from_snapshot(&snapshot).expect("Snapshot can be restored");
+	assert_eq!(script.file_name(), "rates.js");
+
+	let results = script.emit("ping", 41).unwrap();
+	assert_eq!(results.len(), 1);
+	assert_eq!(results[0].as_ref().unwrap(), &serde_json::json!(42));
+
+	let err = script.call::<_, ()>("fail", ()).unwrap_err();
+	assert!(err.to_string().contains("rates.js:"), "{err}");
+	assert!(err
+		.render(false)
+		.contains("throw new Error(\"from snapshot\");"));
+}
+
+#[test]
+fn snapshot_cancels_timers() {
+	let snapshot = Script::create_snapshot(
+		r#"
+		let ticks = 0;
+		setTimeout(() => ticks++, 0);
+		Promise.resolve().then(() => setInterval(() => ticks++, 1));
+
+		function tickCount() {
+			return new Promise((resolve) => setTimeout(() => resolve(ticks), 20));
+		}
+		"#,
+	)
+	.expect("Snapshot can be created");
+
+	// Neither the timers nor their callbacks survive; new timers work
+	let mut script = Script::from_snapshot(&snapshot).expect("Snapshot can be restored");
+	let ticks: u32 = script.call("tickCount", ()).unwrap();
+	assert_eq!(ticks, 0);
+}
+
+/// Never answers
+struct Hanging;
+
+impl FetchTransport for Hanging {
+	fn send(&self, _request: FetchRequest) -> FetchFuture {
+		Box::pin(future::pending())
+	}
+}
+
+#[test]
+fn snapshot_refuses_pending_ops() {
+	let result = Script::builder()
+		.fetch(Fetch::new(Hanging))
+		.permissions(Permissions::new().allow_net("prices.internal"))
+		.create_snapshot("fetch('https://prices.internal/latest').catch(() => {});");
+
+	let error = result.unwrap_err().to_string();
+	assert!(
+		error.contains("still waits for async operations"),
+		"{error}"
+	);
+}
+
+#[test]
+fn snapshot_error_in_code() {
+	let result = Script::create_snapshot("function broken( {");
+	assert!(result.is_err());
+}
+
+#[test]
+fn invalid_snapshot() {
+	let result = Script::from_snapshot(b"not a snapshot");
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+
+	let mut snapshot = Script::create_snapshot(PLUGIN).unwrap();
+	snapshot.truncate(30);
+	assert!(Script::from_snapshot(&snapshot).is_err());
+}
@@ -0,0 +1,283 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "sqlite")]
+
+use std::sync::Arc;
+
+use js_sandbox::exposed_func::{DefaultExposedFunction, SqlSelectExposedFunction};
+use js_sandbox::{Access, CallArgs, HostSql, JsValue, Permissions, Script, SqliteExecutor};
+use serde_json::json;
+
+const CODE: &str = r#"
+	function select(query, params) {
+		return sqlSelect(query, params);
+	}
+
+	function execute(query, params) {
+		return sqlExecute(query, params);
+	}
+
+	function byCustomer(customer) {
+		return sqlSelect`SELECT id FROM invoices WHERE customer = ${customer} ORDER BY id`;
+	}
+
+	function typeOf(query, params) {
+		return typeof sqlSelect(query, params)[0].value;
+	}
+"#;
+
+fn executor() -> Arc<SqliteExecutor> {
+	let executor = SqliteExecutor::open_in_memory().unwrap();
+	executor
+		.execute_batch(
+			"CREATE TABLE invoices (id INTEGER PRIMARY KEY, customer TEXT NOT NULL, total REAL, paid INTEGER NOT NULL);
+			INSERT INTO invoices VALUES (1, 'acme', 120.5, 0), (2, 'acme', 80, 1), (3, 'globex', NULL, 0);",
+		)
+		.unwrap();
+	Arc::new(executor)
+}
+
+fn sql_script(sql: HostSql) -> Script {
+	Script::builder()
+		.sql(sql)
+		.permissions(Permissions::new().allow_host_functions("sql"))
+		.build_from_string(CODE)
+		.expect("Initialization succeeds")
+}
+
+fn call_error(script: &mut Script, fn_name: &str, args: impl CallArgs) -> String {
+	let result: Result<JsValue, _> = script.call(fn_name, args);
+	result.unwrap_err().to_string()
+}
+
+#[test]
+fn select_rows() {
+	let mut script = sql_script(HostSql::new(executor()));
+
+	let rows: JsValue = script
+		.call(
+			"select",
+			(
+				"SELECT id, total, paid FROM invoices WHERE customer = ? ORDER BY id",
+				["acme"],
+			),
+		)
+		.unwrap();
+	assert_eq!(
+		rows,
+		json!([
+			{ "id": 1, "total": 120.5, "paid": 0 },
+			{ "id": 2, "total": 80.0, "paid": 1 },
+		])
+	);
+
+	let rows: JsValue = script
+		.call(
+			"select",
+			("SELECT total FROM invoices WHERE id = ?", json!([3])),
+		)
+		.unwrap();
+	assert_eq!(rows, json!([{ "total": null }]));
+
+	let rows: JsValue = script.call("byCustomer", ("globex",)).unwrap();
+	assert_eq!(rows, json!([{ "id": 3 }]));
+
+	// Constant literals are part of the query, not values
+	let rows: JsValue = script
+		.call(
+			"select",
+			(
+				"SELECT id, COALESCE(total, '') AS total FROM invoices WHERE customer = 'globex'",
+				json!([]),
+			),
+		)
+		.unwrap();
+	assert_eq!(rows, json!([{ "id": 3, "total": "" }]));
+}
+
+#[test]
+fn parameters_are_not_spliced() {
+	let mut script = sql_script(HostSql::new(executor()));
+
+	let rows: JsValue = script.call("byCustomer", ("acme' OR '1' = '1",)).unwrap();
+	assert_eq!(rows, json!([]));
+
+	let kind: String = script
+		.call("typeOf", ("SELECT ? AS value", ["1 + 1"]))
+		.unwrap();
+	assert_eq!(kind, "string");
+
+	let error = call_error(&mut script, "select", ("SELECT ?", json!([{ "id": 1 }])));
+	assert!(
+		error.contains("SQL parameter 1 must be null, a boolean, a number or a string"),
+		"{error}"
+	);
+
+	let error = call_error(&mut script, "select", ("SELECT ?", "acme"));
+	assert!(
+		error.contains("parameters must be passed as one array"),
+		"{error}"
+	);
+}
+
+#[test]
+fn execute_statements() {
+	let executor = executor();
+	let mut script = sql_script(HostSql::new(executor.clone()));
+
+	let changed: u64 = script
+		.call(
+			"execute",
+			(
+				"UPDATE invoices SET paid = ? WHERE customer = ?",
+				json!([true, "acme"]),
+			),
+		)
+		.unwrap();
+	assert_eq!(changed, 2);
+
+	let rows: JsValue = script
+		.call(
+			"select",
+			(
+				"SELECT COUNT(*) AS open FROM invoices WHERE paid = 0",
+				json!([]),
+			),
+		)
+		.unwrap();
+	assert_eq!(rows, json!([{ "open": 1 }]));
+
+	// sqlSelect() cannot be used to sneak in writes
+	let error = call_error(&mut script, "select", ("DELETE FROM invoices", json!([])));
+	assert!(
+		error.contains("statement would modify the database"),
+		"{error}"
+	);
+}
+
+#[test]
+fn read_only() {
+	let mut script = sql_script(HostSql::new(executor()).read_only());
+
+	let error = call_error(
+		&mut script,
+		"execute",
+		("DELETE FROM invoices WHERE id = ?", [1]),
+	);
+	assert!(error.contains("SQL access is read-only"), "{error}");
+
+	let rows: JsValue = script
+		.call(
+			"select",
+			("SELECT COUNT(*) AS count FROM invoices", json!([])),
+		)
+		.unwrap();
+	assert_eq!(rows, json!([{ "count": 3 }]));
+}
+
+#[test]
+fn read_only_connection() {
+	let path = std::env::temp_dir().join(format!("js_sandbox_sql_{}.db", std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	SqliteExecutor::open(&path)
+		.unwrap()
+		.execute_batch(
+			"CREATE TABLE invoices (id INTEGER PRIMARY KEY); INSERT INTO invoices VALUES (1);",
+		)
+		.unwrap();
+
+	// The database refuses writes even where HostSql allows them
+	let executor = Arc::new(SqliteExecutor::open_read_only(&path).unwrap());
+	let mut script = sql_script(HostSql::new(executor));
+
+	let error = call_error(&mut script, "execute", ("DELETE FROM invoices", json!([])));
+	assert!(error.contains("readonly database"), "{error}");
+
+	let rows: JsValue = script
+		.call("select", ("SELECT id FROM invoices", json!([])))
+		.unwrap();
+	assert_eq!(rows, json!([{ "id": 1 }]));
+
+	drop(script);
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn max_rows() {
+	let mut script = sql_script(HostSql::new(executor()).max_rows(2));
+
+	let rows: JsValue = script
+		.call("select", ("SELECT id FROM invoices WHERE id < ?", [3]))
+		.unwrap();
+	assert_eq!(rows, json!([{ "id": 1 }, { "id": 2 }]));
+
+	let error = call_error(
+		&mut script,
+		"select",
+		("SELECT id FROM invoices", json!([])),
+	);
+	assert!(error.contains("query returned more than 2 rows"), "{error}");
+}
+
+#[test]
+fn requires_permission() {
+	let mut script = Script::builder()
+		.sql(HostSql::new(executor()))
+		.build_from_string(CODE)
+		.expect("Initialization succeeds");
+
+	let error = call_error(&mut script, "select", ("SELECT 1", json!([])));
+	assert!(error.contains("PermissionDenied"), "{error}");
+
+	let denials = script.take_denials();
+	assert_eq!(denials.len(), 1);
+	assert_eq!(denials[0].access, Access::HostFunction);
+	assert_eq!(denials[0].resource, "sql");
+}
+
+#[test]
+fn exposed_function() {
+	let mut script = Script::builder()
+		.sql(HostSql::new(executor()))
+		.permissions(Permissions::new().allow_host_functions("sql"))
+		.build_from_string("function total(id) { return sqlSelect('SELECT total FROM invoices WHERE id = ?', [id]); }")
+		.expect("Initialization succeeds");
+	script.add_exposed_func::<SqlSelectExposedFunction>();
+
+	let rows: JsValue = script.call("total", (1,)).unwrap();
+	assert_eq!(rows, json!([{ "total": 120.5 }]));
+}
+
+#[test]
+fn rd_runtime() {
+	let builder = Script::builder().sql(HostSql::new(executor())).permissions(
+		Permissions::new()
+			.allow_host_functions("sql")
+			.allow_host_functions("default_func"),
+	);
+	let mut script =
+		Script::rd_get_run_time_with_builder(builder).expect("Initialization succeeds");
+	script.add_exposed_func::<SqlSelectExposedFunction>();
+	script.add_exposed_func::<DefaultExposedFunction>();
+
+	script
+		.rd_run_string(
+			"globalThis.rows = sqlSelect('SELECT customer FROM invoices WHERE id = ?', [3]);",
+		)
+		.expect("sqlSelect is available");
+	let rows: JsValue = script.get_global("rows").unwrap();
+	assert_eq!(rows, json!([{ "customer": "globex" }]));
+
+	script
+		.rd_load_module("./assets/test/test.js")
+		.expect("Module can be loaded");
+}
+
+#[test]
+fn not_installed_by_default() {
+	let mut script = Script::from_string("function check() { return typeof sqlSelect; }")
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("check", ()).unwrap();
+	assert_eq!(result, "undefined");
+}
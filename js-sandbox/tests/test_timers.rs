@@ -0,0 +1,151 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::time::{Duration, Instant};
+
+use js_sandbox::Script;
+
+const CODE: &str = r#"
+	const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+
+	let ticks = 0;
+	let interval;
+
+	async function delayed(value, ms) {
+		await sleep(ms);
+		return value;
+	}
+
+	// Retries with exponential backoff, as plugins do for flaky services
+	async function retry(failures) {
+		let delay = 1000;
+		for (let attempt = 1; ; attempt++) {
+			if (attempt > failures) return attempt;
+			await sleep(delay);
+			delay *= 2;
+		}
+	}
+
+	function startTicking(ms) {
+		interval = setInterval(() => ticks++, ms);
+	}
+
+	function stopTicking() {
+		clearInterval(interval);
+	}
+
+	function getTicks() {
+		return ticks;
+	}
+
+	async function order() {
+		const log = [];
+		const cancelled = setTimeout(() => log.push("cancelled"), 0);
+		setTimeout(() => log.push("b"), 20);
+		setTimeout(() => log.push("a"), 10);
+		setTimeout((x) => log.push(x), 20, "c");
+		queueMicrotask(() => log.push("microtask"));
+		clearTimeout(cancelled);
+
+		await sleep(30);
+		return log;
+	}
+"#;
+
+#[test]
+fn timeout_in_real_time() {
+	let mut script = Script::from_string(CODE).expect("Initialization succeeds");
+
+	let start = Instant::now();
+	let result: String = script.call("delayed", ("done", 50)).unwrap();
+
+	assert_eq!(result, "done");
+	assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn timer_order() {
+	let mut script = Script::from_string(CODE).expect("Initialization succeeds");
+
+	let log: Vec<String> = script.call("order", ()).unwrap();
+	assert_eq!(log, vec!["microtask", "a", "b", "c"]);
+}
+
+#[test]
+fn virtual_clock_skips_waiting() {
+	let mut script = Script::builder()
+		.virtual_clock()
+		.build_from_string(CODE)
+		.expect("Initialization succeeds");
+
+	let start = Instant::now();
+	let attempts: u32 = script.call("retry", (5,)).unwrap();
+
+	assert_eq!(attempts, 6);
+	assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn virtual_clock_advance_time() {
+	let mut script = Script::builder()
+		.virtual_clock()
+		.build_from_string(CODE)
+		.expect("Initialization succeeds");
+
+	let _: () = script.call("startTicking", (100,)).unwrap();
+	assert_eq!(script.call::<_, u32>("getTicks", ()).unwrap(), 0);
+
+	script.advance_time(Duration::from_millis(350)).unwrap();
+	assert_eq!(script.call::<_, u32>("getTicks", ()).unwrap(), 3);
+
+	script.advance_time(Duration::from_millis(50)).unwrap();
+	assert_eq!(script.call::<_, u32>("getTicks", ()).unwrap(), 4);
+
+	let _: () = script.call("stopTicking", ()).unwrap();
+	script.advance_time(Duration::from_secs(1)).unwrap();
+	assert_eq!(script.call::<_, u32>("getTicks", ()).unwrap(), 4);
+}
+
+#[test]
+fn timer_limits() {
+	let mut script = Script::builder()
+		.max_timers(2)
+		.max_timer_delay(Duration::from_secs(10))
+		.build_from_string(CODE)
+		.expect("Initialization succeeds");
+
+	let result: Result<String, _> = script.call("delayed", ("late", 20_000));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("exceeds the limit"));
+
+	let _: () = script.call("startTicking", (1000,)).unwrap();
+	let _: () = script.call("startTicking", (1000,)).unwrap();
+	let result: Result<(), _> = script.call("startTicking", (1000,));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("Too many pending timers"));
+}
+
+#[test]
+fn timeout_cancels_timers() {
+	let mut script = Script::builder()
+		.timeout(Duration::from_millis(100))
+		.build_from_string(CODE)
+		.expect("Initialization succeeds");
+
+	let _: () = script.call("startTicking", (10,)).unwrap();
+
+	let start = Instant::now();
+	let result: Result<String, _> = script.call("delayed", ("never", 5_000));
+
+	assert!(result.is_err());
+	assert!(start.elapsed() < Duration::from_secs(5));
+
+	// The interval was cancelled along with the call
+	let ticks: u32 = script.call("getTicks", ()).unwrap();
+	let result: String = script.call("delayed", ("later", 50)).unwrap();
+	assert_eq!(result, "later");
+	assert_eq!(script.call::<_, u32>("getTicks", ()).unwrap(), ticks);
+}
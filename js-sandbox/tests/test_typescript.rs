@@ -0,0 +1,93 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "typescript")]
+
+use js_sandbox::{MemoryModuleLoader, Script};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Line {
+	qty: u32,
+	price: f64,
+}
+
+#[test]
+fn typescript_file() {
+	let mut script = Script::from_file("assets/test/invoice.ts").expect("File can be loaded");
+
+	let lines = vec![Line { qty: 2, price: 1.5 }, Line { qty: 1, price: 4.0 }];
+	let result: f64 = script.call("total", (lines,)).unwrap();
+	assert_eq!(result, 7.0);
+}
+
+#[test]
+fn typescript_file_error_location() {
+	let mut script = Script::from_file("assets/test/invoice.ts").expect("File can be loaded");
+
+	let err = script
+		.call::<_, f64>("checked", (Vec::<u32>::new(),))
+		.unwrap_err()
+		.to_string();
+
+	// line of the `throw` in the TypeScript source, not in the transpiled code
+	assert!(err.contains("invoice has no lines"), "{err}");
+	assert!(err.contains("invoice.ts:12:"), "{err}");
+}
+
+#[test]
+fn typescript_modules() {
+	let loader: MemoryModuleLoader = [
+		(
+			"main.ts",
+			r#"
+			import { money, type Currency } from "./money.ts";
+			const currency: Currency = "EUR";
+			export function price(amount: number): string { return money(amount, currency); }
+			"#,
+		),
+		(
+			"money.ts",
+			r#"
+			export type Currency = "EUR" | "USD";
+			export function money(amount: number, currency: Currency): string {
+				return amount.toFixed(2) + " " + currency;
+			}
+			"#,
+		),
+	]
+	.into_iter()
+	.collect();
+
+	let mut script = Script::builder()
+		.module_loader(loader)
+		.build_from_module("main.ts")
+		.expect("Module can be loaded");
+
+	let result: String = script.call("price", (3,)).unwrap();
+	assert_eq!(result, "3.00 EUR");
+}
+
+#[test]
+fn typescript_module_error_location() {
+	let mut loader = MemoryModuleLoader::default();
+	loader.insert(
+		"main.ts",
+		"type Id = string;\n\
+		 \n\
+		 export function find(id: Id): never {\n\
+		 \tthrow new Error(`no record ${id}`);\n\
+		 }\n",
+	);
+
+	let mut script = Script::builder()
+		.module_loader(loader)
+		.build_from_module("main.ts")
+		.expect("Module can be loaded");
+
+	let err = script
+		.call::<_, ()>("find", ("a1",))
+		.unwrap_err()
+		.to_string();
+	assert!(err.contains("no record a1"), "{err}");
+	assert!(err.contains("main.ts:4:"), "{err}");
+}
@@ -0,0 +1,239 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use js_sandbox::{HostFs, JsValue, Permissions, Script, VirtualFs};
+use serde_json::json;
+
+const CODE: &str = r#"
+	function summarize(input, output) {
+		const rows = host.fs.readText(input).trim().split("\n").slice(1);
+		const total = rows.map((row) => Number(row.split(",")[1])).reduce((a, b) => a + b, 0);
+		host.fs.writeText(output, `${rows.length} items, ${total} units`);
+	}
+
+	function writeBytes(path, bytes) {
+		host.fs.writeBytes(path, new Uint8Array(bytes));
+	}
+
+	function remove(path) {
+		return host.fs.remove(path);
+	}
+
+	function list(path) {
+		return host.fs.list(path).map((entry) => [entry.name, entry.isFile]);
+	}
+
+	function stat(path) {
+		const { isFile, isDirectory, size } = host.fs.stat(path);
+		return { isFile, isDirectory, size };
+	}
+
+	function readText(path) {
+		return host.fs.readText(path);
+	}
+"#;
+
+fn vfs_script(vfs: &VirtualFs) -> Script {
+	Script::builder()
+		.virtual_fs(vfs.clone())
+		.build_from_string(CODE)
+		.expect("Initialization succeeds")
+}
+
+#[test]
+fn read_and_write() {
+	let vfs = VirtualFs::new();
+	vfs.insert("/in/data.csv", "item,qty\napple,3\npear,4\n");
+	let mut script = vfs_script(&vfs);
+
+	let _: () = script
+		.call("summarize", ("/in/data.csv", "/out/report.txt"))
+		.unwrap();
+	assert_eq!(
+		vfs.get("/out/report.txt"),
+		Some(b"2 items, 7 units".to_vec())
+	);
+
+	let _: () = script
+		.call("writeBytes", ("/out/./raw.bin", [1, 2, 255]))
+		.unwrap();
+	assert_eq!(vfs.get("/out/raw.bin"), Some(vec![1, 2, 255]));
+
+	// The host's changes are visible to the script right away
+	vfs.insert("/in/data.csv", "item,qty\nplum,5\n");
+	let _: () = script
+		.call("summarize", ("/in/data.csv", "/out/report.txt"))
+		.unwrap();
+	assert_eq!(
+		vfs.get("/out/report.txt"),
+		Some(b"1 items, 5 units".to_vec())
+	);
+
+	let removed: bool = script.call("remove", ("/out/raw.bin",)).unwrap();
+	assert!(removed);
+	assert_eq!(vfs.paths(), vec!["/in/data.csv", "/out/report.txt"]);
+}
+
+#[test]
+fn list_and_stat() {
+	let vfs = VirtualFs::new();
+	vfs.insert("/in/data.csv", "a,b\n");
+	vfs.insert("/in/nested/deep.txt", "");
+	vfs.insert("/readme.md", "# Hi");
+	let mut script = vfs_script(&vfs);
+
+	let entries: JsValue = script.call("list", ("/",)).unwrap();
+	assert_eq!(entries, json!([["in", false], ["readme.md", true]]));
+
+	let entries: JsValue = script.call("list", ("/in",)).unwrap();
+	assert_eq!(entries, json!([["data.csv", true], ["nested", false]]));
+
+	let stat: JsValue = script.call("stat", ("/in/data.csv",)).unwrap();
+	assert_eq!(
+		stat,
+		json!({ "isFile": true, "isDirectory": false, "size": 4 })
+	);
+
+	let stat: JsValue = script.call("stat", ("/in/nested",)).unwrap();
+	assert_eq!(stat["isDirectory"], json!(true));
+
+	let result: Result<JsValue, _> = script.call("stat", ("/missing",));
+	assert!(result.is_err());
+}
+
+#[test]
+fn size_limit() {
+	let vfs = VirtualFs::new();
+	vfs.set_max_size(60);
+	vfs.insert("/in/data.csv", "item,qty\napple,3\n");
+	let mut script = vfs_script(&vfs);
+
+	let result: Result<(), _> = script.call("writeBytes", ("/out/a.bin", [0; 8]));
+	assert!(result.is_ok());
+
+	let result: Result<(), _> = script.call("writeBytes", ("/out/b.bin", [0; 8]));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("would exceed its limit of 60 bytes"));
+	assert_eq!(vfs.get("/out/b.bin"), None);
+
+	// Replacing a file counts only its new contents; removing one frees its space
+	let result: Result<(), _> = script.call("writeBytes", ("/out/a.bin", [0; 8]));
+	assert!(result.is_ok());
+	vfs.remove("/out/a.bin");
+	let result: Result<(), _> = script.call("writeBytes", ("/out/b.bin", [0; 8]));
+	assert!(result.is_ok());
+}
+
+#[test]
+fn overlay_mounts() {
+	let vfs = VirtualFs::new();
+	vfs.insert("/templates/invoice.txt", "Virtual invoice");
+	vfs.insert("/templates/receipt.txt", "Receipt");
+
+	let mut script = Script::builder()
+		.host_fs(
+			HostFs::new()
+				.mount("/templates", "assets/test/fs/templates")
+				.unwrap(),
+		)
+		.virtual_fs(vfs.clone())
+		.permissions(Permissions::new().allow_read("assets/test/fs"))
+		.build_from_string(CODE)
+		.expect("Initialization succeeds");
+
+	let text: String = script
+		.call("readText", ("/templates/invoice.txt",))
+		.unwrap();
+	assert_eq!(text, "Virtual invoice");
+
+	let text: String = script.call("readText", ("/templates/letter.txt",)).unwrap();
+	assert!(!text.is_empty());
+
+	let entries: JsValue = script.call("list", ("/templates",)).unwrap();
+	assert_eq!(
+		entries,
+		json!([
+			["invoice.txt", true],
+			["letter.txt", true],
+			["receipt.txt", true]
+		])
+	);
+}
+
+#[test]
+fn overlay_list_errors() {
+	let vfs = VirtualFs::new();
+	vfs.insert("/templates/invoice.txt", "Virtual invoice");
+	vfs.insert("/templates/drafts/quote.txt", "Quote");
+
+	let mut script = Script::builder()
+		.host_fs(
+			HostFs::new()
+				.mount("/templates", "assets/test/fs/templates")
+				.unwrap(),
+		)
+		.virtual_fs(vfs)
+		.build_from_string(CODE)
+		.expect("Initialization succeeds");
+
+	// A directory missing on the host is fine, one that may not be read is not
+	let entries: JsValue = script.call("list", ("/templates/drafts",)).unwrap();
+	assert_eq!(entries, json!([["quote.txt", true]]));
+
+	let result: Result<JsValue, _> = script.call("list", ("/templates",));
+	let error = result.unwrap_err().to_string();
+	assert!(error.contains("PermissionDenied"), "{error}");
+}
+
+#[test]
+fn mounts_are_read_only() {
+	let mut script = Script::builder()
+		.host_fs(
+			HostFs::new()
+				.mount("/templates", "assets/test/fs/templates")
+				.unwrap(),
+		)
+		.permissions(Permissions::new().allow_read("assets/test/fs"))
+		.build_from_string(CODE)
+		.expect("Initialization succeeds");
+
+	let result: Result<(), _> = script.call("writeBytes", ("/templates/new.txt", [1]));
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("only a virtual file system is writable"));
+}
+
+#[test]
+fn import_modules() {
+	let vfs = VirtualFs::new();
+	vfs.insert(
+		"/main.js",
+		"import { format } from './lib/format.js'; export function greet(name) { return format(name); }",
+	);
+	vfs.insert(
+		"/lib/format.js",
+		"export function format(name) { return `Hello ${name}`; }",
+	);
+
+	let mut script = Script::builder()
+		.virtual_fs(vfs)
+		.build_from_module("vfs:///main.js")
+		.expect("Module can be loaded");
+
+	let result: String = script.call("greet", ("Roger",)).unwrap();
+	assert_eq!(result, "Hello Roger");
+}
+
+#[test]
+fn missing_module() {
+	let result = Script::builder()
+		.virtual_fs(VirtualFs::new())
+		.build_from_module("vfs:///main.js");
+
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("no file in the virtual file system"));
+}
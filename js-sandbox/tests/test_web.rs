@@ -0,0 +1,155 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "web")]
+
+use std::time::UNIX_EPOCH;
+
+use js_sandbox::{JsValue, Script};
+use serde_json::json;
+
+fn web_script(js_code: &str) -> Script {
+	Script::builder()
+		.web_apis()
+		.build_from_string(js_code)
+		.expect("Initialization succeeds")
+}
+
+#[test]
+fn text_encoding() {
+	let mut script = web_script(
+		r#"
+		function roundTrip(text) {
+			const bytes = new TextEncoder().encode(text);
+			return { length: bytes.length, text: new TextDecoder().decode(bytes) };
+		}
+
+		function decodeInvalid(fatal) {
+			return new TextDecoder("utf-8", { fatal }).decode(new Uint8Array([0x61, 0xff]));
+		}
+		"#,
+	);
+
+	let result: JsValue = script.call("roundTrip", ("grüße €",)).unwrap();
+	assert_eq!(result, json!({ "length": 11, "text": "grüße €" }));
+
+	let lossy: String = script.call("decodeInvalid", (false,)).unwrap();
+	assert_eq!(lossy, "a\u{fffd}");
+
+	let strict: Result<String, _> = script.call("decodeInvalid", (true,));
+	assert!(strict.unwrap_err().to_string().contains("TypeError"));
+}
+
+#[test]
+fn url() {
+	let mut script = web_script(
+		r#"
+		function parse(href, base) {
+			const url = new URL(href, base);
+			url.searchParams.append("page", "2");
+			url.hash = "top";
+
+			return {
+				href: url.href,
+				host: url.host,
+				pathname: url.pathname,
+				query: url.searchParams.get("q"),
+				valid: URL.canParse("not a url"),
+			};
+		}
+
+		function params() {
+			const params = new URLSearchParams({ name: "Jane Doe", tag: "a&b" });
+			params.append("tag", "c");
+			return [params.toString(), params.getAll("tag")];
+		}
+		"#,
+	);
+
+	let result: JsValue = script
+		.call(
+			"parse",
+			("../items?q=shoes", "https://shop.example:8080/api/v1/"),
+		)
+		.unwrap();
+	assert_eq!(
+		result,
+		json!({
+			"href": "https://shop.example:8080/api/items?q=shoes&page=2#top",
+			"host": "shop.example:8080",
+			"pathname": "/api/items",
+			"query": "shoes",
+			"valid": false,
+		})
+	);
+
+	let result: JsValue = script.call("params", ()).unwrap();
+	assert_eq!(
+		result,
+		json!(["name=Jane+Doe&tag=a%26b&tag=c", ["a&b", "c"]])
+	);
+}
+
+#[test]
+fn base64_clone_uuid() {
+	let mut script = web_script(
+		r#"
+		function base64(text) {
+			const encoded = btoa(text);
+			return [encoded, atob(encoded)];
+		}
+
+		function clone() {
+			const original = { date: new Date(0), nested: { list: [1, 2] }, map: new Map([["k", 1]]) };
+			const copy = structuredClone(original);
+			copy.nested.list.push(3);
+
+			return [original.nested.list.length, copy.date instanceof Date, copy.map.get("k")];
+		}
+
+		function cloneFunction() {
+			return structuredClone({ f() {} });
+		}
+
+		function uuid() {
+			return crypto.randomUUID();
+		}
+		"#,
+	);
+
+	let result: Vec<String> = script.call("base64", ("hello, world",)).unwrap();
+	assert_eq!(result, vec!["aGVsbG8sIHdvcmxk", "hello, world"]);
+
+	let result: JsValue = script.call("clone", ()).unwrap();
+	assert_eq!(result, json!([2, true, 1]));
+
+	let result: Result<JsValue, _> = script.call("cloneFunction", ());
+	assert!(result.unwrap_err().to_string().contains("DataCloneError"));
+
+	let uuid: String = script.call("uuid", ()).unwrap();
+	assert_eq!(uuid.len(), 36);
+	assert_eq!(&uuid[14..15], "4");
+}
+
+#[test]
+fn deterministic_uuid() {
+	let uuid = || -> String {
+		Script::builder()
+			.web_apis()
+			.deterministic(3, UNIX_EPOCH)
+			.build_from_string("function uuid() { return crypto.randomUUID(); }")
+			.expect("Initialization succeeds")
+			.call("uuid", ())
+			.unwrap()
+	};
+
+	assert_eq!(uuid(), uuid());
+}
+
+#[test]
+fn not_installed_by_default() {
+	let mut script = Script::from_string("function check() { return typeof TextEncoder; }")
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("check", ()).unwrap();
+	assert_eq!(result, "undefined");
+}